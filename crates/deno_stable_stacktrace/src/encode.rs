@@ -1,3 +1,71 @@
+//! A compact binary encoding for stack traces, usually transported as
+//! base64url (without padding).
+//!
+//! Integers are LEB128 varints, and `os`/`arch` are a single byte for known
+//! values, or otherwise a length-prefixed string. Every trace starts with the
+//! trace version, which determines the rest of the layout:
+//!
+//! - Version 0: `os`, `arch`, `version`, then one stable address per frame
+//!   until the end of the input. Frames without one are written as 0.
+//! - Version 1: the extensible layout, described below.
+//!
+//! ## Version 1
+//!
+//! ```text
+//! trace_version: varint (1)
+//! os, arch, version
+//! frame_count: varint
+//! frames: frame_count * (tag: u8, payload)
+//! sections: until the end of the input, each
+//!   tag: varint
+//!   len: varint
+//!   payload: len bytes
+//! ```
+//!
+//! Frame tags are `0` (unknown), `1` (stable address), `2` (absolute address)
+//! and `3` (module index and address relative to that module).
+//!
+//! Return addresses point to the instruction after a call, so they have to be
+//! looked up at `addr - 1` to find the call. In version 0, addresses were
+//! adjusted that way when captured, including the faulting instruction of a
+//! crash, which shouldn't be. From version 1 on, addresses are left as is, and
+//! bit `0x80` of the frame tag marks return addresses (see
+//! [`FrameAddr::lookup_addr`]).
+//!
+//! Sections carry everything that isn't needed to symbolicate the main
+//! executable's frames. Each may appear at most once, and their order doesn't
+//! matter. Decoders skip sections with tags they don't know, and ignore
+//! trailing bytes in a known section's payload, so new sections (or fields at
+//! the end of existing ones) can be added without bumping the version.
+//!
+//! | Tag | Section      | Payload                                          |
+//! | --- | ------------ | ------------------------------------------------ |
+//! | 1   | Build ID     | the main executable's build ID                   |
+//! | 2   | Module table | count, then per module: path, base, build ID     |
+//! | 3   | Thread       | thread ID, then name (empty if unnamed)          |
+//! | 4   | Signal       | signal number, code (zigzag), fault address + 1  |
+//! | 5   | Threads      | count, then per thread: ID, name, frame count,   |
+//! |     |              | frames                                           |
+//! | 6   | Stack memory | instruction pointer, register count, then per    |
+//! |     |              | register: DWARF number, value; then the memory's |
+//! |     |              | start address, length and bytes                  |
+//!
+//! The threads section holds the stacks of threads other than the one in the
+//! trace's frames (which is described by the thread section), e.g. for a dump
+//! of every thread in a hung process. Their frames are encoded like the
+//! trace's own, and share its module table.
+//!
+//! The stack memory section is only written when a client opts into
+//! capturing it (see [`StackMemory`]). It lets the symbolicator unwind the
+//! stack again with the binary's unwind info, rather than relying on the
+//! frames the client's unwinder found.
+//!
+//! Strings in sections are a varint byte length followed by UTF-8 bytes.
+
+use crate::BuildId;
+use crate::Module;
+use crate::{Register, StackMemory};
+
 fn varint_encoded_size(mut v: u64) -> usize {
   if v == 0 {
     return 1;
@@ -23,42 +91,113 @@ fn varint_encode_into(buf: &mut [u8], value: u64) -> usize {
   i + 1
 }
 
+fn varint_decode(buf: &[u8]) -> Result<(u64, usize), DecodeError> {
+  let mut value = 0u64;
+  let mut shift = 0;
+  for (i, &byte) in buf.iter().enumerate() {
+    if shift == 63 && byte > 1 {
+      return Err(DecodeError::InvalidVarint { offset: 0 });
+    }
+    value |= ((byte & 0x7F) as u64) << shift;
+    if byte & 0x80 == 0 {
+      return Ok((value, i + 1));
+    }
+    shift += 7;
+  }
+  Err(DecodeError::UnexpectedEof { offset: buf.len() })
+}
+
 const BASE64URL_CHARS: &[u8] =
   b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
 
 fn base64url_encode(input: &[u8]) -> String {
-  let mut result = String::with_capacity((input.len() + 2) / 3 * 4);
-  let mut i = 0;
-
-  while i + 3 <= input.len() {
-    let chunk = ((input[i] as u32) << 16)
-      | ((input[i + 1] as u32) << 8)
-      | (input[i + 2] as u32);
+  let mut result = vec![0; base64url_encoded_len(input.len())];
+  base64url_encode_into(input, &mut result);
+  // Only contains characters from `BASE64URL_CHARS`.
+  String::from_utf8(result).unwrap()
+}
 
-    result.push(BASE64URL_CHARS[((chunk >> 18) & 0x3F) as usize] as char);
-    result.push(BASE64URL_CHARS[((chunk >> 12) & 0x3F) as usize] as char);
-    result.push(BASE64URL_CHARS[((chunk >> 6) & 0x3F) as usize] as char);
-    result.push(BASE64URL_CHARS[(chunk & 0x3F) as usize] as char);
+/// The length of `len` bytes encoded as base64url, without padding.
+pub fn base64url_encoded_len(len: usize) -> usize {
+  (len * 4).div_ceil(3)
+}
 
-    i += 3;
+/// Encodes `input` as base64url (without padding) into `out`, without
+/// allocating. Returns the number of bytes written, or `None` if `out` is
+/// shorter than [`base64url_encoded_len`].
+pub fn base64url_encode_into(input: &[u8], out: &mut [u8]) -> Option<usize> {
+  let len = base64url_encoded_len(input.len());
+  let out = out.get_mut(..len)?;
+  for (chunk, out) in input.chunks(3).zip(out.chunks_mut(4)) {
+    let mut bits = 0u32;
+    for (j, &byte) in chunk.iter().enumerate() {
+      bits |= (byte as u32) << (16 - j * 8);
+    }
+    // Without padding, n bytes turn into n + 1 characters.
+    for (j, c) in out.iter_mut().enumerate() {
+      *c = BASE64URL_CHARS[((bits >> (18 - j * 6)) & 0x3F) as usize];
+    }
   }
+  Some(len)
+}
 
-  // Handle remaining bytes without padding
-  let remaining = input.len() - i;
-  if remaining == 1 {
-    let chunk = (input[i] as u32) << 16;
-    result.push(BASE64URL_CHARS[((chunk >> 18) & 0x3F) as usize] as char);
-    result.push(BASE64URL_CHARS[((chunk >> 12) & 0x3F) as usize] as char);
-  } else if remaining == 2 {
-    let chunk = ((input[i] as u32) << 16) | ((input[i + 1] as u32) << 8);
-    result.push(BASE64URL_CHARS[((chunk >> 18) & 0x3F) as usize] as char);
-    result.push(BASE64URL_CHARS[((chunk >> 12) & 0x3F) as usize] as char);
-    result.push(BASE64URL_CHARS[((chunk >> 6) & 0x3F) as usize] as char);
-  }
+/// The line printed before an encoded trace in crash output, so traces can be
+/// found in logs with `grep`.
+pub const TRACE_BANNER_BEGIN: &str = "-----BEGIN DENO STACK TRACE-----";
+/// The line printed after an encoded trace in crash output.
+pub const TRACE_BANNER_END: &str = "-----END DENO STACK TRACE-----";
+
+/// Returns the encoded trace between [`TRACE_BANNER_BEGIN`] and
+/// [`TRACE_BANNER_END`] in `text` (e.g. a crash log), or `None` if there's no
+/// complete banner.
+pub fn find_banner_trace(text: &str) -> Option<&str> {
+  let (_, rest) = text.split_once(TRACE_BANNER_BEGIN)?;
+  let (trace, _) = rest.split_once(TRACE_BANNER_END)?;
+  Some(trace.trim())
+}
+
+fn base64url_value(c: u8) -> Option<u32> {
+  let value = match c {
+    b'A'..=b'Z' => c - b'A',
+    b'a'..=b'z' => c - b'a' + 26,
+    b'0'..=b'9' => c - b'0' + 52,
+    b'-' => 62,
+    b'_' => 63,
+    _ => return None,
+  };
+  Some(value as u32)
+}
 
-  result
+fn base64url_decode(input: &str) -> Result<Vec<u8>, DecodeError> {
+  // Accept padded input as well, since it's easy to end up with when copying
+  // traces around.
+  let input = input.trim().trim_end_matches('=').as_bytes();
+  if input.len() % 4 == 1 {
+    return Err(DecodeError::InvalidBase64 {
+      offset: input.len() - 1,
+    });
+  }
+  let mut result = Vec::with_capacity(input.len() / 4 * 3 + 2);
+  for (chunk_idx, chunk) in input.chunks(4).enumerate() {
+    let mut value = 0u32;
+    for (j, &c) in chunk.iter().enumerate() {
+      let bits = base64url_value(c).ok_or(DecodeError::InvalidBase64 {
+        offset: chunk_idx * 4 + j,
+      })?;
+      value |= bits << (18 - 6 * j);
+    }
+    result.push((value >> 16) as u8);
+    if chunk.len() > 2 {
+      result.push((value >> 8) as u8);
+    }
+    if chunk.len() > 3 {
+      result.push(value as u8);
+    }
+  }
+  Ok(result)
 }
-#[derive(Debug, Clone)]
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(from = "String", into = "String"))]
 pub enum Os {
@@ -68,7 +207,7 @@ pub enum Os {
   Other(OtherString<3>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Version {
@@ -83,12 +222,26 @@ pub struct Version {
   pub dev_build: bool,
 }
 
+/// Formats as `major.minor.patch[-canary_hash][+dev]`.
+impl std::fmt::Display for Version {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+    if let Some(canary_hash) = self.canary_hash.as_str() {
+      write!(f, "-{canary_hash}")?;
+    }
+    if self.dev_build {
+      f.write_str("+dev")?;
+    }
+    Ok(())
+  }
+}
+
 #[cfg(feature = "serde")]
 fn canary_hash_is_none(canary_hash: &CanaryHash) -> bool {
   canary_hash.0.is_none()
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(
   feature = "serde",
@@ -103,6 +256,9 @@ impl CanaryHash {
   pub fn none() -> Self {
     Self(None)
   }
+  pub fn as_str(&self) -> Option<&str> {
+    self.0.as_ref().map(|s| s.as_str())
+  }
 }
 
 impl<S: AsRef<str>> From<Option<S>> for CanaryHash {
@@ -136,7 +292,19 @@ impl Encode for CanaryHash {
   }
 }
 
-#[derive(Debug, Clone)]
+impl Decode for CanaryHash {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    match peek_byte(buf)? {
+      0 => Ok((CanaryHash::none(), 1)),
+      _ => {
+        let (value, n) = OtherString::decode_from(buf)?;
+        Ok((CanaryHash(Some(value)), n))
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Header {
@@ -144,9 +312,38 @@ pub struct Header {
   os: Os,
   version: Version,
   arch: Arch,
+  /// Identifies the exact binary the trace was captured from. Only encoded
+  /// from trace version 1 onwards.
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  build_id: Option<BuildId>,
+}
+
+impl Header {
+  pub fn trace_version(&self) -> u8 {
+    self.trace_version
+  }
+
+  pub fn os(&self) -> &Os {
+    &self.os
+  }
+
+  pub fn arch(&self) -> &Arch {
+    &self.arch
+  }
+
+  pub fn version(&self) -> &Version {
+    &self.version
+  }
+
+  pub fn build_id(&self) -> Option<&BuildId> {
+    self.build_id.as_ref()
+  }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(from = "String", into = "String"))]
 pub enum Arch {
@@ -178,6 +375,19 @@ impl Encode for Arch {
   }
 }
 
+impl Decode for Arch {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    match peek_byte(buf)? {
+      0 => Ok((Arch::X86_64, 1)),
+      1 => Ok((Arch::Aarch64, 1)),
+      _ => {
+        let (value, n) = OtherString::decode_from(buf)?;
+        Ok((Arch::Other(value), n))
+      }
+    }
+  }
+}
+
 impl<S: AsRef<str>> From<S> for Arch {
   fn from(value: S) -> Self {
     match value.as_ref() {
@@ -220,7 +430,40 @@ impl From<Os> for String {
   }
 }
 
-#[derive(Debug, Clone)]
+impl Os {
+  pub fn as_str(&self) -> &str {
+    match self {
+      Os::Linux => "linux",
+      Os::Mac => "macos",
+      Os::Windows => "windows",
+      Os::Other(other_string) => other_string.as_str(),
+    }
+  }
+}
+
+impl Arch {
+  pub fn as_str(&self) -> &str {
+    match self {
+      Arch::X86_64 => "x86_64",
+      Arch::Aarch64 => "aarch64",
+      Arch::Other(other_string) => other_string.as_str(),
+    }
+  }
+}
+
+impl std::fmt::Display for Os {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl std::fmt::Display for Arch {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct OtherString<const MIN: usize> {
   value: String,
 }
@@ -235,6 +478,10 @@ impl<const MIN: usize> OtherString<MIN> {
   fn new(value: String) -> Self {
     Self { value }
   }
+
+  pub fn as_str(&self) -> &str {
+    &self.value
+  }
 }
 
 impl<const MIN: usize> Encode for OtherString<MIN> {
@@ -259,6 +506,19 @@ impl<const MIN: usize> Encode for OtherString<MIN> {
   }
 }
 
+impl<const MIN: usize> Decode for OtherString<MIN> {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let len = peek_byte(buf)? as usize;
+    let bytes = buf
+      .get(1..1 + len)
+      .ok_or(DecodeError::UnexpectedEof { offset: buf.len() })?;
+    // `encode_into` truncates without regard for char boundaries, so don't
+    // fail on a partial trailing character.
+    let value = String::from_utf8_lossy(bytes).into_owned();
+    Ok((Self { value }, 1 + len))
+  }
+}
+
 pub trait Encode {
   fn encoded_size(&self) -> usize;
   fn encode_into(&self, buf: &mut [u8]) -> usize;
@@ -268,6 +528,90 @@ pub trait Encode {
   }
 }
 
+/// An error encountered while decoding an encoded stack trace.
+///
+/// Offsets are byte offsets into the decoded (not base64url) input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+  /// The input ended before the value was complete.
+  UnexpectedEof { offset: usize },
+  /// A varint was longer than 10 bytes or didn't fit in the target type.
+  InvalidVarint { offset: usize },
+  /// A tag or enum byte had a value that isn't known to this decoder.
+  UnknownDiscriminant {
+    offset: usize,
+    kind: &'static str,
+    value: u64,
+  },
+  /// The base64url input contained an invalid character, or had an invalid
+  /// length. The offset is a character index into the string.
+  InvalidBase64 { offset: usize },
+}
+
+impl DecodeError {
+  pub fn offset(&self) -> usize {
+    match self {
+      DecodeError::UnexpectedEof { offset }
+      | DecodeError::InvalidVarint { offset }
+      | DecodeError::UnknownDiscriminant { offset, .. }
+      | DecodeError::InvalidBase64 { offset } => *offset,
+    }
+  }
+
+  fn offset_by(mut self, by: usize) -> Self {
+    match &mut self {
+      DecodeError::UnexpectedEof { offset }
+      | DecodeError::InvalidVarint { offset }
+      | DecodeError::UnknownDiscriminant { offset, .. } => *offset += by,
+      DecodeError::InvalidBase64 { .. } => {}
+    }
+    self
+  }
+}
+
+impl std::fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      DecodeError::UnexpectedEof { offset } => {
+        write!(f, "unexpected end of input at byte {offset}")
+      }
+      DecodeError::InvalidVarint { offset } => {
+        write!(f, "invalid varint at byte {offset}")
+      }
+      DecodeError::UnknownDiscriminant {
+        offset,
+        kind,
+        value,
+      } => write!(f, "unknown {kind} {value} at byte {offset}"),
+      DecodeError::InvalidBase64 { offset } => {
+        write!(f, "invalid base64url input at character {offset}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub trait Decode: Sized {
+  /// Decodes a value from the start of `buf`, returning it along with the
+  /// number of bytes consumed.
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError>;
+}
+
+fn peek_byte(buf: &[u8]) -> Result<u8, DecodeError> {
+  buf
+    .first()
+    .copied()
+    .ok_or(DecodeError::UnexpectedEof { offset: 0 })
+}
+
+/// Decodes a `T` at `buf[*i..]`, advancing `i` past it.
+fn decode_at<T: Decode>(buf: &[u8], i: &mut usize) -> Result<T, DecodeError> {
+  let (value, n) = T::decode_from(&buf[*i..]).map_err(|e| e.offset_by(*i))?;
+  *i += n;
+  Ok(value)
+}
+
 impl Encode for Os {
   fn encoded_size(&self) -> usize {
     match self {
@@ -295,6 +639,20 @@ impl Encode for Os {
   }
 }
 
+impl Decode for Os {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    match peek_byte(buf)? {
+      0 => Ok((Os::Linux, 1)),
+      1 => Ok((Os::Mac, 1)),
+      2 => Ok((Os::Windows, 1)),
+      _ => {
+        let (value, n) = OtherString::decode_from(buf)?;
+        Ok((Os::Other(value), n))
+      }
+    }
+  }
+}
+
 impl Encode for u64 {
   fn encoded_size(&self) -> usize {
     varint_encoded_size(*self)
@@ -305,6 +663,41 @@ impl Encode for u64 {
   }
 }
 
+impl Decode for u64 {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    varint_decode(buf)
+  }
+}
+
+/// Decodes a varint into a narrower type, rejecting values that don't fit
+/// rather than truncating them.
+fn decode_narrow<T: TryFrom<u64>>(
+  buf: &[u8],
+) -> Result<(T, usize), DecodeError> {
+  let (value, n) = varint_decode(buf)?;
+  let value =
+    T::try_from(value).map_err(|_| DecodeError::InvalidVarint { offset: 0 })?;
+  Ok((value, n))
+}
+
+impl Decode for u16 {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    decode_narrow(buf)
+  }
+}
+
+impl Decode for u32 {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    decode_narrow(buf)
+  }
+}
+
+impl Decode for usize {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    decode_narrow(buf)
+  }
+}
+
 impl Encode for bool {
   fn encoded_size(&self) -> usize {
     1
@@ -315,6 +708,20 @@ impl Encode for bool {
   }
 }
 
+impl Decode for bool {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    match peek_byte(buf)? {
+      0 => Ok((false, 1)),
+      1 => Ok((true, 1)),
+      value => Err(DecodeError::UnknownDiscriminant {
+        offset: 0,
+        kind: "bool",
+        value: value as u64,
+      }),
+    }
+  }
+}
+
 impl Encode for Version {
   fn encoded_size(&self) -> usize {
     self.major.encoded_size()
@@ -334,6 +741,107 @@ impl Encode for Version {
   }
 }
 
+impl Decode for Version {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let version = Version {
+      major: decode_at(buf, &mut i)?,
+      minor: decode_at(buf, &mut i)?,
+      patch: decode_at(buf, &mut i)?,
+      canary_hash: decode_at(buf, &mut i)?,
+      dev_build: decode_at(buf, &mut i)?,
+    };
+    Ok((version, i))
+  }
+}
+
+impl Encode for Option<BuildId> {
+  fn encoded_size(&self) -> usize {
+    match self {
+      None => 1,
+      Some(BuildId::Gnu(bytes)) => 2 + bytes.len().min(u8::MAX as usize),
+      Some(BuildId::MachO(uuid)) => 1 + uuid.len(),
+      Some(BuildId::Pdb { guid, age }) => {
+        1 + guid.len() + (*age as u64).encoded_size()
+      }
+    }
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    match self {
+      None => {
+        buf[0] = 0;
+        1
+      }
+      Some(BuildId::Gnu(bytes)) => {
+        let len = bytes.len().min(u8::MAX as usize);
+        buf[0] = 1;
+        buf[1] = len as u8;
+        buf[2..2 + len].copy_from_slice(&bytes[..len]);
+        2 + len
+      }
+      Some(BuildId::MachO(uuid)) => {
+        buf[0] = 2;
+        buf[1..17].copy_from_slice(uuid);
+        17
+      }
+      Some(BuildId::Pdb { guid, age }) => {
+        buf[0] = 3;
+        buf[1..17].copy_from_slice(guid);
+        17 + (*age as u64).encode_into(&mut buf[17..])
+      }
+    }
+  }
+}
+
+fn decode_bytes<const N: usize>(
+  buf: &[u8],
+  i: &mut usize,
+) -> Result<[u8; N], DecodeError> {
+  let bytes = buf
+    .get(*i..*i + N)
+    .ok_or(DecodeError::UnexpectedEof { offset: buf.len() })?;
+  *i += N;
+  Ok(bytes.try_into().unwrap())
+}
+
+impl Decode for Option<BuildId> {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 1;
+    let build_id = match peek_byte(buf)? {
+      0 => None,
+      1 => {
+        let len = decode_bytes::<1>(buf, &mut i)?[0] as usize;
+        let bytes = buf
+          .get(i..i + len)
+          .ok_or(DecodeError::UnexpectedEof { offset: buf.len() })?;
+        i += len;
+        Some(BuildId::Gnu(bytes.to_vec()))
+      }
+      2 => Some(BuildId::MachO(decode_bytes(buf, &mut i)?)),
+      3 => {
+        let guid = decode_bytes(buf, &mut i)?;
+        let age: u64 = decode_at(buf, &mut i)?;
+        let age = u32::try_from(age)
+          .map_err(|_| DecodeError::InvalidVarint { offset: 17 })?;
+        Some(BuildId::Pdb { guid, age })
+      }
+      value => {
+        return Err(DecodeError::UnknownDiscriminant {
+          offset: 0,
+          kind: "build id kind",
+          value: value as u64,
+        });
+      }
+    };
+    Ok((build_id, i))
+  }
+}
+
+/// The newest trace version this crate knows how to encode and decode. See
+/// the [module docs](self) for the layout of each version.
+pub const LATEST_TRACE_VERSION: u8 = 1;
+
 impl Encode for Header {
   fn encoded_size(&self) -> usize {
     (self.trace_version.encoded_size())
@@ -352,6 +860,28 @@ impl Encode for Header {
   }
 }
 
+impl Decode for Header {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let trace_version: u8 = decode_at(buf, &mut i)?;
+    if trace_version > LATEST_TRACE_VERSION {
+      return Err(DecodeError::UnknownDiscriminant {
+        offset: 0,
+        kind: "trace version",
+        value: trace_version as u64,
+      });
+    }
+    let header = Header {
+      trace_version,
+      os: decode_at(buf, &mut i)?,
+      arch: decode_at(buf, &mut i)?,
+      version: decode_at(buf, &mut i)?,
+      build_id: None,
+    };
+    Ok((header, i))
+  }
+}
+
 impl Encode for u8 {
   fn encoded_size(&self) -> usize {
     (*self as u64).encoded_size()
@@ -362,104 +892,1033 @@ impl Encode for u8 {
   }
 }
 
-#[derive(Debug, Clone)]
+impl Decode for u8 {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let (value, n) = varint_decode(buf)?;
+    let value = u8::try_from(value)
+      .map_err(|_| DecodeError::InvalidVarint { offset: 0 })?;
+    Ok((value, n))
+  }
+}
+
+/// A single frame of a [`StackTrace`].
+///
+/// `call_site` is set for return addresses (every frame but the first, or
+/// the first too if it isn't the exact instruction that was executing), which
+/// are looked up at `addr - 1`. It's never set for frames decoded from version
+/// 0 traces, whose addresses were adjusted when captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
-pub struct StackTrace {
-  header: Header,
-  addrs: Addrs,
+#[cfg_attr(
+  feature = "serde",
+  serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+  )
+)]
+pub enum FrameAddr {
+  /// An address relative to the main executable, which can be symbolicated
+  /// with its debug info.
+  Stable {
+    addr: u64,
+    #[cfg_attr(
+      feature = "serde",
+      serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    call_site: bool,
+  },
+  /// The absolute instruction pointer of a frame that isn't in the main
+  /// executable (e.g. in a shared library or JIT code).
+  Absolute {
+    addr: u64,
+    #[cfg_attr(
+      feature = "serde",
+      serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    call_site: bool,
+  },
+  /// An address relative to another module, as an index into
+  /// [`StackTrace::modules`]. Requires trace version 1.
+  InModule {
+    module: usize,
+    addr: u64,
+    #[cfg_attr(
+      feature = "serde",
+      serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    call_site: bool,
+  },
+  /// A frame whose address couldn't be determined at all.
+  Unknown,
 }
 
-impl StackTrace {
-  pub fn new(
-    addrs: Vec<u64>,
-    target_arch: &str,
-    target_os: &str,
-    version: Version,
-  ) -> Self {
-    StackTrace {
-      header: Header {
-        arch: match target_arch {
-          "aarch64" => Arch::Aarch64,
-          "x86_64" => Arch::X86_64,
-          other => Arch::Other(OtherString::new(other.into())),
-        },
-        trace_version: 0,
-        os: match target_os {
-          "macos" => Os::Mac,
-          "linux" => Os::Linux,
-          "windows" => Os::Windows,
-          value => Os::Other(OtherString::new(value.into())),
-        },
-        version,
+/// Set in the tag of return address frames.
+const FRAME_TAG_CALL_SITE: u8 = 0x80;
+
+/// Adjusts a return address to point into the call instruction.
+fn call_site_lookup_addr(addr: u64, call_site: bool) -> u64 {
+  if call_site {
+    addr.saturating_sub(1)
+  } else {
+    addr
+  }
+}
+
+impl From<Option<u64>> for FrameAddr {
+  fn from(value: Option<u64>) -> Self {
+    match value {
+      Some(addr) => FrameAddr::Stable {
+        addr,
+        call_site: false,
       },
-      addrs: Addrs(addrs),
+      None => FrameAddr::Unknown,
     }
   }
+}
 
-  pub fn encode(&self) -> Vec<u8> {
-    let mut buf = Vec::new();
-    <Self as Encode>::encode(self, &mut buf);
-    buf
+impl FrameAddr {
+  pub fn stable_addr(&self) -> Option<u64> {
+    match self {
+      FrameAddr::Stable { addr, .. } => Some(*addr),
+      FrameAddr::Absolute { .. }
+      | FrameAddr::InModule { .. }
+      | FrameAddr::Unknown => None,
+    }
   }
 
-  pub fn encode_base64url(&self) -> String {
-    let encoded = self.encode();
-    base64url_encode(&encoded)
+  /// Whether the frame's address is a return address.
+  pub fn is_call_site(&self) -> bool {
+    match self {
+      FrameAddr::Stable { call_site, .. }
+      | FrameAddr::Absolute { call_site, .. }
+      | FrameAddr::InModule { call_site, .. } => *call_site,
+      FrameAddr::Unknown => false,
+    }
+  }
+
+  /// The address to look up in debug info for this frame: the call
+  /// instruction for return addresses, and the address itself otherwise.
+  pub fn lookup_addr(&self) -> Option<u64> {
+    match self {
+      FrameAddr::Stable { addr, call_site }
+      | FrameAddr::Absolute { addr, call_site }
+      | FrameAddr::InModule {
+        addr, call_site, ..
+      } => Some(call_site_lookup_addr(*addr, *call_site)),
+      FrameAddr::Unknown => None,
+    }
+  }
+
+  /// The address written for this frame in trace version 0, which can only
+  /// hold stable addresses, adjusted for return addresses. Other frames are
+  /// written as 0 so the frame indices stay the same.
+  fn legacy_addr(&self) -> u64 {
+    match *self {
+      FrameAddr::Stable { addr, call_site } => {
+        call_site_lookup_addr(addr, call_site)
+      }
+      _ => 0,
+    }
+  }
+
+  fn tag(&self) -> u8 {
+    let tag = match self {
+      FrameAddr::Unknown => 0,
+      FrameAddr::Stable { .. } => 1,
+      FrameAddr::Absolute { .. } => 2,
+      FrameAddr::InModule { .. } => 3,
+    };
+    if self.is_call_site() {
+      tag | FRAME_TAG_CALL_SITE
+    } else {
+      tag
+    }
   }
 }
 
-#[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[cfg_attr(
-  feature = "serde",
-  serde(rename_all = "camelCase", from = "Vec<u64>", into = "Vec<u64>")
-)]
-struct Addrs(Vec<u64>);
-impl Encode for Addrs {
+impl Encode for FrameAddr {
   fn encoded_size(&self) -> usize {
-    self.0.iter().map(|v| v.encoded_size()).sum()
+    match self {
+      FrameAddr::Stable { addr, .. } | FrameAddr::Absolute { addr, .. } => {
+        1 + addr.encoded_size()
+      }
+      FrameAddr::InModule { module, addr, .. } => {
+        1 + (*module as u64).encoded_size() + addr.encoded_size()
+      }
+      FrameAddr::Unknown => 1,
+    }
   }
 
   fn encode_into(&self, buf: &mut [u8]) -> usize {
-    let mut i = 0;
-    for &v in &self.0 {
-      i += v.encode_into(&mut buf[i..]);
+    buf[0] = self.tag();
+    let mut i = 1;
+    match self {
+      FrameAddr::Unknown => {}
+      FrameAddr::Stable { addr, .. } | FrameAddr::Absolute { addr, .. } => {
+        i += addr.encode_into(&mut buf[i..]);
+      }
+      FrameAddr::InModule { module, addr, .. } => {
+        i += (*module as u64).encode_into(&mut buf[i..]);
+        i += addr.encode_into(&mut buf[i..]);
+      }
     }
     i
   }
 }
 
-impl From<Vec<u64>> for Addrs {
-  fn from(value: Vec<u64>) -> Self {
-    Self(value)
+impl Decode for FrameAddr {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 1;
+    let tag = peek_byte(buf)?;
+    let call_site = tag & FRAME_TAG_CALL_SITE != 0;
+    let frame = match tag & !FRAME_TAG_CALL_SITE {
+      0 if !call_site => FrameAddr::Unknown,
+      1 => FrameAddr::Stable {
+        addr: decode_at(buf, &mut i)?,
+        call_site,
+      },
+      2 => FrameAddr::Absolute {
+        addr: decode_at(buf, &mut i)?,
+        call_site,
+      },
+      3 => FrameAddr::InModule {
+        module: decode_at(buf, &mut i)?,
+        addr: decode_at(buf, &mut i)?,
+        call_site,
+      },
+      _ => {
+        return Err(DecodeError::UnknownDiscriminant {
+          offset: 0,
+          kind: "frame kind",
+          value: tag as u64,
+        });
+      }
+    };
+    Ok((frame, i))
+  }
+}
+
+impl Encode for String {
+  fn encoded_size(&self) -> usize {
+    (self.len() as u64).encoded_size() + self.len()
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    let i = (self.len() as u64).encode_into(buf);
+    buf[i..i + self.len()].copy_from_slice(self.as_bytes());
+    i + self.len()
   }
 }
 
-impl From<Addrs> for Vec<u64> {
-  fn from(value: Addrs) -> Self {
-    value.0
+impl Decode for String {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let len: usize = decode_at(buf, &mut i)?;
+    let bytes = buf
+      .get(i..i.saturating_add(len))
+      .ok_or(DecodeError::UnexpectedEof { offset: buf.len() })?;
+    Ok((String::from_utf8_lossy(bytes).into_owned(), i + len))
   }
 }
 
-impl Encode for StackTrace {
+impl Encode for Module {
   fn encoded_size(&self) -> usize {
-    self.header.encoded_size() + self.addrs.encoded_size()
+    self.path.encoded_size()
+      + self.base.encoded_size()
+      + self.build_id.encoded_size()
   }
 
   fn encode_into(&self, buf: &mut [u8]) -> usize {
     let mut i = 0;
-    i += self.header.encode_into(&mut buf[i..]);
-    i += self.addrs.encode_into(&mut buf[i..]);
+    i += self.path.encode_into(&mut buf[i..]);
+    i += self.base.encode_into(&mut buf[i..]);
+    i += self.build_id.encode_into(&mut buf[i..]);
     i
   }
 }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+impl Decode for Module {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let module = Module {
+      path: decode_at(buf, &mut i)?,
+      base: decode_at(buf, &mut i)?,
+      build_id: decode_at(buf, &mut i)?,
+    };
+    Ok((module, i))
+  }
+}
 
-  #[test]
+/// The thread a stack trace was captured on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ThreadInfo {
+  pub id: u64,
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub name: Option<String>,
+}
+
+impl Encode for ThreadInfo {
+  fn encoded_size(&self) -> usize {
+    self.id.encoded_size()
+      + match &self.name {
+        Some(name) => name.encoded_size(),
+        None => 1,
+      }
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    let mut i = self.id.encode_into(buf);
+    match &self.name {
+      Some(name) => i += name.encode_into(&mut buf[i..]),
+      None => i += 0u64.encode_into(&mut buf[i..]),
+    }
+    i
+  }
+}
+
+impl Decode for ThreadInfo {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let id = decode_at(buf, &mut i)?;
+    let name: String = decode_at(buf, &mut i)?;
+    let name = (!name.is_empty()).then_some(name);
+    Ok((ThreadInfo { id, name }, i))
+  }
+}
+
+/// The signal that caused a stack trace to be captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct SignalInfo {
+  /// The signal number, e.g. `SIGSEGV`.
+  pub signal: u32,
+  /// `si_code`, which says why the signal was sent. Often negative.
+  pub code: i32,
+  /// `si_addr`, for signals caused by a memory access.
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub fault_addr: Option<u64>,
+}
+
+fn zigzag(value: i32) -> u64 {
+  ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+fn unzigzag(value: u64) -> i32 {
+  let value = value as u32;
+  ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// Fault addresses are written plus one, so 0 can mean there is none.
+fn fault_addr_value(fault_addr: Option<u64>) -> u64 {
+  fault_addr.map_or(0, |addr| addr.wrapping_add(1))
+}
+
+impl Encode for SignalInfo {
+  fn encoded_size(&self) -> usize {
+    (self.signal as u64).encoded_size()
+      + zigzag(self.code).encoded_size()
+      + fault_addr_value(self.fault_addr).encoded_size()
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    let mut i = 0;
+    i += (self.signal as u64).encode_into(&mut buf[i..]);
+    i += zigzag(self.code).encode_into(&mut buf[i..]);
+    i += fault_addr_value(self.fault_addr).encode_into(&mut buf[i..]);
+    i
+  }
+}
+
+impl Decode for SignalInfo {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let signal = decode_at(buf, &mut i)?;
+    let code = unzigzag(decode_at(buf, &mut i)?);
+    let fault_addr = match decode_at::<u64>(buf, &mut i)? {
+      0 => None,
+      value => Some(value.wrapping_sub(1)),
+    };
+    let signal_info = SignalInfo {
+      signal,
+      code,
+      fault_addr,
+    };
+    Ok((signal_info, i))
+  }
+}
+
+/// The stack of another thread than the one a [`StackTrace`] was captured on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ThreadStack {
+  pub thread: ThreadInfo,
+  pub frames: Vec<FrameAddr>,
+}
+
+impl ThreadStack {
+  fn encoded_size(&self) -> usize {
+    self.thread.encoded_size()
+      + (self.frames.len() as u64).encoded_size()
+      + self.frames.iter().map(|f| f.encoded_size()).sum::<usize>()
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    let mut i = self.thread.encode_into(buf);
+    i += (self.frames.len() as u64).encode_into(&mut buf[i..]);
+    for frame in &self.frames {
+      i += frame.encode_into(&mut buf[i..]);
+    }
+    i
+  }
+}
+
+impl Decode for ThreadStack {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let thread = decode_at(buf, &mut i)?;
+    let count = decode_at::<u64>(buf, &mut i)?;
+    // Don't trust the count for the allocation, it's untrusted input.
+    let mut frames = Vec::new();
+    for _ in 0..count {
+      frames.push(decode_at(buf, &mut i)?);
+    }
+    Ok((ThreadStack { thread, frames }, i))
+  }
+}
+
+impl Encode for StackMemory {
+  fn encoded_size(&self) -> usize {
+    self.ip.encoded_size()
+      + (self.registers.len() as u64).encoded_size()
+      + self
+        .registers
+        .iter()
+        .map(|register| {
+          (register.number as u64).encoded_size()
+            + register.value.encoded_size()
+        })
+        .sum::<usize>()
+      + self.start.encoded_size()
+      + (self.memory.len() as u64).encoded_size()
+      + self.memory.len()
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    let mut i = self.ip.encode_into(buf);
+    i += (self.registers.len() as u64).encode_into(&mut buf[i..]);
+    for register in &self.registers {
+      i += (register.number as u64).encode_into(&mut buf[i..]);
+      i += register.value.encode_into(&mut buf[i..]);
+    }
+    i += self.start.encode_into(&mut buf[i..]);
+    i += (self.memory.len() as u64).encode_into(&mut buf[i..]);
+    buf[i..i + self.memory.len()].copy_from_slice(&self.memory);
+    i + self.memory.len()
+  }
+}
+
+impl Decode for StackMemory {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let ip = decode_at(buf, &mut i)?;
+    let count = decode_at::<u64>(buf, &mut i)?;
+    let mut registers = Vec::new();
+    for _ in 0..count {
+      registers.push(Register {
+        number: decode_at(buf, &mut i)?,
+        value: decode_at(buf, &mut i)?,
+      });
+    }
+    let start = decode_at(buf, &mut i)?;
+    let len: usize = decode_at(buf, &mut i)?;
+    let memory = buf
+      .get(i..i.saturating_add(len))
+      .ok_or(DecodeError::UnexpectedEof { offset: buf.len() })?
+      .to_vec();
+    let stack_memory = StackMemory {
+      ip,
+      registers,
+      start,
+      memory,
+    };
+    Ok((stack_memory, i + len))
+  }
+}
+
+const SECTION_BUILD_ID: u64 = 1;
+const SECTION_MODULES: u64 = 2;
+const SECTION_THREAD: u64 = 3;
+const SECTION_SIGNAL: u64 = 4;
+const SECTION_THREADS: u64 = 5;
+const SECTION_STACK_MEMORY: u64 = 6;
+
+/// A borrowed section of a version 1 trace, for encoding. Modules are an
+/// iterator so a subset of a module table can be encoded without allocating.
+pub(crate) enum Section<'a, M> {
+  BuildId(&'a Option<BuildId>),
+  Modules(M),
+  Thread(&'a ThreadInfo),
+  Signal(&'a SignalInfo),
+  /// The other threads' stacks.
+  Threads(&'a [ThreadStack]),
+  StackMemory(&'a StackMemory),
+}
+
+impl<'a, M: Iterator<Item = &'a Module> + Clone> Section<'a, M> {
+  fn tag(&self) -> u64 {
+    match self {
+      Section::BuildId(_) => SECTION_BUILD_ID,
+      Section::Modules(_) => SECTION_MODULES,
+      Section::Thread(_) => SECTION_THREAD,
+      Section::Signal(_) => SECTION_SIGNAL,
+      Section::Threads(..) => SECTION_THREADS,
+      Section::StackMemory(_) => SECTION_STACK_MEMORY,
+    }
+  }
+
+  fn payload_size(&self) -> usize {
+    match self {
+      Section::BuildId(build_id) => build_id.encoded_size(),
+      Section::Modules(modules) => {
+        (modules.clone().count() as u64).encoded_size()
+          + modules.clone().map(|m| m.encoded_size()).sum::<usize>()
+      }
+      Section::Thread(thread) => thread.encoded_size(),
+      Section::Signal(signal) => signal.encoded_size(),
+      Section::Threads(threads) => {
+        (threads.len() as u64).encoded_size()
+          + threads.iter().map(|t| t.encoded_size()).sum::<usize>()
+      }
+      Section::StackMemory(stack_memory) => stack_memory.encoded_size(),
+    }
+  }
+}
+
+impl<'a, M: Iterator<Item = &'a Module> + Clone> Encode for Section<'a, M> {
+  fn encoded_size(&self) -> usize {
+    let payload_size = self.payload_size();
+    self.tag().encoded_size()
+      + (payload_size as u64).encoded_size()
+      + payload_size
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    let mut i = 0;
+    i += self.tag().encode_into(&mut buf[i..]);
+    i += (self.payload_size() as u64).encode_into(&mut buf[i..]);
+    match self {
+      Section::BuildId(build_id) => i += build_id.encode_into(&mut buf[i..]),
+      Section::Modules(modules) => {
+        i += (modules.clone().count() as u64).encode_into(&mut buf[i..]);
+        for module in modules.clone() {
+          i += module.encode_into(&mut buf[i..]);
+        }
+      }
+      Section::Thread(thread) => i += thread.encode_into(&mut buf[i..]),
+      Section::Signal(signal) => i += signal.encode_into(&mut buf[i..]),
+      Section::Threads(threads) => {
+        i += (threads.len() as u64).encode_into(&mut buf[i..]);
+        for thread in *threads {
+          i += thread.encode_into(&mut buf[i..]);
+        }
+      }
+      Section::StackMemory(stack_memory) => {
+        i += stack_memory.encode_into(&mut buf[i..]);
+      }
+    }
+    i
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct StackTrace {
+  header: Header,
+  frames: Vec<FrameAddr>,
+  /// Modules referenced by [`FrameAddr::InModule`] frames. Index 0 is the
+  /// main executable. Only encoded from trace version 1 onwards, as are the
+  /// fields below.
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Vec::is_empty")
+  )]
+  modules: Vec<Module>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  thread: Option<ThreadInfo>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  signal: Option<SignalInfo>,
+  /// The stacks of other threads, e.g. when dumping every thread.
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Vec::is_empty")
+  )]
+  threads: Vec<ThreadStack>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  stack_memory: Option<StackMemory>,
+}
+
+impl StackTrace {
+  pub fn new(
+    addrs: Vec<u64>,
+    target_arch: &str,
+    target_os: &str,
+    version: Version,
+  ) -> Self {
+    StackTrace {
+      header: Header {
+        arch: match target_arch {
+          "aarch64" => Arch::Aarch64,
+          "x86_64" => Arch::X86_64,
+          other => Arch::Other(OtherString::new(other.into())),
+        },
+        trace_version: 0,
+        os: match target_os {
+          "macos" => Os::Mac,
+          "linux" => Os::Linux,
+          "windows" => Os::Windows,
+          value => Os::Other(OtherString::new(value.into())),
+        },
+        version,
+        build_id: None,
+      },
+      frames: addrs
+        .into_iter()
+        .map(|addr| FrameAddr::Stable {
+          addr,
+          call_site: false,
+        })
+        .collect(),
+      modules: Vec::new(),
+      thread: None,
+      signal: None,
+      threads: Vec::new(),
+      stack_memory: None,
+    }
+  }
+
+  /// Like [`StackTrace::new`], but keeps frames that have no stable address
+  /// (e.g. `None`s from `stable_stacktrace_addrs`), so frame indices match
+  /// the original stack. Uses the latest trace version.
+  pub fn from_frames(
+    frames: Vec<FrameAddr>,
+    target_arch: &str,
+    target_os: &str,
+    version: Version,
+  ) -> Self {
+    let mut stack_trace =
+      Self::new(Vec::new(), target_arch, target_os, version);
+    stack_trace.header.trace_version = LATEST_TRACE_VERSION;
+    stack_trace.frames = frames;
+    stack_trace
+  }
+
+  /// Builds a stack trace for the current platform from a captured stack,
+  /// including the module table and the main executable's build ID.
+  #[cfg(feature = "stacktrace")]
+  pub fn from_captured(
+    captured: &crate::CapturedStackTrace,
+    version: Version,
+  ) -> Self {
+    Self::from_captured_frames(&captured.frames, &captured.modules, version)
+  }
+
+  /// Builds a stack trace for the current platform from the captured stacks
+  /// of all threads. The calling thread's stack becomes the trace's frames,
+  /// and the others go into the threads section.
+  #[cfg(all(feature = "stacktrace", target_os = "linux"))]
+  pub fn from_captured_threads(
+    captured: &crate::CapturedThreads,
+    version: Version,
+  ) -> Self {
+    let thread_info = |thread: &crate::CapturedThreadStack| ThreadInfo {
+      id: thread.id,
+      name: thread.name.clone(),
+    };
+    let (current, others) = captured.threads.split_first().unwrap();
+    let threads = others
+      .iter()
+      .map(|thread| ThreadStack {
+        thread: thread_info(thread),
+        frames: thread.frames.iter().map(Self::captured_frame).collect(),
+      })
+      .collect();
+    Self::from_captured_frames(&current.frames, &captured.modules, version)
+      .with_thread(thread_info(current))
+      .with_threads(threads)
+  }
+
+  #[cfg(feature = "stacktrace")]
+  fn from_captured_frames(
+    frames: &[crate::Frame],
+    modules: &[Module],
+    version: Version,
+  ) -> Self {
+    let stack_trace = Self::from_frames(
+      frames.iter().map(Self::captured_frame).collect(),
+      std::env::consts::ARCH,
+      std::env::consts::OS,
+      version,
+    )
+    .with_modules(modules.to_vec());
+    match &modules[0].build_id {
+      Some(build_id) => stack_trace.with_build_id(build_id.clone()),
+      None => stack_trace,
+    }
+  }
+
+  #[cfg(feature = "stacktrace")]
+  fn captured_frame(frame: &crate::Frame) -> FrameAddr {
+    let call_site = !frame.exact;
+    match frame.stable {
+      Some(crate::StableAddr { module: 0, addr }) => {
+        FrameAddr::Stable { addr, call_site }
+      }
+      Some(crate::StableAddr { module, addr }) => FrameAddr::InModule {
+        module,
+        addr,
+        call_site,
+      },
+      None => FrameAddr::Absolute {
+        addr: frame.ip,
+        call_site,
+      },
+    }
+  }
+
+  /// Records the build ID of the binary the trace was captured from, so the
+  /// symbolicator can check it has the right debug info. This bumps the
+  /// trace version to the latest, as version 0 can't hold a build ID.
+  pub fn with_build_id(mut self, build_id: BuildId) -> Self {
+    self.header.trace_version = LATEST_TRACE_VERSION;
+    self.header.build_id = Some(build_id);
+    self
+  }
+
+  /// Sets the module table, bumping the trace version to the latest.
+  pub fn with_modules(mut self, modules: Vec<Module>) -> Self {
+    self.header.trace_version = LATEST_TRACE_VERSION;
+    self.modules = modules;
+    self
+  }
+
+  /// Records the thread the trace was captured on, bumping the trace version
+  /// to the latest.
+  pub fn with_thread(mut self, thread: ThreadInfo) -> Self {
+    self.header.trace_version = LATEST_TRACE_VERSION;
+    self.thread = Some(thread);
+    self
+  }
+
+  /// Records the signal that triggered the trace, bumping the trace version
+  /// to the latest.
+  pub fn with_signal(mut self, signal: SignalInfo) -> Self {
+    self.header.trace_version = LATEST_TRACE_VERSION;
+    self.signal = Some(signal);
+    self
+  }
+
+  /// Adds the stacks of other threads than the one in `frames` (see
+  /// [`StackTrace::with_thread`]), bumping the trace version to the latest.
+  pub fn with_threads(mut self, threads: Vec<ThreadStack>) -> Self {
+    self.header.trace_version = LATEST_TRACE_VERSION;
+    self.threads = threads;
+    self
+  }
+
+  /// Records registers and stack memory captured along with the trace, so
+  /// the symbolicator can unwind the stack again, bumping the trace version
+  /// to the latest.
+  pub fn with_stack_memory(mut self, stack_memory: StackMemory) -> Self {
+    self.header.trace_version = LATEST_TRACE_VERSION;
+    self.stack_memory = Some(stack_memory);
+    self
+  }
+
+  /// Replaces the trace's frames, e.g. with the ones a symbolicator found by
+  /// unwinding its stack memory. Everything else is kept.
+  pub fn with_frames(mut self, frames: Vec<FrameAddr>) -> Self {
+    self.frames = frames;
+    self
+  }
+
+  pub fn encode(&self) -> Vec<u8> {
+    let mut buf = Vec::new();
+    <Self as Encode>::encode(self, &mut buf);
+    buf
+  }
+
+  pub fn encode_base64url(&self) -> String {
+    let encoded = self.encode();
+    base64url_encode(&encoded)
+  }
+
+  /// Encodes the trace as an older (or newer) trace version, e.g. for servers
+  /// that don't understand the latest one. Anything the version can't hold is
+  /// dropped, and frames without a stable address are written as address 0
+  /// in version 0.
+  ///
+  /// # Panics
+  ///
+  /// If `trace_version` is greater than [`LATEST_TRACE_VERSION`].
+  pub fn encode_as(&self, trace_version: u8) -> Vec<u8> {
+    assert!(trace_version <= LATEST_TRACE_VERSION);
+    let mut stack_trace = self.clone();
+    stack_trace.header.trace_version = trace_version;
+    stack_trace.encode()
+  }
+
+  /// Like [`StackTrace::encode_as`], but encodes to base64url.
+  pub fn encode_base64url_as(&self, trace_version: u8) -> String {
+    base64url_encode(&self.encode_as(trace_version))
+  }
+
+  /// Decodes a stack trace produced by [`StackTrace::encode`].
+  pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+    let (stack_trace, _) = <Self as Decode>::decode_from(buf)?;
+    Ok(stack_trace)
+  }
+
+  /// Decodes a stack trace produced by [`StackTrace::encode_base64url`].
+  pub fn decode_base64url(s: &str) -> Result<Self, DecodeError> {
+    let decoded = base64url_decode(s)?;
+    Self::decode(&decoded)
+  }
+
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+
+  pub fn frames(&self) -> &[FrameAddr] {
+    &self.frames
+  }
+
+  pub fn modules(&self) -> &[Module] {
+    &self.modules
+  }
+
+  pub fn thread(&self) -> Option<&ThreadInfo> {
+    self.thread.as_ref()
+  }
+
+  pub fn signal(&self) -> Option<&SignalInfo> {
+    self.signal.as_ref()
+  }
+
+  pub fn threads(&self) -> &[ThreadStack] {
+    &self.threads
+  }
+
+  pub fn stack_memory(&self) -> Option<&StackMemory> {
+    self.stack_memory.as_ref()
+  }
+
+  fn sections(&self) -> Vec<Section<'_, std::slice::Iter<'_, Module>>> {
+    let mut sections = Vec::new();
+    if self.header.build_id.is_some() {
+      sections.push(Section::BuildId(&self.header.build_id));
+    }
+    if !self.modules.is_empty() {
+      sections.push(Section::Modules(self.modules.iter()));
+    }
+    if let Some(thread) = &self.thread {
+      sections.push(Section::Thread(thread));
+    }
+    if let Some(signal) = &self.signal {
+      sections.push(Section::Signal(signal));
+    }
+    if !self.threads.is_empty() {
+      sections.push(Section::Threads(&self.threads));
+    }
+    if let Some(stack_memory) = &self.stack_memory {
+      sections.push(Section::StackMemory(stack_memory));
+    }
+    sections
+  }
+}
+
+impl Encode for StackTrace {
+  fn encoded_size(&self) -> usize {
+    let mut size = self.header.encoded_size();
+    if self.header.trace_version == 0 {
+      size += self
+        .frames
+        .iter()
+        .map(|f| f.legacy_addr().encoded_size())
+        .sum::<usize>();
+      return size;
+    }
+    size += (self.frames.len() as u64).encoded_size();
+    size += self.frames.iter().map(|f| f.encoded_size()).sum::<usize>();
+    size += self
+      .sections()
+      .iter()
+      .map(|s| s.encoded_size())
+      .sum::<usize>();
+    size
+  }
+
+  fn encode_into(&self, buf: &mut [u8]) -> usize {
+    let mut i = 0;
+    i += self.header.encode_into(&mut buf[i..]);
+    if self.header.trace_version == 0 {
+      for frame in &self.frames {
+        i += frame.legacy_addr().encode_into(&mut buf[i..]);
+      }
+      return i;
+    }
+    i += (self.frames.len() as u64).encode_into(&mut buf[i..]);
+    for frame in &self.frames {
+      i += frame.encode_into(&mut buf[i..]);
+    }
+    for section in self.sections() {
+      i += section.encode_into(&mut buf[i..]);
+    }
+    i
+  }
+}
+
+/// Encodes a version 1 trace into `buf` without allocating, for
+/// callers that can't allocate (e.g. signal handlers). `frames` must be
+/// representable in the header's version. `None` sections are skipped.
+/// Returns `None` if `buf` is too small.
+#[cfg(feature = "stacktrace")]
+pub(crate) fn encode_sections_into<
+  'a,
+  M: Iterator<Item = &'a Module> + Clone,
+>(
+  header: &Header,
+  frames: impl Iterator<Item = FrameAddr> + Clone,
+  sections: &[Option<Section<'a, M>>],
+  buf: &mut [u8],
+) -> Option<usize> {
+  debug_assert!(header.trace_version >= 1);
+  let sections = sections.iter().flatten();
+  let frame_count = frames.clone().count() as u64;
+  let size = header.encoded_size()
+    + frame_count.encoded_size()
+    + frames.clone().map(|f| f.encoded_size()).sum::<usize>()
+    + sections.clone().map(|s| s.encoded_size()).sum::<usize>();
+  let buf = buf.get_mut(..size)?;
+  let mut i = 0;
+  i += header.encode_into(&mut buf[i..]);
+  i += frame_count.encode_into(&mut buf[i..]);
+  for frame in frames {
+    i += frame.encode_into(&mut buf[i..]);
+  }
+  for section in sections {
+    i += section.encode_into(&mut buf[i..]);
+  }
+  Some(i)
+}
+
+impl StackTrace {
+  /// Decodes the payload of a known section into `self`. Unknown sections are
+  /// skipped.
+  fn decode_section(
+    &mut self,
+    tag: u64,
+    payload: &[u8],
+  ) -> Result<(), DecodeError> {
+    let mut i = 0;
+    match tag {
+      SECTION_BUILD_ID => self.header.build_id = decode_at(payload, &mut i)?,
+      SECTION_MODULES => {
+        let count = decode_at::<u64>(payload, &mut i)?;
+        // Don't trust the count for the allocation, it's untrusted input.
+        let mut modules = Vec::new();
+        for _ in 0..count {
+          modules.push(decode_at(payload, &mut i)?);
+        }
+        self.modules = modules;
+      }
+      SECTION_THREAD => self.thread = Some(decode_at(payload, &mut i)?),
+      SECTION_SIGNAL => self.signal = Some(decode_at(payload, &mut i)?),
+      SECTION_THREADS => {
+        let count = decode_at::<u64>(payload, &mut i)?;
+        let mut threads = Vec::new();
+        for _ in 0..count {
+          threads.push(decode_at(payload, &mut i)?);
+        }
+        self.threads = threads;
+      }
+      SECTION_STACK_MEMORY => {
+        self.stack_memory = Some(decode_at(payload, &mut i)?);
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+}
+
+impl Decode for StackTrace {
+  fn decode_from(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+    let mut i = 0;
+    let header: Header = decode_at(buf, &mut i)?;
+    let version = header.trace_version;
+    let mut stack_trace = StackTrace {
+      header,
+      frames: Vec::new(),
+      modules: Vec::new(),
+      thread: None,
+      signal: None,
+      threads: Vec::new(),
+      stack_memory: None,
+    };
+    if version == 0 {
+      while i < buf.len() {
+        stack_trace.frames.push(FrameAddr::Stable {
+          addr: decode_at(buf, &mut i)?,
+          call_site: false,
+        });
+      }
+      return Ok((stack_trace, i));
+    }
+
+    let frame_count = decode_at::<u64>(buf, &mut i)?;
+    for _ in 0..frame_count {
+      stack_trace.frames.push(decode_at(buf, &mut i)?);
+    }
+    while i < buf.len() {
+      let tag: u64 = decode_at(buf, &mut i)?;
+      let len: usize = decode_at(buf, &mut i)?;
+      let payload = buf
+        .get(i..i.saturating_add(len))
+        .ok_or(DecodeError::UnexpectedEof { offset: buf.len() })?;
+      stack_trace
+        .decode_section(tag, payload)
+        .map_err(|e| e.offset_by(i))?;
+      i += len;
+    }
+    Ok((stack_trace, i))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
   fn encode() {
     let stack_trace = StackTrace::new(
       vec![1, 2, 3],
@@ -491,4 +1950,575 @@ mod tests {
       ]
     )
   }
+
+  #[test]
+  fn decode() {
+    let encoded = [0, 2, 1, 4, 5, 6, 0, 1, 1, 2, 0xAC, 0x02];
+    let stack_trace = StackTrace::decode(&encoded).unwrap();
+    assert_eq!(
+      stack_trace,
+      StackTrace::new(
+        vec![1, 2, 300],
+        "aarch64",
+        "windows",
+        Version {
+          major: 4,
+          minor: 5,
+          patch: 6,
+          canary_hash: CanaryHash::none(),
+          dev_build: true,
+        },
+      )
+    );
+  }
+
+  #[test]
+  fn encode_build_id() {
+    let stack_trace = StackTrace::new(
+      vec![1],
+      "x86_64",
+      "macos",
+      Version {
+        major: 4,
+        minor: 5,
+        patch: 6,
+        canary_hash: CanaryHash::none(),
+        dev_build: false,
+      },
+    )
+    .with_build_id(BuildId::MachO([0xAB; 16]));
+    let encoded = stack_trace.encode();
+    let mut expected = vec![
+      1, // trace version 1
+      1, // os macos
+      0, // arch x86_64
+      4, // major 4
+      5, // minor 5
+      6, // patch 6
+      0, // canary hash none
+      0, // dev build false
+      1, // 1 frame
+      1, 1, // stable addr 1
+      1, 17, // build id section
+      2,  // build id mach-o
+    ];
+    expected.extend([0xAB; 16]);
+    assert_eq!(encoded, expected);
+    assert_eq!(StackTrace::decode(&encoded).unwrap(), stack_trace);
+  }
+
+  #[test]
+  fn encode_frames() {
+    let version = Version {
+      major: 1,
+      minor: 2,
+      patch: 3,
+      canary_hash: CanaryHash::none(),
+      dev_build: false,
+    };
+    let frames = vec![
+      FrameAddr::Stable {
+        addr: 2,
+        call_site: true,
+      },
+      FrameAddr::Unknown,
+      FrameAddr::Absolute {
+        addr: 300,
+        call_site: true,
+      },
+    ];
+    let stack_trace =
+      StackTrace::from_frames(frames, "x86_64", "linux", version.clone());
+    let encoded = stack_trace.encode();
+    assert_eq!(
+      encoded,
+      vec![
+        1, // trace version 1
+        0, // os linux
+        0, // arch x86_64
+        1, // major 1
+        2, // minor 2
+        3, // patch 3
+        0, // canary hash none
+        0, // dev build false
+        3, // 3 frames
+        0x81, 2, // stable call site addr 2
+        0, // unknown
+        0x82, 0xAC, 0x02, // absolute call site addr 300
+      ]
+    );
+    assert_eq!(StackTrace::decode(&encoded).unwrap(), stack_trace);
+    assert_eq!(stack_trace.frames()[0].lookup_addr(), Some(1));
+
+    // Version 0 can only hold stable addresses, and has no call site flag, so
+    // they are adjusted when encoding instead.
+    let encoded = stack_trace.encode_as(0);
+    assert_eq!(encoded, vec![0, 0, 0, 1, 2, 3, 0, 0, 1, 0, 0]);
+    assert_eq!(
+      StackTrace::decode(&encoded).unwrap().frames()[0],
+      FrameAddr::Stable {
+        addr: 1,
+        call_site: false
+      }
+    );
+  }
+
+  #[test]
+  fn encode_sections() {
+    let version = Version {
+      major: 1,
+      minor: 2,
+      patch: 3,
+      canary_hash: CanaryHash::none(),
+      dev_build: false,
+    };
+    let frames = vec![
+      FrameAddr::Stable {
+        addr: 1,
+        call_site: false,
+      },
+      FrameAddr::InModule {
+        module: 1,
+        addr: 2,
+        call_site: false,
+      },
+    ];
+    let stack_trace =
+      StackTrace::from_frames(frames, "x86_64", "linux", version)
+        .with_build_id(BuildId::Gnu(vec![0xAB, 0xCD]))
+        .with_modules(vec![
+          Module {
+            path: "deno".into(),
+            base: 0x100,
+            build_id: Some(BuildId::Gnu(vec![0xAB, 0xCD])),
+          },
+          Module {
+            path: "libc".into(),
+            base: 0x200,
+            build_id: None,
+          },
+        ])
+        .with_thread(ThreadInfo {
+          id: 7,
+          name: Some("main".into()),
+        })
+        .with_signal(SignalInfo {
+          signal: 11,
+          code: -1,
+          fault_addr: Some(0),
+        })
+        .with_threads(vec![ThreadStack {
+          thread: ThreadInfo { id: 8, name: None },
+          frames: vec![FrameAddr::Absolute {
+            addr: 5,
+            call_site: true,
+          }],
+        }]);
+    let encoded = stack_trace.encode();
+    #[rustfmt::skip]
+    let expected = vec![
+      1, 0, 0, 1, 2, 3, 0, 0, // header
+      2, // 2 frames
+      1, 1, // stable addr 1
+      3, 1, 2, // module 1 addr 2
+      1, 4, 1, 2, 0xAB, 0xCD, // build id section
+      2, 20, 2, // module table section, 2 modules
+      4, b'd', b'e', b'n', b'o', 0x80, 0x02, 1, 2, 0xAB, 0xCD,
+      4, b'l', b'i', b'b', b'c', 0x80, 0x04, 0,
+      3, 6, 7, 4, b'm', b'a', b'i', b'n', // thread section
+      4, 3, 11, 1, 1, // signal section
+      5, 6, 1, // threads section, 1 thread
+      8, 0, 1, 0x82, 5, // thread 8, 1 frame: absolute call site addr 5
+    ];
+    assert_eq!(encoded, expected);
+    assert_eq!(StackTrace::decode(&encoded).unwrap(), stack_trace);
+
+    // Version 0 drops what it can't represent.
+    let decoded = StackTrace::decode(&stack_trace.encode_as(0)).unwrap();
+    assert_eq!(
+      decoded.frames(),
+      [
+        FrameAddr::Stable {
+          addr: 1,
+          call_site: false
+        },
+        FrameAddr::Stable {
+          addr: 0,
+          call_site: false
+        }
+      ]
+    );
+    assert_eq!(decoded.header().build_id(), None);
+    assert!(decoded.modules().is_empty());
+    assert_eq!(decoded.thread(), None);
+    assert!(decoded.threads().is_empty());
+  }
+
+  #[test]
+  fn skip_unknown_sections() {
+    let mut encoded = vec![1, 0, 0, 1, 2, 3, 0, 0, 1, 1, 1];
+    // An unknown section, followed by a thread section with an extra byte
+    // at the end of its payload.
+    encoded.extend([99, 3, 1, 2, 3]);
+    encoded.extend([3, 3, 5, 0, 42]);
+    let stack_trace = StackTrace::decode(&encoded).unwrap();
+    assert_eq!(
+      stack_trace.frames(),
+      [FrameAddr::Stable {
+        addr: 1,
+        call_site: false
+      }]
+    );
+    assert_eq!(
+      stack_trace.thread(),
+      Some(&ThreadInfo { id: 5, name: None })
+    );
+
+    // Sections can't extend past the end of the input.
+    encoded.extend([4, 10, 11]);
+    assert_eq!(
+      StackTrace::decode(&encoded),
+      Err(DecodeError::UnexpectedEof {
+        offset: encoded.len()
+      })
+    );
+  }
+
+  #[test]
+  fn stack_memory_section() {
+    let version = Version {
+      major: 1,
+      minor: 2,
+      patch: 3,
+      canary_hash: CanaryHash::none(),
+      dev_build: false,
+    };
+    let stack_memory = StackMemory {
+      ip: 0x1234,
+      registers: vec![
+        Register {
+          number: 6,
+          value: 0x7f10,
+        },
+        Register {
+          number: 7,
+          value: 0x7f00,
+        },
+      ],
+      start: 0x7f00,
+      memory: (1..=10).collect(),
+    };
+    let stack_trace = StackTrace::from_frames(
+      vec![FrameAddr::Unknown],
+      "x86_64",
+      "linux",
+      version,
+    )
+    .with_stack_memory(stack_memory.clone());
+    let encoded = stack_trace.encode();
+    #[rustfmt::skip]
+    let expected = vec![
+      1, 0, 0, 1, 2, 3, 0, 0, // header
+      1, 0, // 1 unknown frame
+      6, 25, // stack memory section
+      0xB4, 0x24, // ip
+      2, 6, 0x90, 0xFE, 0x01, 7, 0x80, 0xFE, 0x01, // rbp and rsp
+      0x80, 0xFE, 0x01, // start
+      10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, // memory
+    ];
+    assert_eq!(encoded, expected);
+    let decoded = StackTrace::decode(&encoded).unwrap();
+    assert_eq!(decoded.stack_memory(), Some(&stack_memory));
+
+    // Register 65542 doesn't fit, rather than wrapping to 6 (`rbp`).
+    assert_eq!(
+      StackMemory::decode_from(&[0, 1, 0x86, 0x80, 0x04, 0]),
+      Err(DecodeError::InvalidVarint { offset: 2 })
+    );
+
+    assert_eq!(stack_memory.register(7), Some(0x7f00));
+    assert_eq!(stack_memory.register(16), None);
+    assert_eq!(stack_memory.read_u64(0x7f02), Some(0x0a09_0807_0605_0403));
+    assert_eq!(stack_memory.read_u64(0x7f03), None);
+    assert_eq!(stack_memory.read_u64(0x7eff), None);
+  }
+
+  #[test]
+  fn find_trace_in_banner() {
+    let log =
+      format!("panicked\n{TRACE_BANNER_BEGIN}\nAAECAw\n{TRACE_BANNER_END}\n");
+    assert_eq!(find_banner_trace(&log), Some("AAECAw"));
+    assert_eq!(find_banner_trace(TRACE_BANNER_BEGIN), None);
+    assert_eq!(find_banner_trace("AAECAw"), None);
+  }
+
+  #[test]
+  fn decode_errors() {
+    assert_eq!(
+      StackTrace::decode(&[0, 2, 1, 4]),
+      Err(DecodeError::UnexpectedEof { offset: 4 })
+    );
+    assert_eq!(
+      StackTrace::decode(&[0, 5, b'l', b'i']),
+      Err(DecodeError::UnexpectedEof { offset: 4 })
+    );
+    assert_eq!(
+      StackTrace::decode(&[0, 0, 0, 1, 2, 3, 0, 2]),
+      Err(DecodeError::UnknownDiscriminant {
+        offset: 7,
+        kind: "bool",
+        value: 2
+      })
+    );
+    assert_eq!(
+      StackTrace::decode(&[9, 0, 0]),
+      Err(DecodeError::UnknownDiscriminant {
+        offset: 0,
+        kind: "trace version",
+        value: 9
+      })
+    );
+    let mut overlong = vec![0, 0, 0, 1, 2, 3, 0, 0];
+    overlong.extend([0xFF; 10]);
+    overlong.push(0);
+    assert_eq!(
+      StackTrace::decode(&overlong),
+      Err(DecodeError::InvalidVarint { offset: 8 })
+    );
+    // A signal number of 2^32 doesn't fit, rather than wrapping to 0.
+    assert_eq!(
+      SignalInfo::decode_from(&[0x80, 0x80, 0x80, 0x80, 0x10, 0, 0]),
+      Err(DecodeError::InvalidVarint { offset: 0 })
+    );
+    assert_eq!(
+      StackTrace::decode_base64url("AAAA*A"),
+      Err(DecodeError::InvalidBase64 { offset: 4 })
+    );
+  }
+
+  mod prop {
+    use super::super::*;
+    use proptest::prelude::*;
+
+    const KNOWN_NAMES: &[&str] =
+      &["linux", "macos", "windows", "x86_64", "aarch64"];
+
+    fn os() -> impl Strategy<Value = Os> {
+      prop_oneof![
+        Just(Os::Linux),
+        Just(Os::Mac),
+        Just(Os::Windows),
+        "[a-z0-9_]{3,40}"
+          .prop_filter("known os", |s| !KNOWN_NAMES.contains(&s.as_str()))
+          .prop_map(Os::from),
+      ]
+    }
+
+    fn arch() -> impl Strategy<Value = Arch> {
+      prop_oneof![
+        Just(Arch::X86_64),
+        Just(Arch::Aarch64),
+        "[a-z0-9_]{2,40}"
+          .prop_filter("known arch", |s| !KNOWN_NAMES.contains(&s.as_str()))
+          .prop_map(Arch::from),
+      ]
+    }
+
+    fn version() -> impl Strategy<Value = Version> {
+      (
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        proptest::option::of("[0-9a-f]{1,40}"),
+        any::<bool>(),
+      )
+        .prop_map(|(major, minor, patch, canary_hash, dev_build)| {
+          Version {
+            major,
+            minor,
+            patch,
+            canary_hash: canary_hash.into(),
+            dev_build,
+          }
+        })
+    }
+
+    fn build_id() -> impl Strategy<Value = BuildId> {
+      prop_oneof![
+        proptest::collection::vec(any::<u8>(), 0..=u8::MAX as usize)
+          .prop_map(BuildId::Gnu),
+        any::<[u8; 16]>().prop_map(BuildId::MachO),
+        (any::<[u8; 16]>(), any::<u32>())
+          .prop_map(|(guid, age)| BuildId::Pdb { guid, age }),
+      ]
+    }
+
+    fn frame() -> impl Strategy<Value = FrameAddr> {
+      prop_oneof![
+        (any::<u64>(), any::<bool>()).prop_map(|(addr, call_site)| {
+          FrameAddr::Stable { addr, call_site }
+        }),
+        (any::<u64>(), any::<bool>()).prop_map(|(addr, call_site)| {
+          FrameAddr::Absolute { addr, call_site }
+        }),
+        (any::<u32>(), any::<u64>(), any::<bool>()).prop_map(
+          |(module, addr, call_site)| FrameAddr::InModule {
+            module: module as usize,
+            addr,
+            call_site,
+          }
+        ),
+        Just(FrameAddr::Unknown),
+      ]
+    }
+
+    fn module() -> impl Strategy<Value = Module> {
+      (".{0,40}", any::<u64>(), proptest::option::of(build_id())).prop_map(
+        |(path, base, build_id)| Module {
+          path,
+          base,
+          build_id,
+        },
+      )
+    }
+
+    fn thread() -> impl Strategy<Value = ThreadInfo> {
+      (any::<u64>(), proptest::option::of(".{1,40}"))
+        .prop_map(|(id, name)| ThreadInfo { id, name })
+    }
+
+    fn signal() -> impl Strategy<Value = SignalInfo> {
+      (
+        any::<u32>(),
+        any::<i32>(),
+        proptest::option::of(any::<u64>()),
+      )
+        .prop_map(|(signal, code, fault_addr)| SignalInfo {
+          signal,
+          code,
+          fault_addr,
+        })
+    }
+
+    fn thread_stack() -> impl Strategy<Value = ThreadStack> {
+      (thread(), proptest::collection::vec(frame(), 0..16))
+        .prop_map(|(thread, frames)| ThreadStack { thread, frames })
+    }
+
+    fn stack_memory() -> impl Strategy<Value = StackMemory> {
+      (
+        any::<u64>(),
+        proptest::collection::vec(
+          (any::<u16>(), any::<u64>())
+            .prop_map(|(number, value)| Register { number, value }),
+          0..24,
+        ),
+        any::<u64>(),
+        proptest::collection::vec(any::<u8>(), 0..256),
+      )
+        .prop_map(|(ip, registers, start, memory)| StackMemory {
+          ip,
+          registers,
+          start,
+          memory,
+        })
+    }
+
+    fn stack_trace() -> impl Strategy<Value = StackTrace> {
+      (
+        prop_oneof![
+          proptest::collection::vec(any::<u64>(), 0..64).prop_map(Err),
+          proptest::collection::vec(frame(), 0..64).prop_map(Ok),
+        ],
+        arch(),
+        os(),
+        version(),
+        proptest::option::of(build_id()),
+        proptest::collection::vec(module(), 0..8),
+        proptest::option::of(thread()),
+        proptest::option::of(signal()),
+        proptest::collection::vec(thread_stack(), 0..4),
+        proptest::option::of(stack_memory()),
+      )
+        .prop_map(
+          |(
+            frames,
+            arch,
+            os,
+            version,
+            build_id,
+            modules,
+            thread,
+            signal,
+            threads,
+            stack_memory,
+          )| {
+            let (arch, os) = (String::from(arch), String::from(os));
+            let mut stack_trace = match frames {
+              Ok(frames) => {
+                StackTrace::from_frames(frames, &arch, &os, version)
+                  .with_modules(modules)
+              }
+              Err(addrs) => StackTrace::new(addrs, &arch, &os, version),
+            };
+            if let Some(build_id) = build_id {
+              stack_trace = stack_trace.with_build_id(build_id);
+            }
+            if let Some(thread) = thread {
+              stack_trace = stack_trace.with_thread(thread);
+            }
+            if let Some(signal) = signal {
+              stack_trace = stack_trace.with_signal(signal);
+            }
+            if !threads.is_empty() {
+              stack_trace = stack_trace.with_threads(threads);
+            }
+            if let Some(stack_memory) = stack_memory {
+              stack_trace = stack_trace.with_stack_memory(stack_memory);
+            }
+            stack_trace
+          },
+        )
+    }
+
+    proptest! {
+      #[test]
+      fn round_trip(stack_trace in stack_trace()) {
+        let decoded = StackTrace::decode(&stack_trace.encode()).unwrap();
+        prop_assert_eq!(decoded, stack_trace);
+      }
+
+      #[test]
+      fn round_trip_base64url(stack_trace in stack_trace()) {
+        let encoded = stack_trace.encode_base64url();
+        let decoded = StackTrace::decode_base64url(&encoded).unwrap();
+        prop_assert_eq!(decoded, stack_trace);
+      }
+
+      #[test]
+      fn varint_round_trip(value in any::<u64>()) {
+        let mut buf = Vec::new();
+        let n = value.encode(&mut buf);
+        prop_assert_eq!(u64::decode_from(&buf), Ok((value, n)));
+      }
+
+      #[test]
+      fn decode_arbitrary_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..128)) {
+        // Must never panic, regardless of the input.
+        let _ = StackTrace::decode(&bytes);
+      }
+
+      #[test]
+      fn truncated_input_errors(stack_trace in stack_trace(), cut in any::<prop::sample::Index>()) {
+        let encoded = stack_trace.encode();
+        let header_len = stack_trace.header.encoded_size();
+        let cut = cut.index(header_len);
+        let is_eof = matches!(
+          StackTrace::decode(&encoded[..cut]),
+          Err(DecodeError::UnexpectedEof { .. })
+        );
+        prop_assert!(is_eof);
+      }
+    }
+  }
 }
@@ -0,0 +1,30 @@
+/// Identifies the exact build of a module, so the matching debug file can be
+/// found for it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(tag = "kind", content = "value", rename_all = "camelCase")
+)]
+pub enum BuildId {
+  /// The contents of an ELF `NT_GNU_BUILD_ID` note.
+  Gnu(Vec<u8>),
+  /// The `LC_UUID` of a Mach-O image.
+  MachO([u8; 16]),
+  /// The CodeView (`RSDS`) signature of a PE image. The GUID bytes are as
+  /// laid out in memory, i.e. with little endian fields.
+  Pdb { guid: [u8; 16], age: u32 },
+}
+
+/// A module (executable or shared library) loaded into the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Module {
+  /// Path of the module, as reported by the dynamic loader.
+  pub path: String,
+  /// The address the module was loaded at. Stable addresses of frames in
+  /// this module are relative to it.
+  pub base: u64,
+  pub build_id: Option<BuildId>,
+}
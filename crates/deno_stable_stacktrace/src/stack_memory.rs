@@ -0,0 +1,47 @@
+/// A register captured in a [`StackMemory`] snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Register {
+  /// The register's DWARF number for the trace's architecture, e.g. 6 for
+  /// `rbp` on x86_64 or 29 for `x29` on aarch64.
+  pub number: u16,
+  pub value: u64,
+}
+
+/// The registers of the crashing (or capturing) frame and a bounded slice of
+/// the stack above its stack pointer, so the stack can be unwound again
+/// offline with the binary's unwind info, when the client's unwinder gave up
+/// early.
+///
+/// Only callee-saved registers, the stack pointer and the frame pointer are
+/// recorded (plus the link register on aarch64), since unwinding doesn't need
+/// the others.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct StackMemory {
+  /// The absolute instruction pointer of the frame the registers are from.
+  pub ip: u64,
+  pub registers: Vec<Register>,
+  /// The address `memory` was copied from, usually the stack pointer.
+  pub start: u64,
+  pub memory: Vec<u8>,
+}
+
+impl StackMemory {
+  pub fn register(&self, number: u16) -> Option<u64> {
+    self
+      .registers
+      .iter()
+      .find(|register| register.number == number)
+      .map(|register| register.value)
+  }
+
+  /// Reads a little endian `u64` at an absolute address, or `None` if it's
+  /// outside of the captured memory.
+  pub fn read_u64(&self, addr: u64) -> Option<u64> {
+    let offset = usize::try_from(addr.checked_sub(self.start)?).ok()?;
+    let bytes = self.memory.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+  }
+}
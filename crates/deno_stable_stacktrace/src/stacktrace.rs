@@ -0,0 +1,355 @@
+use crate::{BuildId, Module};
+
+#[cfg(feature = "encode")]
+mod hook;
+mod modules;
+mod signal_safe;
+mod stack_memory;
+#[cfg(target_os = "linux")]
+mod threads;
+
+#[cfg(all(unix, feature = "encode"))]
+pub use hook::install_fatal_signal_handler;
+#[cfg(feature = "encode")]
+pub use hook::{HookOptions, install_panic_hook};
+#[cfg(feature = "encode")]
+pub use signal_safe::SignalSafeEncoder;
+pub use signal_safe::{
+  cache_modules, cached_module, signal_safe_stacktrace,
+  signal_safe_stacktrace_from_ip,
+};
+#[cfg(unix)]
+pub use stack_memory::capture_stack_memory_from_ucontext;
+pub use stack_memory::{MAX_STACK_REGISTERS, capture_stack_memory};
+#[cfg(target_os = "linux")]
+pub use threads::{
+  CapturedThreadStack, CapturedThreads, all_threads_stacktraces,
+};
+
+/// The location of a frame within a loaded module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StableAddr {
+  /// Index into [`CapturedStackTrace::modules`].
+  pub module: usize,
+  /// The frame's instruction pointer, relative to the module's base address.
+  /// Like [`Frame::ip`], it's a return address unless the frame is
+  /// [exact](Frame::exact).
+  pub addr: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Frame {
+  /// The absolute instruction pointer reported by the unwinder.
+  pub ip: u64,
+  /// `None` if the instruction pointer isn't inside of any loaded module.
+  pub stable: Option<StableAddr>,
+  /// Whether `ip` is the exact instruction that was executing (e.g. the
+  /// faulting instruction of a crash), rather than a return address, which
+  /// points after the call instruction.
+  ///
+  /// Symbolicators look up return addresses at `ip - 1` to find the call, and
+  /// exact frames at `ip`.
+  pub exact: bool,
+}
+
+impl Frame {
+  /// The address to look up for an instruction pointer. Return addresses
+  /// point to the instruction after the call, which may belong to a different
+  /// function (or even module), so the call instruction is looked up instead.
+  pub(crate) fn lookup_addr(ip: u64, exact: bool) -> u64 {
+    if exact { ip } else { ip.saturating_sub(1) }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedStackTrace {
+  /// The modules referenced by `frames`. The main executable is always the
+  /// first entry, whether or not any frames are in it.
+  pub modules: Vec<Module>,
+  pub frames: Vec<Frame>,
+}
+
+impl CapturedStackTrace {
+  /// Returns the stable address of each frame in the main executable, and
+  /// `None` for frames that are in other modules (or no module at all).
+  ///
+  /// Return addresses are adjusted to point into the call instruction, as
+  /// expected by `StackTrace::new`.
+  pub fn main_module_addrs(&self) -> Vec<Option<u64>> {
+    self
+      .frames
+      .iter()
+      .map(|frame| match frame.stable {
+        Some(StableAddr { module: 0, addr }) => {
+          Some(Frame::lookup_addr(addr, frame.exact))
+        }
+        _ => None,
+      })
+      .collect()
+  }
+}
+
+/// Returns the instruction pointer stored in a `ucontext_t`, as passed to a
+/// `SA_SIGINFO` signal handler, or `None` on unsupported architectures.
+///
+/// # Safety
+///
+/// `ucontext` must point to a valid `ucontext_t`.
+#[cfg(unix)]
+pub unsafe fn ucontext_ip(ucontext: *const std::ffi::c_void) -> Option<u64> {
+  let ucontext = ucontext.cast::<libc::ucontext_t>();
+  if ucontext.is_null() {
+    return None;
+  }
+  unsafe { context_ip(&*ucontext) }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn context_ip(context: &libc::ucontext_t) -> Option<u64> {
+  Some(context.uc_mcontext.gregs[libc::REG_RIP as usize] as u64)
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+unsafe fn context_ip(context: &libc::ucontext_t) -> Option<u64> {
+  Some(context.uc_mcontext.pc)
+}
+
+#[cfg(all(target_vendor = "apple", target_arch = "x86_64"))]
+unsafe fn context_ip(context: &libc::ucontext_t) -> Option<u64> {
+  Some(unsafe { (*context.uc_mcontext).__ss.__rip })
+}
+
+#[cfg(all(target_vendor = "apple", target_arch = "aarch64"))]
+unsafe fn context_ip(context: &libc::ucontext_t) -> Option<u64> {
+  Some(unsafe { (*context.uc_mcontext).__ss.__pc })
+}
+
+#[cfg(all(
+  unix,
+  not(any(
+    all(target_os = "linux", target_arch = "x86_64"),
+    all(target_os = "linux", target_arch = "aarch64"),
+    all(target_vendor = "apple", target_arch = "x86_64"),
+    all(target_vendor = "apple", target_arch = "aarch64"),
+  ))
+))]
+unsafe fn context_ip(_context: &libc::ucontext_t) -> Option<u64> {
+  None
+}
+
+/// Walks the current stack, returning each instruction pointer and whether
+/// it's exact. Returns `None` if `only_if_no_debuginfo` is set and the main
+/// executable has debug info.
+fn walk_stack(
+  only_if_no_debuginfo: bool,
+  start_ip: Option<u64>,
+) -> Option<Vec<(u64, bool)>> {
+  let mut ips = Vec::new();
+  let mut walked = 0;
+  let mut found_start = false;
+  let mut have_debuginfo = false;
+  backtrace::trace(|frame| {
+    // The first frame is always in this crate, so it resolving means the
+    // main executable has symbols. Frames in other modules (e.g. libc's
+    // exported functions) may resolve regardless, so don't check those.
+    if only_if_no_debuginfo && walked == 0 {
+      backtrace::resolve_frame(frame, |f| {
+        if f.addr().is_some() {
+          have_debuginfo = true;
+        }
+      });
+      if have_debuginfo {
+        return false;
+      }
+    }
+    walked += 1;
+    let ip = frame.ip() as usize as u64;
+    if let Some(start_ip) = start_ip
+      && !found_start
+      && ip == start_ip
+    {
+      // Drop the frames of the signal handler (or whatever else) that ran
+      // on top of the starting frame.
+      found_start = true;
+      ips.clear();
+      ips.push((ip, true));
+      return true;
+    }
+    ips.push((ip, false));
+    true
+  });
+
+  if only_if_no_debuginfo && have_debuginfo {
+    return None;
+  }
+  if let Some(start_ip) = start_ip
+    && !found_start
+  {
+    // The unwinder didn't go through the starting frame, so keep everything
+    // it found rather than losing the trace.
+    ips.insert(0, (start_ip, true));
+  }
+  Some(ips)
+}
+
+/// Builds the module table for frames captured on one or more stacks. It
+/// only holds the main executable and the modules frames are in.
+struct ModuleTable {
+  loaded: modules::LoadedModules,
+  /// Maps indices into `loaded` to indices into `modules`.
+  indices: Vec<Option<usize>>,
+  modules: Vec<Module>,
+}
+
+impl ModuleTable {
+  /// Loads the module table. `addrs` are lookup addresses of the frames that
+  /// will be added, which Windows needs to find their modules.
+  fn new(addrs: &[u64]) -> Self {
+    let loaded = modules::LoadedModules::new(addrs);
+    let mut indices = vec![None; loaded.len()];
+    indices[0] = Some(0);
+    let modules = vec![loaded.module(0).clone()];
+    Self {
+      loaded,
+      indices,
+      modules,
+    }
+  }
+
+  fn frame(&mut self, ip: u64, exact: bool) -> Frame {
+    let addr = Frame::lookup_addr(ip, exact);
+    let stable = self.loaded.find(addr).map(|index| {
+      let module = *self.indices[index].get_or_insert_with(|| {
+        self.modules.push(self.loaded.module(index).clone());
+        self.modules.len() - 1
+      });
+      StableAddr {
+        module,
+        addr: ip - self.loaded.module(index).base,
+      }
+    });
+    Frame { ip, stable, exact }
+  }
+}
+
+fn stable_stacktrace_maybe(
+  only_if_no_debuginfo: bool,
+  start_ip: Option<u64>,
+) -> Option<CapturedStackTrace> {
+  let ips = walk_stack(only_if_no_debuginfo, start_ip)?;
+  let addrs = ips
+    .iter()
+    .map(|&(ip, exact)| Frame::lookup_addr(ip, exact))
+    .collect::<Vec<_>>();
+  let mut table = ModuleTable::new(&addrs);
+  let frames = ips
+    .into_iter()
+    .map(|(ip, exact)| table.frame(ip, exact))
+    .collect();
+  Some(CapturedStackTrace {
+    modules: table.modules,
+    frames,
+  })
+}
+
+/// Captures the current stack trace, recording the module each frame
+/// belongs to.
+pub fn stable_stacktrace() -> CapturedStackTrace {
+  stable_stacktrace_maybe(false, None).unwrap()
+}
+
+/// Like [`stable_stacktrace`], but returns `None` if the binary has debug
+/// info (in which case the stack trace can be symbolicated directly).
+pub fn stable_stacktrace_if_no_debuginfo() -> Option<CapturedStackTrace> {
+  stable_stacktrace_maybe(true, None)
+}
+
+/// Like [`stable_stacktrace`], but starts at the frame executing `ip`, which
+/// becomes an [exact](Frame::exact) frame 0. Frames above it (e.g. a signal
+/// handler and the kernel's trampoline) are dropped.
+///
+/// Meant for crash handlers, with `ip` being the faulting instruction (see
+/// [`ucontext_ip`]). If the unwinder never reaches `ip`, the frame for it is
+/// put in front of the full trace instead.
+pub fn stable_stacktrace_from_ip(ip: u64) -> CapturedStackTrace {
+  stable_stacktrace_maybe(false, Some(ip)).unwrap()
+}
+
+/// Returns the build ID of the main executable, to include in an encoded
+/// stack trace (see `StackTrace::with_build_id`).
+pub fn main_module_build_id() -> Option<BuildId> {
+  modules::LoadedModules::new(&[]).module(0).build_id.clone()
+}
+
+pub fn stable_stacktrace_addrs_if_no_debuginfo() -> Option<Vec<Option<u64>>> {
+  stable_stacktrace_if_no_debuginfo().map(|trace| trace.main_module_addrs())
+}
+
+pub fn stable_stacktrace_addrs() -> Vec<Option<u64>> {
+  stable_stacktrace().main_module_addrs()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[inline(never)]
+  fn capture() -> CapturedStackTrace {
+    stable_stacktrace()
+  }
+
+  #[test]
+  fn captures_main_module_and_libraries() {
+    let trace = capture();
+    assert!(!trace.frames.is_empty());
+    assert_eq!(
+      std::path::Path::new(&trace.modules[0].path),
+      std::env::current_exe().unwrap()
+    );
+
+    // The frame for `capture` should be in the test binary.
+    let ip = capture as *const () as usize as u64;
+    let in_main = trace.frames.iter().any(|frame| {
+      frame.stable.is_some_and(|stable| stable.module == 0)
+        && frame.ip > ip
+        && frame.ip - ip < 0x1000
+    });
+    assert!(in_main, "{trace:#?}");
+
+    // The thread entry point lives in libc, which is a separate module.
+    if cfg!(target_os = "linux") {
+      assert!(trace.modules.len() > 1, "{trace:#?}");
+      assert!(trace.modules.iter().all(|m| m.build_id.is_some()));
+    }
+  }
+
+  #[test]
+  fn capture_from_ip() {
+    let full = capture();
+    // Start at a caller of this test, whose frames are the same in both
+    // traces.
+    let start = full.frames.len() - 2;
+    let trace = stable_stacktrace_from_ip(full.frames[start].ip);
+    assert_eq!(trace.frames.len(), 2, "{trace:#?}");
+    assert!(trace.frames[0].exact);
+    assert_eq!(trace.frames[0].ip, full.frames[start].ip);
+    assert_eq!(
+      trace.frames[0].stable.map(|stable| stable.addr),
+      full.frames[start].stable.map(|stable| stable.addr)
+    );
+    assert_eq!(trace.frames[1].ip, full.frames[start + 1].ip);
+    assert!(!trace.frames[1].exact);
+
+    // An unknown instruction pointer is put in front of the whole trace.
+    let trace = stable_stacktrace_from_ip(1);
+    assert_eq!(
+      trace.frames[0],
+      Frame {
+        ip: 1,
+        stable: None,
+        exact: true
+      }
+    );
+    assert!(trace.frames.len() > 2);
+  }
+}
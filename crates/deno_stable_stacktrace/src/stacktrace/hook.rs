@@ -0,0 +1,569 @@
+//! Hooks that print an encoded stack trace to stderr when the process panics
+//! or crashes, between [`TRACE_BANNER_BEGIN`] and [`TRACE_BANNER_END`] lines.
+
+use std::io::Write;
+
+use crate::encode::{
+  StackTrace, TRACE_BANNER_BEGIN, TRACE_BANNER_END, Version,
+};
+
+/// The placeholder in [`HookOptions::url_template`] that's replaced with the
+/// encoded trace.
+const TRACE_PLACEHOLDER: &str = "{trace}";
+
+#[derive(Debug, Clone, Default)]
+pub struct HookOptions {
+  /// A link to a symbolication service, printed after the trace with
+  /// `{trace}` replaced by it, e.g. `https://example.com/trace/{trace}`.
+  pub url_template: Option<String>,
+  /// Don't print a trace on panic if the binary has debug info, since the
+  /// default panic output is readable then. Fatal signals always print one.
+  pub only_if_no_debuginfo: bool,
+  /// How many bytes of stack memory to include in traces, along with the
+  /// registers needed to unwind it, so the symbolicator can unwind the stack
+  /// again where the client's unwinder gave up (see [`crate::StackMemory`]).
+  /// 0 (the default) includes none. Each byte adds about 1.3 characters to
+  /// the encoded trace.
+  pub stack_memory_len: usize,
+}
+
+/// Splits a URL template around its placeholder. Templates without one get
+/// the trace appended.
+fn split_url_template(template: &str) -> (&str, &str) {
+  template
+    .split_once(TRACE_PLACEHOLDER)
+    .unwrap_or((template, ""))
+}
+
+fn write_trace(
+  out: &mut impl Write,
+  encoded: &str,
+  url_template: Option<&str>,
+) -> std::io::Result<()> {
+  writeln!(out, "{TRACE_BANNER_BEGIN}")?;
+  writeln!(out, "{encoded}")?;
+  writeln!(out, "{TRACE_BANNER_END}")?;
+  if let Some(template) = url_template {
+    let (prefix, suffix) = split_url_template(template);
+    writeln!(out, "Symbolicate at: {prefix}{encoded}{suffix}")?;
+  }
+  Ok(())
+}
+
+/// Installs a panic hook that runs the previously installed hook (by default
+/// the one printing the panic message), then prints the encoded stack trace
+/// of the panic to stderr.
+pub fn install_panic_hook(version: Version, options: HookOptions) {
+  let previous = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    previous(info);
+    let captured = if options.only_if_no_debuginfo {
+      super::stable_stacktrace_if_no_debuginfo()
+    } else {
+      Some(super::stable_stacktrace())
+    };
+    let Some(captured) = captured else {
+      return;
+    };
+    let mut stack_trace = StackTrace::from_captured(&captured, version.clone());
+    if options.stack_memory_len > 0
+      && let Some(stack_memory) =
+        super::capture_stack_memory(options.stack_memory_len)
+    {
+      stack_trace = stack_trace.with_stack_memory(stack_memory);
+    }
+    let encoded = stack_trace.encode_base64url();
+    // Nothing sensible to do if stderr is gone.
+    let _ = write_trace(
+      &mut std::io::stderr().lock(),
+      &encoded,
+      options.url_template.as_deref(),
+    );
+  }));
+}
+
+#[cfg(unix)]
+pub use fatal_signal::install_fatal_signal_handler;
+
+#[cfg(unix)]
+mod fatal_signal {
+  use std::cell::UnsafeCell;
+  use std::sync::OnceLock;
+  use std::sync::atomic::{AtomicBool, Ordering};
+
+  use libc::c_int;
+
+  use super::{HookOptions, split_url_template};
+  use crate::encode::{
+    SignalInfo, TRACE_BANNER_BEGIN, TRACE_BANNER_END, Version,
+    base64url_encode_into, base64url_encoded_len,
+  };
+  use crate::{
+    Frame, MAX_STACK_REGISTERS, SignalSafeEncoder, StackMemory,
+    capture_stack_memory_from_ucontext, signal_safe_stacktrace,
+    signal_safe_stacktrace_from_ip, ucontext_ip,
+  };
+
+  const FATAL_SIGNALS: [(c_int, &str); 5] = [
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGABRT, "SIGABRT"),
+  ];
+  const MAX_FRAMES: usize = 128;
+  const MAX_ENCODED_LEN: usize = 4096;
+
+  struct Handler {
+    encoder: SignalSafeEncoder,
+    url_template: Option<(String, String)>,
+    /// The actions that were installed before ours, indexed like
+    /// `FATAL_SIGNALS`.
+    previous: [libc::sigaction; FATAL_SIGNALS.len()],
+    /// Set if [`HookOptions::stack_memory_len`] isn't 0.
+    stack_memory: Option<UnsafeCell<StackMemoryBuffers>>,
+  }
+
+  // SAFETY: `sigaction` is plain data; the handler function pointer in it is
+  // only ever passed back to `sigaction`. `stack_memory` is only accessed by
+  // the thread that set `HANDLING`.
+  unsafe impl Send for Handler {}
+  unsafe impl Sync for Handler {}
+
+  /// Buffers for traces with stack memory, which don't fit the static ones.
+  /// They're allocated up front, since the handler can't allocate.
+  struct StackMemoryBuffers {
+    stack_memory: StackMemory,
+    max_len: usize,
+    encoded: Vec<u8>,
+    base64: Vec<u8>,
+  }
+
+  impl StackMemoryBuffers {
+    fn new(max_len: usize) -> Self {
+      // Registers take at most 2 + 10 bytes each, and a few more bytes hold
+      // the section's other fields.
+      let encoded_len =
+        MAX_ENCODED_LEN + MAX_STACK_REGISTERS * 12 + max_len + 64;
+      Self {
+        stack_memory: StackMemory {
+          ip: 0,
+          registers: Vec::with_capacity(MAX_STACK_REGISTERS),
+          start: 0,
+          memory: Vec::with_capacity(max_len),
+        },
+        max_len,
+        encoded: vec![0; encoded_len],
+        base64: vec![0; base64url_encoded_len(encoded_len)],
+      }
+    }
+  }
+
+  struct Buffers {
+    frames: [Frame; MAX_FRAMES],
+    encoded: [u8; MAX_ENCODED_LEN],
+    base64: [u8; MAX_ENCODED_LEN / 3 * 4 + 4],
+  }
+
+  struct SharedBuffers(UnsafeCell<Buffers>);
+  // SAFETY: Only accessed by the thread that set `HANDLING`.
+  unsafe impl Sync for SharedBuffers {}
+
+  static HANDLER: OnceLock<Handler> = OnceLock::new();
+  /// Set by the first thread to crash, so concurrent crashes don't share the
+  /// buffers (or interleave their output).
+  static HANDLING: AtomicBool = AtomicBool::new(false);
+  static BUFFERS: SharedBuffers = SharedBuffers(UnsafeCell::new(Buffers {
+    frames: [Frame {
+      ip: 0,
+      stable: None,
+      exact: false,
+    }; MAX_FRAMES],
+    encoded: [0; MAX_ENCODED_LEN],
+    base64: [0; MAX_ENCODED_LEN / 3 * 4 + 4],
+  }));
+
+  /// Installs handlers for fatal signals (`SIGSEGV`, `SIGBUS`, `SIGILL`,
+  /// `SIGFPE` and `SIGABRT`) that print the encoded stack trace of the
+  /// crashing thread to stderr, including the signal, then pass the signal
+  /// on to the previously installed handler. The trace starts at the
+  /// interrupted instruction, without the handler's own frames. If it
+  /// doesn't fit the handler's fixed buffers, the stack memory, then the
+  /// module table, then the outermost frames are left out.
+  ///
+  /// The handlers don't allocate, so this also caches the module table (see
+  /// [`crate::cache_modules`]). Only the first call installs handlers.
+  ///
+  /// Handlers run on the thread's alternate signal stack if it has one, which
+  /// Rust sets up for its own stack overflow detection. With
+  /// [`HookOptions::stack_memory_len`] set, the stack memory is copied from
+  /// the interrupted frame.
+  pub fn install_fatal_signal_handler(version: Version, options: HookOptions) {
+    let mut installed = false;
+    HANDLER.get_or_init(|| {
+      installed = true;
+      // SAFETY: Querying the current actions doesn't change anything.
+      let previous = FATAL_SIGNALS.map(|(signal, _)| unsafe {
+        let mut action = std::mem::zeroed();
+        libc::sigaction(signal, std::ptr::null(), &mut action);
+        action
+      });
+      Handler {
+        encoder: SignalSafeEncoder::new(version),
+        url_template: options.url_template.as_deref().map(|template| {
+          let (prefix, suffix) = split_url_template(template);
+          (prefix.to_owned(), suffix.to_owned())
+        }),
+        previous,
+        stack_memory: (options.stack_memory_len > 0).then(|| {
+          UnsafeCell::new(StackMemoryBuffers::new(options.stack_memory_len))
+        }),
+      }
+    });
+    if !installed {
+      return;
+    }
+    for (signal, _) in FATAL_SIGNALS {
+      // SAFETY: `handle_signal` only does async-signal-safe work, except for
+      // the unwinder's lookup of unwind info (see `signal_safe`).
+      unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_signal
+          as extern "C" fn(c_int, *mut libc::siginfo_t, *mut libc::c_void)
+          as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+      }
+    }
+  }
+
+  fn write_all(mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+      // SAFETY: `write` is async-signal-safe.
+      let written = unsafe {
+        libc::write(libc::STDERR_FILENO, bytes.as_ptr().cast(), bytes.len())
+      };
+      if written < 0 {
+        if std::io::Error::last_os_error().kind()
+          == std::io::ErrorKind::Interrupted
+        {
+          continue;
+        }
+        return;
+      }
+      bytes = &bytes[written as usize..];
+    }
+  }
+
+  fn print_trace(
+    handler: &Handler,
+    name: &str,
+    signal: &SignalInfo,
+    context: *mut libc::c_void,
+  ) {
+    write_all(b"\nReceived fatal signal ");
+    write_all(name.as_bytes());
+    write_all(b"\n");
+
+    // SAFETY: Only the thread that set `HANDLING` gets here.
+    let buffers = unsafe { &mut *BUFFERS.0.get() };
+    // SAFETY: The kernel passes a valid `ucontext_t` with `SA_SIGINFO`.
+    let len = match unsafe { ucontext_ip(context) } {
+      Some(ip) => signal_safe_stacktrace_from_ip(ip, &mut buffers.frames),
+      None => signal_safe_stacktrace(&mut buffers.frames),
+    };
+    let (stack_memory, encoded, base64) = match &handler.stack_memory {
+      Some(stack_buffers) => {
+        // SAFETY: Like `BUFFERS`.
+        let stack_buffers = unsafe { &mut *stack_buffers.get() };
+        // SAFETY: As above. It doesn't allocate, since the buffers have the
+        // capacity.
+        let captured = unsafe {
+          capture_stack_memory_from_ucontext(
+            context,
+            stack_buffers.max_len,
+            &mut stack_buffers.stack_memory,
+          )
+        };
+        (
+          captured.then_some(&stack_buffers.stack_memory),
+          &mut stack_buffers.encoded[..],
+          &mut stack_buffers.base64[..],
+        )
+      }
+      None => (None, &mut buffers.encoded[..], &mut buffers.base64[..]),
+    };
+    let Some(len) = encode_fitting(
+      &handler.encoder,
+      &buffers.frames[..len],
+      signal,
+      stack_memory,
+      encoded,
+    ) else {
+      return;
+    };
+    let Some(len) = base64url_encode_into(&encoded[..len], base64) else {
+      return;
+    };
+    let encoded = &base64[..len];
+
+    write_all(TRACE_BANNER_BEGIN.as_bytes());
+    write_all(b"\n");
+    write_all(encoded);
+    write_all(b"\n");
+    write_all(TRACE_BANNER_END.as_bytes());
+    write_all(b"\n");
+    if let Some((prefix, suffix)) = &handler.url_template {
+      write_all(b"Symbolicate at: ");
+      write_all(prefix.as_bytes());
+      write_all(encoded);
+      write_all(suffix.as_bytes());
+      write_all(b"\n");
+    }
+  }
+
+  /// Encodes the trace into `buf`, leaving out the stack memory, then the
+  /// module table, then more and more of the outermost frames until it fits.
+  /// A trace without frames always fits the handler's buffers.
+  pub(super) fn encode_fitting(
+    encoder: &SignalSafeEncoder,
+    mut frames: &[Frame],
+    signal: &SignalInfo,
+    stack_memory: Option<&StackMemory>,
+    buf: &mut [u8],
+  ) -> Option<usize> {
+    if let Some(len) = encoder.encode_with_stack_memory_into(
+      frames,
+      Some(signal),
+      stack_memory,
+      buf,
+    ) {
+      return Some(len);
+    }
+    if stack_memory.is_some()
+      && let Some(len) = encoder.encode_into(frames, Some(signal), buf)
+    {
+      return Some(len);
+    }
+    loop {
+      if let Some(len) =
+        encoder.encode_without_modules_into(frames, Some(signal), buf)
+      {
+        return Some(len);
+      }
+      if frames.is_empty() {
+        return None;
+      }
+      frames = &frames[..frames.len() / 2];
+    }
+  }
+
+  extern "C" fn handle_signal(
+    signal: c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+  ) {
+    let Some(handler) = HANDLER.get() else {
+      return;
+    };
+    let Some(index) = FATAL_SIGNALS.iter().position(|&(s, _)| s == signal)
+    else {
+      return;
+    };
+    // SAFETY: The kernel passes a valid `siginfo_t` with `SA_SIGINFO`.
+    let info = unsafe { &*info };
+    // Positive codes mean the kernel raised the signal for a fault, while
+    // the rest were sent by a process (e.g. with `abort` or `kill`).
+    let from_fault = info.si_code > 0;
+    if !HANDLING.swap(true, Ordering::SeqCst) {
+      let fault_addr = matches!(
+        signal,
+        libc::SIGSEGV | libc::SIGBUS | libc::SIGILL | libc::SIGFPE
+      ) && from_fault;
+      let signal_info = SignalInfo {
+        signal: signal as u32,
+        code: info.si_code,
+        // SAFETY: `si_addr` is set for faults of these signals.
+        fault_addr: fault_addr
+          .then(|| unsafe { info.si_addr() } as usize as u64),
+      };
+      print_trace(handler, FATAL_SIGNALS[index].1, &signal_info, context);
+    }
+
+    // Restore the previous action and let it handle the signal. A fault
+    // happens again when the handler returns, but a sent signal has to be
+    // raised again. It stays blocked until this handler returns.
+    // SAFETY: `sigaction` and `raise` are async-signal-safe.
+    unsafe {
+      libc::sigaction(signal, &handler.previous[index], std::ptr::null_mut());
+      if !from_fault {
+        libc::raise(signal);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::process::Command;
+
+  use super::*;
+  use crate::encode::{CanaryHash, FrameAddr, find_banner_trace};
+
+  /// Set in the child process the tests spawn to crash in.
+  const CRASH_ENV: &str = "DENO_STABLE_STACKTRACE_TEST_CRASH";
+
+  fn version() -> Version {
+    Version {
+      major: 1,
+      minor: 2,
+      patch: 3,
+      canary_hash: CanaryHash::none(),
+      dev_build: false,
+    }
+  }
+
+  fn options() -> HookOptions {
+    HookOptions {
+      url_template: Some("https://example.com/{trace}?v=1".into()),
+      only_if_no_debuginfo: false,
+      stack_memory_len: 4096,
+    }
+  }
+
+  /// Runs `test` in a child process with [`CRASH_ENV`] set, and returns its
+  /// stderr and exit status.
+  fn run_crashing(test: &str) -> (String, std::process::ExitStatus) {
+    let output = Command::new(std::env::current_exe().unwrap())
+      .args(["--exact", test, "--nocapture", "--test-threads=1"])
+      .env(CRASH_ENV, "1")
+      .output()
+      .unwrap();
+    (
+      String::from_utf8_lossy(&output.stderr).into_owned(),
+      output.status,
+    )
+  }
+
+  fn check_output(stderr: &str) -> StackTrace {
+    let encoded = find_banner_trace(stderr).expect(stderr);
+    assert!(
+      stderr.contains(&format!("https://example.com/{encoded}?v=1\n")),
+      "{stderr}"
+    );
+    let stack_trace = StackTrace::decode_base64url(encoded).unwrap();
+    assert_eq!(stack_trace.header().version(), &version());
+    assert!(
+      stack_trace
+        .frames()
+        .iter()
+        .any(|frame| matches!(frame, FrameAddr::Stable { .. })),
+      "{stack_trace:#?}"
+    );
+    if cfg!(any(target_os = "linux", target_vendor = "apple"))
+      && cfg!(any(target_arch = "x86_64", target_arch = "aarch64"))
+    {
+      let stack_memory = stack_trace.stack_memory().expect(stderr);
+      assert!(!stack_memory.memory.is_empty());
+    }
+    stack_trace
+  }
+
+  #[test]
+  fn url_template() {
+    let mut out = Vec::new();
+    write_trace(&mut out, "AAECAw", Some("https://example.com/t/")).unwrap();
+    assert_eq!(
+      String::from_utf8(out).unwrap(),
+      format!(
+        "{TRACE_BANNER_BEGIN}\nAAECAw\n{TRACE_BANNER_END}\n\
+         Symbolicate at: https://example.com/t/AAECAw\n"
+      )
+    );
+  }
+
+  #[test]
+  fn panic_hook_prints_trace() {
+    if std::env::var_os(CRASH_ENV).is_some() {
+      install_panic_hook(version(), options());
+      panic!("test panic");
+    }
+    let (stderr, status) =
+      run_crashing("stacktrace::hook::tests::panic_hook_prints_trace");
+    assert!(!status.success());
+    // The default hook still ran.
+    assert!(stderr.contains("test panic"), "{stderr}");
+    check_output(&stderr);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn encode_fitting_drops_parts() {
+    use crate::encode::SignalInfo;
+    use crate::{Frame, SignalSafeEncoder, StableAddr};
+
+    let encoder = SignalSafeEncoder::new(version());
+    let signal = SignalInfo {
+      signal: libc::SIGSEGV as u32,
+      code: 1,
+      fault_addr: Some(0x10),
+    };
+    let frames = (0..128)
+      .map(|i| Frame {
+        ip: 0x1000 + i,
+        stable: Some(StableAddr {
+          module: 0,
+          addr: 0x1000 + i,
+        }),
+        exact: false,
+      })
+      .collect::<Vec<_>>();
+    let mut buf = [0; 4096];
+    let encode = |buf: &mut [u8]| {
+      let len =
+        fatal_signal::encode_fitting(&encoder, &frames, &signal, None, buf)
+          .unwrap();
+      StackTrace::decode(&buf[..len]).unwrap()
+    };
+
+    let full = encode(&mut buf);
+    assert_eq!(full.frames().len(), frames.len());
+    assert!(!full.modules().is_empty());
+
+    let bare = encoder
+      .encode_without_modules_into(&[], Some(&signal), &mut buf)
+      .unwrap();
+    let truncated = encode(&mut buf[..bare + 16]);
+    assert!(truncated.modules().is_empty());
+    assert!(
+      !truncated.frames().is_empty() && truncated.frames().len() < frames.len()
+    );
+    assert_eq!(
+      truncated.frames(),
+      &full.frames()[..truncated.frames().len()]
+    );
+    assert_eq!(truncated.signal(), Some(&signal));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn fatal_signal_prints_trace() {
+    use std::os::unix::process::ExitStatusExt;
+
+    if std::env::var_os(CRASH_ENV).is_some() {
+      install_fatal_signal_handler(version(), options());
+      std::process::abort();
+    }
+    let (stderr, status) =
+      run_crashing("stacktrace::hook::tests::fatal_signal_prints_trace");
+    // The previous (default) action still terminated the process.
+    assert_eq!(status.signal(), Some(libc::SIGABRT), "{stderr}");
+    assert!(stderr.contains("Received fatal signal SIGABRT"), "{stderr}");
+    let stack_trace = check_output(&stderr);
+    assert_eq!(
+      stack_trace.signal().map(|signal| signal.signal),
+      Some(libc::SIGABRT as u32)
+    );
+  }
+}
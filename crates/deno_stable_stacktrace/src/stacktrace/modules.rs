@@ -0,0 +1,351 @@
+use std::ops::Range;
+
+use crate::Module;
+
+pub(crate) struct LoadedModule {
+  pub module: Module,
+  /// The address ranges the module's code is mapped at.
+  pub ranges: Vec<Range<u64>>,
+}
+
+/// A snapshot of the modules loaded into the process. The main executable is
+/// always at index 0.
+pub(crate) struct LoadedModules(Vec<LoadedModule>);
+
+impl LoadedModules {
+  /// Enumerates the loaded modules. `addrs` are the addresses that will be
+  /// looked up, for platforms where modules are discovered by address rather
+  /// than enumerated up front.
+  pub fn new(addrs: &[u64]) -> Self {
+    let mut modules = loaded_modules(addrs);
+    if modules.is_empty() {
+      // Keep the invariant that the main executable is at index 0, even if we
+      // couldn't find out where it's loaded.
+      modules.push(LoadedModule {
+        module: Module {
+          path: current_exe(),
+          base: 0,
+          build_id: None,
+        },
+        ranges: Vec::new(),
+      });
+    }
+    Self(modules)
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn module(&self, index: usize) -> &Module {
+    &self.0[index].module
+  }
+
+  /// Returns the index of the module containing `addr`.
+  pub fn find(&self, addr: u64) -> Option<usize> {
+    self
+      .0
+      .iter()
+      .position(|m| m.ranges.iter().any(|range| range.contains(&addr)))
+  }
+}
+
+fn current_exe() -> String {
+  std::env::current_exe()
+    .map(|path| path.to_string_lossy().into_owned())
+    .unwrap_or_default()
+}
+
+#[cfg(target_vendor = "apple")]
+fn loaded_modules(_addrs: &[u64]) -> Vec<LoadedModule> {
+  #![allow(deprecated)]
+  use std::ffi::CStr;
+
+  use crate::BuildId;
+
+  static TEXT: &CStr = c"__TEXT";
+  const LC_UUID: u32 = 0x1b;
+
+  let mut modules = Vec::new();
+  let image_count = unsafe { libc::_dyld_image_count() };
+  // Image 0 is the main executable.
+  for image in 0..image_count {
+    let header = unsafe { libc::_dyld_get_image_header(image) };
+    if header.is_null() {
+      continue;
+    }
+    let slide = unsafe { libc::_dyld_get_image_vmaddr_slide(image) };
+    let name = unsafe { libc::_dyld_get_image_name(image) };
+    let path = if name.is_null() {
+      String::new()
+    } else {
+      unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned()
+    };
+
+    let mut text = None;
+    let mut uuid = None;
+    let mut offset = 0;
+    let start =
+      unsafe { header.cast::<u8>().add(size_of::<libc::mach_header_64>()) };
+    for _ in 0..unsafe { (*header).ncmds } {
+      let hdr = unsafe { start.add(offset).cast::<libc::load_command>() };
+      let cmd = unsafe { *hdr };
+      if cmd.cmd == libc::LC_SEGMENT_64 {
+        let data = hdr.cast::<libc::segment_command_64>();
+        let segname = unsafe { (*data).segname };
+        let segname =
+          unsafe { std::mem::transmute::<[i8; 16], [u8; 16]>(segname) };
+
+        if CStr::from_bytes_until_nul(&segname).ok() == Some(TEXT) {
+          text = Some(unsafe { ((*data).vmaddr, (*data).vmsize) });
+        }
+      } else if cmd.cmd == LC_UUID {
+        // struct uuid_command { uint32_t cmd; uint32_t cmdsize; uint8_t uuid[16]; }
+        let bytes = unsafe { hdr.cast::<u8>().add(8).cast::<[u8; 16]>() };
+        uuid = Some(unsafe { bytes.read_unaligned() });
+      }
+
+      offset += cmd.cmdsize as usize;
+    }
+
+    let Some((vmaddr, vmsize)) = text else {
+      continue;
+    };
+    let base = vmaddr.wrapping_add_signed(slide as i64);
+    modules.push(LoadedModule {
+      module: Module {
+        path: if image == 0 && path.is_empty() {
+          current_exe()
+        } else {
+          path
+        },
+        base,
+        build_id: uuid.map(BuildId::MachO),
+      },
+      ranges: vec![base..base + vmsize],
+    });
+  }
+  modules
+}
+
+#[cfg(all(not(target_vendor = "apple"), unix))]
+fn loaded_modules(_addrs: &[u64]) -> Vec<LoadedModule> {
+  use std::ffi::{CStr, c_int, c_void};
+
+  use crate::BuildId;
+
+  const NT_GNU_BUILD_ID: u32 = 3;
+
+  /// Finds the `NT_GNU_BUILD_ID` note in a loaded `PT_NOTE` segment.
+  unsafe fn gnu_build_id(
+    notes: *const u8,
+    len: usize,
+    align: usize,
+  ) -> Option<Vec<u8>> {
+    let align_up = |v: usize| (v + align - 1) & !(align - 1);
+    let mut offset = 0;
+    while offset + 12 <= len {
+      let header = unsafe { notes.add(offset).cast::<[u32; 3]>() };
+      let [namesz, descsz, ty] = unsafe { header.read_unaligned() };
+      let name = offset + 12;
+      let desc = name + align_up(namesz as usize);
+      let next = desc + align_up(descsz as usize);
+      if next > len {
+        return None;
+      }
+      if ty == NT_GNU_BUILD_ID
+        && unsafe {
+          std::slice::from_raw_parts(notes.add(name), namesz as usize)
+        } == b"GNU\0"
+      {
+        let desc = unsafe {
+          std::slice::from_raw_parts(notes.add(desc), descsz as usize)
+        };
+        return Some(desc.to_vec());
+      }
+      offset = next;
+    }
+    None
+  }
+
+  unsafe extern "C" fn callback(
+    info: *mut libc::dl_phdr_info,
+    _size: usize,
+    data: *mut c_void,
+  ) -> c_int {
+    let modules = unsafe { &mut *data.cast::<Vec<LoadedModule>>() };
+    let info = unsafe { &*info };
+    let dlpi_addr = info.dlpi_addr;
+    let phdrs = if info.dlpi_phdr.is_null() {
+      &[][..]
+    } else {
+      unsafe {
+        std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize)
+      }
+    };
+
+    let mut ranges = Vec::new();
+    let mut build_id = None;
+    for phdr in phdrs {
+      let segment_start = dlpi_addr.wrapping_add(phdr.p_vaddr);
+      if phdr.p_type == libc::PT_LOAD {
+        ranges.push(segment_start..segment_start + phdr.p_memsz);
+      } else if phdr.p_type == libc::PT_NOTE && build_id.is_none() {
+        let align = if phdr.p_align == 8 { 8 } else { 4 };
+        build_id = unsafe {
+          gnu_build_id(
+            segment_start as usize as *const u8,
+            phdr.p_memsz as usize,
+            align,
+          )
+        };
+      }
+    }
+
+    // The first object is the main executable, which has an empty name.
+    let path = if modules.is_empty() {
+      current_exe()
+    } else if info.dlpi_name.is_null() {
+      String::new()
+    } else {
+      unsafe { CStr::from_ptr(info.dlpi_name) }
+        .to_string_lossy()
+        .into_owned()
+    };
+    modules.push(LoadedModule {
+      module: Module {
+        path,
+        base: dlpi_addr,
+        build_id: build_id.map(BuildId::Gnu),
+      },
+      ranges,
+    });
+    0
+  }
+
+  let mut modules = Vec::<LoadedModule>::new();
+  unsafe { libc::dl_iterate_phdr(Some(callback), (&raw mut modules).cast()) };
+  modules
+}
+
+#[cfg(windows)]
+fn loaded_modules(addrs: &[u64]) -> Vec<LoadedModule> {
+  use std::os::windows::ffi::OsStringExt;
+
+  use windows_sys::Win32::System::LibraryLoader::{
+    GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+    GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT, GetModuleFileNameW,
+    GetModuleHandleExW, GetModuleHandleW,
+  };
+
+  use crate::BuildId;
+
+  const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+  const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+  unsafe fn read<T: Copy>(base: usize, offset: usize) -> T {
+    unsafe { ((base + offset) as *const T).read_unaligned() }
+  }
+
+  /// Reads the image size and CodeView signature out of the PE headers of a
+  /// loaded module.
+  unsafe fn pe_info(base: usize) -> Option<(u64, Option<BuildId>)> {
+    unsafe {
+      if read::<[u8; 2]>(base, 0) != *b"MZ" {
+        return None;
+      }
+      let nt = base + read::<u32>(base, 0x3c) as usize;
+      if read::<[u8; 4]>(nt, 0) != *b"PE\0\0" {
+        return None;
+      }
+      let optional = nt + 24;
+      let data_directories = match read::<u16>(optional, 0) {
+        0x10b => optional + 96,
+        0x20b => optional + 112,
+        _ => return None,
+      };
+      let size_of_image = read::<u32>(optional, 56) as u64;
+      // NumberOfRvaAndSizes directly precedes the data directories.
+      let num_directories = read::<u32>(data_directories - 4, 0) as usize;
+      if num_directories <= IMAGE_DIRECTORY_ENTRY_DEBUG {
+        return Some((size_of_image, None));
+      }
+      let debug_dir = data_directories + IMAGE_DIRECTORY_ENTRY_DEBUG * 8;
+      let rva = read::<u32>(debug_dir, 0) as usize;
+      let size = read::<u32>(debug_dir, 4) as usize;
+
+      let mut build_id = None;
+      // Each IMAGE_DEBUG_DIRECTORY is 28 bytes.
+      for entry in (0..size / 28).map(|i| base + rva + i * 28) {
+        if read::<u32>(entry, 12) != IMAGE_DEBUG_TYPE_CODEVIEW {
+          continue;
+        }
+        let data = base + read::<u32>(entry, 20) as usize;
+        if read::<[u8; 4]>(data, 0) == *b"RSDS" {
+          build_id = Some(BuildId::Pdb {
+            guid: read::<[u8; 16]>(data, 4),
+            age: read::<u32>(data, 20),
+          });
+          break;
+        }
+      }
+      Some((size_of_image, build_id))
+    }
+  }
+
+  fn module_info(handle: *mut std::ffi::c_void) -> Option<LoadedModule> {
+    if handle.is_null() {
+      return None;
+    }
+    let base = handle as usize;
+    let mut name_buf = [0u16; 512];
+    let len = unsafe {
+      GetModuleFileNameW(handle, name_buf.as_mut_ptr(), name_buf.len() as u32)
+    };
+    let path = std::ffi::OsString::from_wide(&name_buf[..len as usize])
+      .to_string_lossy()
+      .into_owned();
+    let (size, build_id) = unsafe { pe_info(base) }?;
+    Some(LoadedModule {
+      module: Module {
+        path,
+        base: base as u64,
+        build_id,
+      },
+      ranges: vec![base as u64..base as u64 + size],
+    })
+  }
+
+  let mut modules = Vec::new();
+  let Some(main) = module_info(unsafe { GetModuleHandleW(std::ptr::null()) })
+  else {
+    return modules;
+  };
+  modules.push(main);
+
+  for &addr in addrs {
+    if modules
+      .iter()
+      .any(|m| m.ranges.iter().any(|range| range.contains(&addr)))
+    {
+      continue;
+    }
+    let mut handle = std::ptr::null_mut();
+    let found = unsafe {
+      GetModuleHandleExW(
+        GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS
+          | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+        addr as usize as *const u16,
+        &mut handle,
+      )
+    };
+    if found != 0
+      && let Some(module) = module_info(handle)
+    {
+      modules.push(module);
+    }
+  }
+  modules
+}
@@ -0,0 +1,399 @@
+//! Stack trace capture for crash handlers, where allocating or taking locks
+//! can deadlock (e.g. if the crash happened inside of `malloc`).
+//!
+//! The module table is snapshotted by [`cache_modules`] ahead of time, and
+//! capturing only walks the stack into a caller-provided buffer.
+//!
+//! The walk itself isn't entirely lock-free, though. The system unwinder
+//! finds each frame's unwind info with `_Unwind_Find_FDE`, which on glibc goes
+//! through `dl_iterate_phdr` and takes the dynamic loader's lock, unless glibc
+//! 2.35+ and libgcc 12+ let it use the lock-free `_dl_find_object` instead. A
+//! crash on a thread that holds the lock (e.g. inside `dlopen`) can then
+//! deadlock the handler, and a crash while another thread holds it waits for
+//! that thread.
+
+use std::sync::OnceLock;
+
+use super::modules::LoadedModules;
+use super::{Frame, StableAddr};
+use crate::Module;
+
+static CACHED_MODULES: OnceLock<LoadedModules> = OnceLock::new();
+
+/// Snapshots the modules loaded into the process for
+/// [`signal_safe_stacktrace`]. Call this at startup, before installing a
+/// crash handler. Later calls are no-ops, so modules loaded afterwards (e.g.
+/// with `dlopen`) aren't known to the signal-safe capture.
+///
+/// On Windows, only the main executable is cached.
+pub fn cache_modules() {
+  CACHED_MODULES.get_or_init(|| LoadedModules::new(&[]));
+}
+
+/// Returns module `index` of the table cached by [`cache_modules`]. The main
+/// executable is always at index 0.
+pub fn cached_module(index: usize) -> Option<&'static Module> {
+  let modules = CACHED_MODULES.get()?;
+  (index < modules.len()).then(|| modules.module(index))
+}
+
+/// Captures the current stack into `frames` without allocating or resolving
+/// symbols, and returns the number of frames written. Frames past the end of
+/// `frames` are dropped. No locks are taken, except that on glibc before 2.35
+/// the unwinder looks up unwind info with `dl_iterate_phdr`, which takes the
+/// dynamic loader's lock, so a crash inside `dlopen` can deadlock.
+///
+/// Stable addresses are computed from the modules cached by
+/// [`cache_modules`], and [`StableAddr::module`] indexes into that table (see
+/// [`cached_module`]). If the modules weren't cached, no frame has a stable
+/// address.
+pub fn signal_safe_stacktrace(frames: &mut [Frame]) -> usize {
+  capture(frames, None)
+}
+
+/// Like [`signal_safe_stacktrace`], but starts at the frame executing `ip`
+/// (e.g. the faulting instruction, see [`super::ucontext_ip`]), like
+/// [`super::stable_stacktrace_from_ip`] does.
+pub fn signal_safe_stacktrace_from_ip(ip: u64, frames: &mut [Frame]) -> usize {
+  capture(frames, Some(ip))
+}
+
+fn capture(frames: &mut [Frame], start_ip: Option<u64>) -> usize {
+  let modules = CACHED_MODULES.get();
+  let frame = |ip: u64, exact: bool| {
+    let addr = Frame::lookup_addr(ip, exact);
+    let stable = modules.and_then(|modules| {
+      let module = modules.find(addr)?;
+      Some(StableAddr {
+        module,
+        addr: ip - modules.module(module).base,
+      })
+    });
+    Frame { ip, stable, exact }
+  };
+
+  let mut len = 0;
+  let mut found_start = false;
+  // SAFETY: `trace` only serializes concurrent traces with a mutex, which
+  // mustn't be taken in a signal handler. Unwinding itself doesn't need it.
+  unsafe {
+    backtrace::trace_unsynchronized(|walked| {
+      let ip = walked.ip() as usize as u64;
+      if let Some(start_ip) = start_ip
+        && !found_start
+        && ip == start_ip
+      {
+        found_start = true;
+        len = 0;
+      }
+      let Some(slot) = frames.get_mut(len) else {
+        // Keep looking for the starting frame, which discards what's been
+        // captured so far.
+        return start_ip.is_some() && !found_start;
+      };
+      *slot = frame(ip, found_start && len == 0);
+      len += 1;
+      true
+    });
+  }
+
+  if let Some(start_ip) = start_ip
+    && !found_start
+    && !frames.is_empty()
+  {
+    // The unwinder didn't go through the starting frame, so put it in front
+    // of everything it found.
+    len = (len + 1).min(frames.len());
+    frames.copy_within(..len - 1, 1);
+    frames[0] = frame(start_ip, true);
+  }
+  len
+}
+
+#[cfg(feature = "encode")]
+pub use encoder::SignalSafeEncoder;
+
+#[cfg(feature = "encode")]
+mod encoder {
+  use super::cached_module;
+  use crate::encode::{
+    FrameAddr, Header, Section, SignalInfo, StackTrace, Version,
+    encode_sections_into,
+  };
+  use crate::{BuildId, Frame, StableAddr, StackMemory};
+
+  /// The most modules other than the main executable that are recorded in a
+  /// trace. Frames in further modules are encoded as absolute addresses.
+  const MAX_MODULES: usize = 16;
+
+  /// Encodes stack traces captured by [`super::signal_safe_stacktrace`],
+  /// without allocating.
+  pub struct SignalSafeEncoder {
+    header: Header,
+    build_id: Option<BuildId>,
+  }
+
+  impl SignalSafeEncoder {
+    /// Caches the module table (see [`super::cache_modules`]) and prepares
+    /// the trace header for the current platform. Call this at startup, not
+    /// in the signal handler.
+    pub fn new(version: Version) -> Self {
+      super::cache_modules();
+      let stack_trace = StackTrace::from_frames(
+        Vec::new(),
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        version,
+      );
+      Self {
+        header: stack_trace.header().clone(),
+        build_id: cached_module(0).and_then(|main| main.build_id.clone()),
+      }
+    }
+
+    /// Encodes `frames` as a trace of the latest version into `buf`, including the
+    /// modules the frames are in and the signal being handled, if any.
+    /// Returns the number of bytes written, or `None` if `buf` is too small.
+    pub fn encode_into(
+      &self,
+      frames: &[Frame],
+      signal: Option<&SignalInfo>,
+      buf: &mut [u8],
+    ) -> Option<usize> {
+      self.encode_with_stack_memory_into(frames, signal, None, buf)
+    }
+
+    /// Like [`SignalSafeEncoder::encode_into`], but also includes the stack
+    /// memory captured for the first frame, if any (see
+    /// [`crate::capture_stack_memory_from_ucontext`]).
+    pub fn encode_with_stack_memory_into(
+      &self,
+      frames: &[Frame],
+      signal: Option<&SignalInfo>,
+      stack_memory: Option<&StackMemory>,
+      buf: &mut [u8],
+    ) -> Option<usize> {
+      self.encode_sections_into(frames, signal, stack_memory, true, buf)
+    }
+
+    /// Like [`SignalSafeEncoder::encode_into`], but without the module
+    /// table, which takes up most of a trace with many modules. Frames
+    /// outside the main executable are encoded as absolute addresses.
+    pub fn encode_without_modules_into(
+      &self,
+      frames: &[Frame],
+      signal: Option<&SignalInfo>,
+      buf: &mut [u8],
+    ) -> Option<usize> {
+      self.encode_sections_into(frames, signal, None, false, buf)
+    }
+
+    fn encode_sections_into(
+      &self,
+      frames: &[Frame],
+      signal: Option<&SignalInfo>,
+      stack_memory: Option<&StackMemory>,
+      with_modules: bool,
+      buf: &mut [u8],
+    ) -> Option<usize> {
+      // The cached indices of the modules referenced by `frames`, in the
+      // order they'll be written to the trace's module table.
+      let mut used = [0; MAX_MODULES + 1];
+      let mut used_len = 1;
+      for frame in frames {
+        if let Some(StableAddr { module, .. }) = frame.stable
+          && with_modules
+          && used_len < used.len()
+          && !used[..used_len].contains(&module)
+        {
+          used[used_len] = module;
+          used_len += 1;
+        }
+      }
+      let used = &used[..used_len];
+
+      let frames = frames.iter().map(|frame| {
+        let call_site = !frame.exact;
+        let absolute = FrameAddr::Absolute {
+          addr: frame.ip,
+          call_site,
+        };
+        match frame.stable {
+          Some(StableAddr { module: 0, addr }) => {
+            FrameAddr::Stable { addr, call_site }
+          }
+          Some(StableAddr { module, addr }) => {
+            match used.iter().position(|&m| m == module) {
+              Some(module) => FrameAddr::InModule {
+                module,
+                addr,
+                call_site,
+              },
+              None => absolute,
+            }
+          }
+          None => absolute,
+        }
+      });
+      let modules = used.iter().filter_map(|&index| cached_module(index));
+      let sections = [
+        self
+          .build_id
+          .is_some()
+          .then_some(Section::BuildId(&self.build_id)),
+        with_modules.then_some(Section::Modules(modules)),
+        signal.map(Section::Signal),
+        stack_memory.map(Section::StackMemory),
+      ];
+      encode_sections_into(&self.header, frames, &sections, buf)
+    }
+  }
+}
+
+#[cfg(all(test, unix, feature = "encode"))]
+mod tests {
+  use std::cell::UnsafeCell;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use super::*;
+  use crate::encode::{CanaryHash, FrameAddr, StackTrace, Version};
+
+  struct Buffer(UnsafeCell<[u8; 4096]>);
+  // SAFETY: Only written by the signal handler, and only read after it ran.
+  unsafe impl Sync for Buffer {}
+
+  static ENCODER: OnceLock<SignalSafeEncoder> = OnceLock::new();
+  static ENCODED: Buffer = Buffer(UnsafeCell::new([0; 4096]));
+  static ENCODED_LEN: AtomicUsize = AtomicUsize::new(0);
+
+  extern "C" fn handler(_signal: libc::c_int) {
+    let mut frames = [Frame::default(); 64];
+    let len = signal_safe_stacktrace(&mut frames);
+    let buf = unsafe { &mut *ENCODED.0.get() };
+    let encoded = ENCODER
+      .get()
+      .unwrap()
+      .encode_into(&frames[..len], None, buf);
+    ENCODED_LEN.store(encoded.unwrap_or(0), Ordering::SeqCst);
+  }
+
+  #[test]
+  fn capture_in_signal_handler() {
+    let version = Version {
+      major: 1,
+      minor: 2,
+      patch: 3,
+      canary_hash: CanaryHash::none(),
+      dev_build: false,
+    };
+    ENCODER.get_or_init(|| SignalSafeEncoder::new(version));
+    unsafe {
+      let mut action: libc::sigaction = std::mem::zeroed();
+      action.sa_sigaction = handler as extern "C" fn(libc::c_int) as usize;
+      libc::sigemptyset(&mut action.sa_mask);
+      assert_eq!(
+        libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut()),
+        0
+      );
+      libc::raise(libc::SIGUSR2);
+    }
+
+    let len = ENCODED_LEN.load(Ordering::SeqCst);
+    assert!(len > 0);
+    let encoded = unsafe { &(&*ENCODED.0.get())[..len] };
+    let stack_trace = StackTrace::decode(encoded).unwrap();
+    assert_eq!(
+      std::path::Path::new(&stack_trace.modules()[0].path),
+      std::env::current_exe().unwrap()
+    );
+    assert_eq!(
+      stack_trace.header().build_id(),
+      stack_trace.modules()[0].build_id.as_ref()
+    );
+    let mut stable = 0;
+    for frame in stack_trace.frames() {
+      match frame {
+        FrameAddr::Stable { .. } => stable += 1,
+        FrameAddr::InModule { module, .. } => {
+          assert!(*module > 0 && *module < stack_trace.modules().len());
+        }
+        _ => {}
+      }
+    }
+    // At least the handler and this test are in the main executable.
+    assert!(stable >= 2, "{stack_trace:#?}");
+  }
+
+  struct Frames(UnsafeCell<[Frame; 64]>);
+  // SAFETY: Only written by the signal handler, and only read after it ran.
+  unsafe impl Sync for Frames {}
+
+  static FRAMES: Frames = Frames(UnsafeCell::new(
+    [Frame {
+      ip: 0,
+      stable: None,
+      exact: false,
+    }; 64],
+  ));
+  static FRAMES_LEN: AtomicUsize = AtomicUsize::new(0);
+  static CONTEXT_IP: AtomicUsize = AtomicUsize::new(0);
+
+  extern "C" fn context_handler(
+    _signal: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+  ) {
+    let Some(ip) = (unsafe { crate::ucontext_ip(context) }) else {
+      return;
+    };
+    let frames = unsafe { &mut *FRAMES.0.get() };
+    let len = signal_safe_stacktrace_from_ip(ip, frames);
+    CONTEXT_IP.store(ip as usize, Ordering::SeqCst);
+    FRAMES_LEN.store(len, Ordering::SeqCst);
+  }
+
+  #[test]
+  fn capture_from_ucontext() {
+    cache_modules();
+    unsafe {
+      let mut action: libc::sigaction = std::mem::zeroed();
+      action.sa_sigaction = context_handler
+        as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+        as usize;
+      action.sa_flags = libc::SA_SIGINFO;
+      libc::sigemptyset(&mut action.sa_mask);
+      assert_eq!(
+        libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()),
+        0
+      );
+      libc::raise(libc::SIGUSR1);
+    }
+
+    let ip = CONTEXT_IP.load(Ordering::SeqCst) as u64;
+    let len = FRAMES_LEN.load(Ordering::SeqCst);
+    if ip == 0 {
+      // `ucontext_ip` doesn't support this architecture.
+      return;
+    }
+    let frames = unsafe { &(&*FRAMES.0.get())[..len] };
+    assert_eq!(frames[0].ip, ip);
+    assert!(frames[0].exact);
+    assert!(frames[1..].iter().all(|frame| !frame.exact));
+    // The handler's frame was skipped, and the test's frame is still there.
+    let names = frames
+      .iter()
+      .map(|frame| {
+        let mut name = String::new();
+        backtrace::resolve(frame.ip as usize as *mut _, |symbol| {
+          if let Some(symbol_name) = symbol.name() {
+            name = symbol_name.to_string();
+          }
+        });
+        name
+      })
+      .collect::<Vec<_>>();
+    let has = |name: &str| names.iter().any(|n| n.contains(name));
+    assert!(!has("context_handler"), "{names:#?}");
+    assert!(has("capture_from_ucontext"), "{names:#?}");
+  }
+}
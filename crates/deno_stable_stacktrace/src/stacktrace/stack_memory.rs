@@ -0,0 +1,370 @@
+//! Captures registers and a slice of stack memory, so a stack can be unwound
+//! again offline (see [`StackMemory`]).
+
+use crate::StackMemory;
+
+/// The most registers [`capture_stack_memory`] records, on aarch64.
+pub const MAX_STACK_REGISTERS: usize = 13;
+
+/// Captures the registers of this function's frame and up to `max_len` bytes
+/// of the stack above its stack pointer, i.e. its callers' frames. Like the
+/// first frames of [`crate::stable_stacktrace`], the first unwound frame is
+/// in this crate.
+///
+/// Returns `None` on platforms other than Linux and macOS on x86_64 and
+/// aarch64.
+#[inline(never)]
+pub fn capture_stack_memory(max_len: usize) -> Option<StackMemory> {
+  // Read the registers here rather than in a callee, whose frame would be
+  // overwritten by the time the stack is copied.
+  // SAFETY: Only reads registers.
+  let (ip, values) = unsafe { arch::current_registers() }?;
+  let mut stack_memory = StackMemory {
+    ip,
+    registers: Vec::with_capacity(MAX_STACK_REGISTERS),
+    start: 0,
+    memory: Vec::with_capacity(max_len),
+  };
+  fill(&mut stack_memory, ip, &values, max_len);
+  Some(stack_memory)
+}
+
+/// Like [`capture_stack_memory`], but for the frame a signal interrupted, from
+/// the `ucontext_t` passed to a `SA_SIGINFO` handler running on the
+/// interrupted thread. Returns `false` on unsupported platforms.
+///
+/// Fills `stack_memory` in place, so it doesn't allocate (and is
+/// async-signal-safe) if its vectors already have capacity for
+/// [`MAX_STACK_REGISTERS`] registers and `max_len` bytes.
+///
+/// # Safety
+///
+/// `ucontext` must point to a valid `ucontext_t`.
+#[cfg(unix)]
+pub unsafe fn capture_stack_memory_from_ucontext(
+  ucontext: *const std::ffi::c_void,
+  max_len: usize,
+  stack_memory: &mut StackMemory,
+) -> bool {
+  let ucontext = ucontext.cast::<libc::ucontext_t>();
+  if ucontext.is_null() {
+    return false;
+  }
+  let Some((ip, values)) = (unsafe { arch::context_registers(&*ucontext) })
+  else {
+    return false;
+  };
+  fill(stack_memory, ip, &values, max_len);
+  true
+}
+
+/// Records `values` (of [`arch::REGISTERS`]) and copies the stack above the
+/// stack pointer, without allocating if `stack_memory` has the capacity.
+fn fill(
+  stack_memory: &mut StackMemory,
+  ip: u64,
+  values: &[u64; arch::REGISTERS.len()],
+  max_len: usize,
+) {
+  let sp = arch::REGISTERS
+    .iter()
+    .position(|&number| number == arch::STACK_POINTER)
+    .map_or(0, |index| values[index]);
+  stack_memory.ip = ip;
+  stack_memory.registers.clear();
+  stack_memory.registers.extend(
+    arch::REGISTERS
+      .iter()
+      .zip(values)
+      .map(|(&number, &value)| crate::Register { number, value }),
+  );
+  stack_memory.start = sp;
+  stack_memory.memory.clear();
+  stack_memory.memory.resize(max_len, 0);
+  let len = read_stack(sp, &mut stack_memory.memory);
+  stack_memory.memory.truncate(len);
+}
+
+/// Copies the stack starting at `addr` into `buf`, stopping at the first page
+/// that isn't mapped. Returns the number of bytes copied.
+#[cfg(target_os = "linux")]
+fn read_stack(addr: u64, buf: &mut [u8]) -> usize {
+  // Copy through the kernel, which fails instead of faulting on unmapped
+  // memory. It only stops between iovecs, so each one covers a page.
+  const PAGE_SIZE: usize = 4096;
+  const BATCH: usize = 16;
+  let mut read = 0;
+  while read < buf.len() {
+    let mut remote = [libc::iovec {
+      iov_base: std::ptr::null_mut(),
+      iov_len: 0,
+    }; BATCH];
+    let mut count = 0;
+    let mut len = 0;
+    let mut at = addr as usize + read;
+    while count < BATCH && read + len < buf.len() {
+      let chunk = (PAGE_SIZE - at % PAGE_SIZE).min(buf.len() - read - len);
+      remote[count] = libc::iovec {
+        iov_base: at as *mut libc::c_void,
+        iov_len: chunk,
+      };
+      at += chunk;
+      len += chunk;
+      count += 1;
+    }
+    let local = libc::iovec {
+      iov_base: buf[read..].as_mut_ptr().cast(),
+      iov_len: len,
+    };
+    // SAFETY: `local` is within `buf`, and the kernel checks `remote`.
+    let copied = unsafe {
+      libc::process_vm_readv(
+        libc::getpid(),
+        &local,
+        1,
+        remote.as_ptr(),
+        count as libc::c_ulong,
+        0,
+      )
+    };
+    if copied <= 0 {
+      break;
+    }
+    read += copied as usize;
+    if (copied as usize) < len {
+      break;
+    }
+  }
+  read
+}
+
+/// Copies the stack starting at `addr` into `buf`, up to the top of the
+/// calling thread's stack. Returns the number of bytes copied.
+#[cfg(target_vendor = "apple")]
+fn read_stack(addr: u64, buf: &mut [u8]) -> usize {
+  // SAFETY: Only queries the calling thread's stack bounds.
+  let (top, size) = unsafe {
+    let thread = libc::pthread_self();
+    (
+      libc::pthread_get_stackaddr_np(thread) as u64,
+      libc::pthread_get_stacksize_np(thread) as u64,
+    )
+  };
+  if addr < top.saturating_sub(size) || addr >= top {
+    return 0;
+  }
+  let len = buf.len().min((top - addr) as usize);
+  // SAFETY: The stack is mapped from the stack pointer up to its top.
+  unsafe {
+    std::ptr::copy_nonoverlapping(addr as *const u8, buf.as_mut_ptr(), len);
+  }
+  len
+}
+
+#[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+fn read_stack(_addr: u64, _buf: &mut [u8]) -> usize {
+  0
+}
+
+#[cfg(all(
+  any(target_os = "linux", target_vendor = "apple"),
+  target_arch = "x86_64"
+))]
+mod arch {
+  /// The DWARF numbers of `rbx`, `rbp`, `rsp` and `r12` to `r15`.
+  pub const REGISTERS: [u16; 7] = [3, 6, 7, 12, 13, 14, 15];
+  pub const STACK_POINTER: u16 = 7;
+
+  /// Returns the instruction pointer and [`REGISTERS`] of the calling
+  /// function. Always inlined, so they're consistent with its frame.
+  #[inline(always)]
+  pub unsafe fn current_registers() -> Option<(u64, [u64; REGISTERS.len()])> {
+    let mut values = [0; REGISTERS.len()];
+    let ip: u64;
+    unsafe {
+      std::arch::asm!(
+        "lea {ip}, [rip]",
+        "mov [{values}], rbx",
+        "mov [{values} + 8], rbp",
+        "mov [{values} + 16], rsp",
+        "mov [{values} + 24], r12",
+        "mov [{values} + 32], r13",
+        "mov [{values} + 40], r14",
+        "mov [{values} + 48], r15",
+        values = in(reg) values.as_mut_ptr(),
+        ip = out(reg) ip,
+        options(nostack, preserves_flags),
+      );
+    }
+    Some((ip, values))
+  }
+
+  #[cfg(target_os = "linux")]
+  pub unsafe fn context_registers(
+    context: &libc::ucontext_t,
+  ) -> Option<(u64, [u64; REGISTERS.len()])> {
+    let gregs = &context.uc_mcontext.gregs;
+    let reg = |index: libc::c_int| gregs[index as usize] as u64;
+    Some((
+      reg(libc::REG_RIP),
+      [
+        reg(libc::REG_RBX),
+        reg(libc::REG_RBP),
+        reg(libc::REG_RSP),
+        reg(libc::REG_R12),
+        reg(libc::REG_R13),
+        reg(libc::REG_R14),
+        reg(libc::REG_R15),
+      ],
+    ))
+  }
+
+  #[cfg(target_vendor = "apple")]
+  pub unsafe fn context_registers(
+    context: &libc::ucontext_t,
+  ) -> Option<(u64, [u64; REGISTERS.len()])> {
+    let state = unsafe { &(*context.uc_mcontext).__ss };
+    Some((
+      state.__rip,
+      [
+        state.__rbx,
+        state.__rbp,
+        state.__rsp,
+        state.__r12,
+        state.__r13,
+        state.__r14,
+        state.__r15,
+      ],
+    ))
+  }
+}
+
+#[cfg(all(
+  any(target_os = "linux", target_vendor = "apple"),
+  target_arch = "aarch64"
+))]
+mod arch {
+  /// The DWARF numbers of `x19` to `x30` (the frame pointer being `x29` and
+  /// the link register `x30`) and `sp`.
+  pub const REGISTERS: [u16; 13] =
+    [19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31];
+  pub const STACK_POINTER: u16 = 31;
+
+  /// Returns the instruction pointer and [`REGISTERS`] of the calling
+  /// function. Always inlined, so they're consistent with its frame.
+  #[inline(always)]
+  pub unsafe fn current_registers() -> Option<(u64, [u64; REGISTERS.len()])> {
+    let mut values = [0; REGISTERS.len()];
+    let ip: u64;
+    unsafe {
+      std::arch::asm!(
+        "adr {ip}, .",
+        "stp x19, x20, [{values}]",
+        "stp x21, x22, [{values}, #16]",
+        "stp x23, x24, [{values}, #32]",
+        "stp x25, x26, [{values}, #48]",
+        "stp x27, x28, [{values}, #64]",
+        "stp x29, x30, [{values}, #80]",
+        "mov {sp}, sp",
+        "str {sp}, [{values}, #96]",
+        values = in(reg) values.as_mut_ptr(),
+        ip = out(reg) ip,
+        sp = out(reg) _,
+        options(nostack, preserves_flags),
+      );
+    }
+    Some((ip, values))
+  }
+
+  #[cfg(target_os = "linux")]
+  pub unsafe fn context_registers(
+    context: &libc::ucontext_t,
+  ) -> Option<(u64, [u64; REGISTERS.len()])> {
+    let mcontext = &context.uc_mcontext;
+    let mut values = [0; REGISTERS.len()];
+    values[..12].copy_from_slice(&mcontext.regs[19..31]);
+    values[12] = mcontext.sp;
+    Some((mcontext.pc, values))
+  }
+
+  #[cfg(target_vendor = "apple")]
+  pub unsafe fn context_registers(
+    context: &libc::ucontext_t,
+  ) -> Option<(u64, [u64; REGISTERS.len()])> {
+    let state = unsafe { &(*context.uc_mcontext).__ss };
+    let mut values = [0; REGISTERS.len()];
+    values[..10].copy_from_slice(&state.__x[19..29]);
+    values[10] = state.__fp;
+    values[11] = state.__lr;
+    values[12] = state.__sp;
+    Some((state.__pc, values))
+  }
+}
+
+#[cfg(not(all(
+  any(target_os = "linux", target_vendor = "apple"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+mod arch {
+  pub const REGISTERS: [u16; 0] = [];
+  pub const STACK_POINTER: u16 = 0;
+
+  pub unsafe fn current_registers() -> Option<(u64, [u64; 0])> {
+    None
+  }
+
+  #[cfg(unix)]
+  pub unsafe fn context_registers(
+    _context: &libc::ucontext_t,
+  ) -> Option<(u64, [u64; 0])> {
+    None
+  }
+}
+
+#[cfg(all(
+  test,
+  any(target_os = "linux", target_vendor = "apple"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod tests {
+  use super::*;
+
+  #[inline(never)]
+  fn capture(max_len: usize) -> (StackMemory, u64) {
+    let marker = 0x5ca1_ab1e_u64;
+    let stack_memory = capture_stack_memory(max_len).unwrap();
+    (
+      stack_memory,
+      std::hint::black_box(&marker) as *const u64 as u64,
+    )
+  }
+
+  #[test]
+  fn captures_registers_and_stack() {
+    let (stack_memory, marker) = capture(64 * 1024);
+    assert_eq!(stack_memory.memory.len(), 64 * 1024);
+    assert_eq!(stack_memory.registers.len(), arch::REGISTERS.len());
+    assert_eq!(
+      stack_memory.register(arch::STACK_POINTER),
+      Some(stack_memory.start)
+    );
+    let ip = capture_stack_memory as *const () as usize as u64;
+    assert!(
+      stack_memory.ip > ip && stack_memory.ip - ip < 0x1000,
+      "{:#x} {ip:#x}",
+      stack_memory.ip
+    );
+    // The caller's locals are above the stack pointer.
+    assert_eq!(stack_memory.read_u64(marker), Some(0x5ca1_ab1e));
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn stops_at_unmapped_memory() {
+    // Test threads have 2 MiB stacks by default, and the copy stops at the
+    // end of the mapping.
+    let (stack_memory, _) = capture(16 * 1024 * 1024);
+    assert!(!stack_memory.memory.is_empty());
+    assert!(stack_memory.memory.len() < 16 * 1024 * 1024);
+  }
+}
@@ -0,0 +1,286 @@
+//! Capturing the stacks of every thread in the process, e.g. to diagnose a
+//! deadlock or a hang.
+//!
+//! A thread can only unwind its own stack, so each thread is sent a signal
+//! whose handler captures its stack into a shared buffer, one thread at a
+//! time, while the requesting thread waits.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+
+use super::{Frame, ModuleTable};
+use crate::Module;
+
+/// The most frames captured per thread.
+const MAX_FRAMES: usize = 256;
+
+/// How long to wait for a thread to handle the signal. Threads that block it,
+/// or are stuck in the kernel, are reported without frames.
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// [`REQUEST`] when no capture is requested.
+const IDLE: i32 = 0;
+/// [`REQUEST`] while a signal handler is writing to [`FRAMES`].
+const CAPTURING: i32 = -1;
+/// [`REQUEST`] once a signal handler has written [`FRAMES`].
+const DONE: i32 = -2;
+
+/// The ID of the thread asked to capture its stack, or one of the states
+/// above.
+static REQUEST: AtomicI32 = AtomicI32::new(IDLE);
+static FRAMES_LEN: AtomicUsize = AtomicUsize::new(0);
+static FRAMES: Frames = Frames(UnsafeCell::new([0; MAX_FRAMES]));
+/// Serializes callers, as there's only one buffer.
+static LOCK: Mutex<()> = Mutex::new(());
+static INSTALL: Once = Once::new();
+
+struct Frames(UnsafeCell<[u64; MAX_FRAMES]>);
+// SAFETY: Only the thread that moved `REQUEST` to `CAPTURING` writes to it,
+// and it's only read after it moved `REQUEST` to `DONE`.
+unsafe impl Sync for Frames {}
+
+/// A thread's stack, captured by [`all_threads_stacktraces`].
+#[derive(Debug, Clone)]
+pub struct CapturedThreadStack {
+  /// The kernel's thread ID.
+  pub id: u64,
+  pub name: Option<String>,
+  /// Empty if the thread didn't handle the signal in time.
+  pub frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedThreads {
+  /// The modules referenced by the frames of all threads. The main
+  /// executable is always the first entry.
+  pub modules: Vec<Module>,
+  /// The calling thread is always the first entry.
+  pub threads: Vec<CapturedThreadStack>,
+}
+
+/// Captures the stacks of all threads in the process, recording the module
+/// each frame belongs to.
+///
+/// Each other thread is interrupted by a `SIGRTMIN` signal, whose handler is
+/// installed on the first call and stays installed, so it mustn't be used by
+/// anything else. The frame a thread was interrupted at is
+/// [exact](Frame::exact). Threads that don't handle the signal within 100ms
+/// (e.g. because they block it) are included without frames.
+pub fn all_threads_stacktraces() -> CapturedThreads {
+  let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+  INSTALL.call_once(install_handler);
+
+  let current = gettid();
+  let mut stacks = vec![(current, super::walk_stack(false, None).unwrap())];
+  for tid in thread_ids() {
+    if tid != current {
+      stacks.push((tid, capture_thread(tid)));
+    }
+  }
+
+  let addrs = stacks
+    .iter()
+    .flat_map(|(_, ips)| ips)
+    .map(|&(ip, exact)| Frame::lookup_addr(ip, exact))
+    .collect::<Vec<_>>();
+  let mut table = ModuleTable::new(&addrs);
+  let threads = stacks
+    .into_iter()
+    .map(|(tid, ips)| CapturedThreadStack {
+      id: tid as u64,
+      name: thread_name(tid),
+      frames: ips
+        .into_iter()
+        .map(|(ip, exact)| table.frame(ip, exact))
+        .collect(),
+    })
+    .collect();
+  CapturedThreads {
+    modules: table.modules,
+    threads,
+  }
+}
+
+fn gettid() -> i32 {
+  unsafe { libc::syscall(libc::SYS_gettid) as i32 }
+}
+
+fn thread_ids() -> Vec<i32> {
+  let Ok(entries) = std::fs::read_dir("/proc/self/task") else {
+    return Vec::new();
+  };
+  let mut tids = entries
+    .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+    .collect::<Vec<_>>();
+  tids.sort_unstable();
+  tids
+}
+
+fn thread_name(tid: i32) -> Option<String> {
+  let comm = std::fs::read_to_string(format!("/proc/self/task/{tid}/comm"));
+  let name = comm.ok()?.trim_end_matches('\n').to_owned();
+  (!name.is_empty()).then_some(name)
+}
+
+fn install_handler() {
+  unsafe {
+    let mut action: libc::sigaction = std::mem::zeroed();
+    action.sa_sigaction = handler
+      as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+      as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+    libc::sigemptyset(&mut action.sa_mask);
+    libc::sigaction(libc::SIGRTMIN(), &action, std::ptr::null_mut());
+  }
+}
+
+/// Returns the instruction pointers `tid` was interrupted at, and whether
+/// each is exact.
+fn capture_thread(tid: i32) -> Vec<(u64, bool)> {
+  REQUEST.store(tid, Ordering::SeqCst);
+  let sent = unsafe {
+    libc::syscall(libc::SYS_tgkill, libc::getpid(), tid, libc::SIGRTMIN())
+  };
+  if sent != 0 {
+    // The thread exited since it was listed.
+    REQUEST.store(IDLE, Ordering::SeqCst);
+    return Vec::new();
+  }
+
+  let deadline = Instant::now() + TIMEOUT;
+  loop {
+    match REQUEST.load(Ordering::Acquire) {
+      DONE => break,
+      // Once the handler started capturing, wait for it to finish, as it
+      // owns the buffer.
+      CAPTURING => {}
+      _ if Instant::now() < deadline => {}
+      _ => {
+        if REQUEST
+          .compare_exchange(tid, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+          .is_ok()
+        {
+          return Vec::new();
+        }
+        continue;
+      }
+    }
+    std::thread::sleep(Duration::from_micros(100));
+  }
+
+  let len = FRAMES_LEN.load(Ordering::SeqCst);
+  // SAFETY: The handler finished writing, and the next one can't start
+  // until `REQUEST` holds a thread ID again.
+  let frames = unsafe { &(&*FRAMES.0.get())[..len] };
+  // The first entry is the interrupted instruction, or zero if it's unknown.
+  let ips = frames
+    .iter()
+    .enumerate()
+    .filter(|&(_, &ip)| ip != 0)
+    .map(|(i, &ip)| (ip, i == 0))
+    .collect();
+  REQUEST.store(IDLE, Ordering::SeqCst);
+  ips
+}
+
+extern "C" fn handler(
+  _signal: libc::c_int,
+  _info: *mut libc::siginfo_t,
+  context: *mut libc::c_void,
+) {
+  let tid = gettid();
+  if REQUEST
+    .compare_exchange(tid, CAPTURING, Ordering::SeqCst, Ordering::SeqCst)
+    .is_err()
+  {
+    // The request timed out, or the signal was sent by someone else.
+    return;
+  }
+
+  // SAFETY: Moving `REQUEST` to `CAPTURING` gave this thread the buffer.
+  let frames = unsafe { &mut *FRAMES.0.get() };
+  let start_ip = unsafe { super::ucontext_ip(context) };
+  frames[0] = 0;
+  let mut len = 1;
+  let mut found_start = false;
+  // SAFETY: `trace` serializes concurrent traces with a mutex, which
+  // mustn't be taken in a signal handler.
+  unsafe {
+    backtrace::trace_unsynchronized(|walked| {
+      let ip = walked.ip() as usize as u64;
+      if let Some(start_ip) = start_ip
+        && !found_start
+        && ip == start_ip
+      {
+        // Drop the frames of this handler.
+        found_start = true;
+        len = 0;
+      }
+      let Some(slot) = frames.get_mut(len) else {
+        return start_ip.is_some() && !found_start;
+      };
+      *slot = ip;
+      len += 1;
+      true
+    });
+  }
+  if let Some(start_ip) = start_ip
+    && !found_start
+  {
+    frames[0] = start_ip;
+  }
+
+  FRAMES_LEN.store(len, Ordering::SeqCst);
+  REQUEST.store(DONE, Ordering::Release);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc;
+
+  use super::*;
+
+  #[test]
+  fn captures_other_threads() {
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let thread = std::thread::Builder::new()
+      .name("stack-dump-test".into())
+      .spawn(move || {
+        ready_tx.send(gettid()).unwrap();
+        done_rx.recv().unwrap();
+      })
+      .unwrap();
+    let tid = ready_rx.recv().unwrap();
+
+    let captured = all_threads_stacktraces();
+    done_tx.send(()).unwrap();
+    thread.join().unwrap();
+
+    assert_eq!(captured.threads[0].id, gettid() as u64);
+    assert!(!captured.threads[0].frames.is_empty());
+    let other = captured
+      .threads
+      .iter()
+      .find(|thread| thread.id == tid as u64)
+      .unwrap();
+    assert_eq!(other.name.as_deref(), Some("stack-dump-test"));
+    assert!(!other.frames.is_empty(), "{captured:#?}");
+    // The thread is blocked in a syscall, but its closure is in the test
+    // binary.
+    assert!(
+      other
+        .frames
+        .iter()
+        .any(|frame| frame.stable.is_some_and(|stable| stable.module == 0)),
+      "{captured:#?}"
+    );
+    for frame in captured.threads.iter().flat_map(|thread| &thread.frames) {
+      if let Some(stable) = frame.stable {
+        assert!(stable.module < captured.modules.len());
+      }
+    }
+  }
+}
@@ -0,0 +1,34 @@
+#!/bin/sh
+# Regenerates the fixtures. The tests depend on the addresses in them, so
+# only rebuild them when changing the tests too.
+set -eu
+cd "$(dirname "$0")"
+flags="-O2 -g -fdebug-prefix-map=$PWD=. -Wl,--build-id"
+
+# Debug info in a separate file, found through the debug link.
+gcc $flags lib.c main.c -o plain.full
+objcopy --only-keep-debug plain.full plain.debug
+objcopy --strip-all --add-gnu-debuglink=plain.debug plain.full plain
+rm plain.full
+
+# Split DWARF 5, with the units in .dwo files.
+gcc $flags -gsplit-dwarf -c lib.c -o lib.o
+gcc $flags -gsplit-dwarf -c main.c -o main.o
+gcc $flags lib.o main.o -o split
+rm lib.o main.o
+
+# A stripped object whose debug file has the skeleton units.
+objcopy --only-keep-debug split split-stripped.debug
+objcopy --strip-all --add-gnu-debuglink=split-stripped.debug split \
+  split-stripped
+
+# Split DWARF 4 (the GNU extension), with the units in a .dwp package.
+gcc $flags -gdwarf-4 -gsplit-dwarf -c lib.c -o lib4.o
+gcc $flags -gdwarf-4 -gsplit-dwarf -c main.c -o main4.o
+gcc $flags lib4.o main4.o -o split4
+dwp -e split4 -o split4.dwp
+rm lib4.o main4.o lib4.dwo main4.dwo
+
+# Stripped of its debug info, so only the symbol table is left.
+gcc $flags lib.c main.c -o symtab
+strip --strip-debug symtab
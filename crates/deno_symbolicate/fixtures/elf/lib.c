@@ -0,0 +1,11 @@
+static inline int helper(int x) {
+  int sum = 0;
+  for (int i = 0; i < x; i++) {
+    sum += i * x;
+  }
+  return sum;
+}
+
+int compute(int x) {
+  return helper(x) + 1;
+}
@@ -0,0 +1,8 @@
+#include <stdio.h>
+
+int compute(int x);
+
+int main(int argc, char **argv) {
+  printf("%d\n", compute(argc));
+  return 0;
+}
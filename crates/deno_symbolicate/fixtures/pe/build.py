@@ -0,0 +1,221 @@
+#!/usr/bin/env python3
+"""Regenerates the fixtures: app.pdb, the matching app.exe, and
+app-rebuilt.exe, a later build of it whose PDB isn't here.
+
+The tests depend on the addresses in them:
+
+- `compute` is at RVA 0x1010..0x1030 (lib.c:9-11). `helper` (lib.c:1) is
+  inlined into it at 0x1018..0x1028, called from lib.c:10.
+- `exported_only` at RVA 0x1080 is an export without debug info.
+
+Needs `llvm-pdbutil`, as there's no MSVC toolchain on Linux.
+"""
+
+import struct
+import subprocess
+import uuid
+from pathlib import Path
+
+HERE = Path(__file__).parent
+GUID = uuid.UUID("9D2E0A4B-7C51-4F3A-8E6D-1B2C3D4E5F60")
+AGE = 2
+TEXT_RVA = 0x1000
+RDATA_RVA = 0x2000
+SECTION_SIZE = 0x200
+
+
+def read_msf(data):
+  block_size, _, _, dir_bytes, _, block_map = struct.unpack_from(
+    "<6I", data, 32)
+
+  def blocks(indices, size):
+    out = b"".join(
+      data[i * block_size:(i + 1) * block_size] for i in indices)
+    return out[:size]
+
+  dir_blocks = -(-dir_bytes // block_size)
+  dir_indices = struct.unpack_from(f"<{dir_blocks}I", data,
+                                   block_map * block_size)
+  directory = blocks(dir_indices, dir_bytes)
+  count = struct.unpack_from("<I", directory)[0]
+  sizes = struct.unpack_from(f"<{count}I", directory, 4)
+  offset = 4 + 4 * count
+  streams = []
+  for size in sizes:
+    size = 0 if size == 0xFFFFFFFF else size
+    n = -(-size // block_size)
+    indices = struct.unpack_from(f"<{n}I", directory, offset)
+    offset += 4 * n
+    streams.append(blocks(indices, size))
+  return block_size, streams
+
+
+def write_msf(block_size, streams):
+  # Block 0 is the superblock and 1-2 the free block maps.
+  out = [b""] * 3
+  indices = []
+  for stream in streams:
+    n = -(-len(stream) // block_size)
+    indices.append(list(range(len(out), len(out) + n)))
+    out += [stream[i * block_size:(i + 1) * block_size] for i in range(n)]
+  directory = struct.pack(f"<{len(streams) + 1}I", len(streams),
+                          *map(len, streams))
+  for stream_indices in indices:
+    directory += struct.pack(f"<{len(stream_indices)}I", *stream_indices)
+  dir_start = len(out)
+  dir_blocks = -(-len(directory) // block_size)
+  out += [
+    directory[i * block_size:(i + 1) * block_size] for i in range(dir_blocks)
+  ]
+  block_map = len(out)
+  out.append(struct.pack(f"<{dir_blocks}I",
+                         *range(dir_start, dir_start + dir_blocks)))
+  num_blocks = len(out)
+  out[0] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0" + struct.pack(
+    "<6I", block_size, 1, num_blocks, len(directory), 0, block_map)
+  # All blocks are used.
+  out[1] = bytes(num_blocks // 8) + bytes(
+    [0xFF << (num_blocks % 8) & 0xFF]) + b"\xff" * block_size
+  return b"".join(block.ljust(block_size, b"\0")[:block_size] for block in out)
+
+
+def record(kind, data):
+  data = struct.pack("<H", kind) + data
+  pad = -(len(data) + 2) % 4
+  data += bytes([0xF0 + i for i in range(pad, 0, -1)])
+  return struct.pack("<H", len(data)) + data
+
+
+def symbols():
+  """compute, with helper inlined into it."""
+  S_GPROC32, S_INLINESITE, S_INLINESITE_END, S_END = (
+    0x1110, 0x114D, 0x114E, 0x0006)
+  proc_offset = 4
+  # The binary annotations: line +2 (1 -> 3), code offset 8, length 0x10,
+  # padded with zeros (no more annotations) rather than the usual padding.
+  annotations = bytes([0x06, 0x04, 0x03, 0x08, 0x04, 0x10, 0x00, 0x00])
+  inline_len = len(record(S_INLINESITE, bytes(12) + annotations))
+  proc_len = len(
+    record(S_GPROC32, bytes(35) + b"compute\0"))
+  inline_offset = proc_offset + proc_len
+  inline_end = inline_offset + inline_len
+  proc_end = inline_end + 4
+  proc = record(
+    S_GPROC32,
+    struct.pack("<7IIHB", 0, proc_end, 0, 0x20, 0, 0x20, 0, 0x10, 1, 0) +
+    b"compute\0")
+  assert len(proc) == proc_len
+  inline = record(S_INLINESITE,
+                  struct.pack("<3I", proc_offset, inline_end, 0x1000) +
+                  annotations)
+  return (struct.pack("<I", 4) + proc + inline +
+          record(S_INLINESITE_END, b"") + record(S_END, b""))
+
+
+def publics():
+  S_PUB32 = 0x110E
+  return record(S_PUB32, struct.pack("<IIH", 2, 0x10, 1) + b"compute\0")
+
+
+def section_header(name, rva, characteristics, file_offset):
+  return struct.pack("<8s6I2HI", name, SECTION_SIZE, rva, SECTION_SIZE,
+                     file_offset, 0, 0, 0, 0, characteristics)
+
+
+SECTIONS = [
+  section_header(b".text", TEXT_RVA, 0x60000020, 0x200),
+  section_header(b".rdata", RDATA_RVA, 0x40000040, 0x400),
+]
+
+
+def pdb():
+  yaml_pdb = HERE / "app.yaml.pdb"
+  subprocess.run(["llvm-pdbutil", "yaml2pdb", HERE / "app.yaml", "-pdb",
+                  yaml_pdb], check=True)
+  block_size, streams = read_msf(yaml_pdb.read_bytes())
+  yaml_pdb.unlink()
+
+  dbi = bytearray(streams[3])
+  (mod_info_size, contrib_size, section_map_size, source_info_size,
+   type_server_size, _, optional_size, ec_size) = struct.unpack_from(
+     "<8i", dbi, 24)
+  # The only module's symbols.
+  module_stream, = struct.unpack_from("<H", dbi, 64 + 34)
+  old_size, = struct.unpack_from("<I", dbi, 64 + 36)
+  new_symbols = symbols()
+  streams[module_stream] = new_symbols + streams[module_stream][old_size:]
+  struct.pack_into("<I", dbi, 64 + 36, len(new_symbols))
+
+  # yaml2pdb ignores the publics, so add the symbol records stream.
+  struct.pack_into("<H", dbi, 20, len(streams))
+  streams.append(publics())
+
+  # Point the optional debug header's section headers at a new stream.
+  optional_start = (64 + mod_info_size + contrib_size + section_map_size +
+                    source_info_size + type_server_size + ec_size)
+  optional = bytearray(dbi[optional_start:optional_start + optional_size])
+  optional = optional.ljust(22, b"\xff")
+  struct.pack_into("<H", optional, 10, len(streams))
+  streams.append(b"".join(SECTIONS))
+  dbi = dbi[:optional_start] + optional
+  struct.pack_into("<i", dbi, 48, len(optional))
+  streams[3] = bytes(dbi)
+  (HERE / "app.pdb").write_bytes(write_msf(block_size, streams))
+
+
+def pe(path, age):
+  exports = [(b"compute", 0x1010), (b"exported_only", 0x1080)]
+  text = bytearray(b"\xcc" * SECTION_SIZE)
+  for _, rva in exports:
+    text[rva - TEXT_RVA] = 0xC3
+
+  rdata = bytearray(SECTION_SIZE)
+  # The CodeView record, which the PDB is matched by.
+  codeview = b"RSDS" + GUID.bytes_le + struct.pack(
+    "<I", age) + b"C:\\src\\app.pdb\0"
+  rdata[0x20:0x20 + len(codeview)] = codeview
+  debug_dir = struct.pack("<IIHHIIII", 0, 0, 0, 0, 2, len(codeview),
+                          RDATA_RVA + 0x20, 0x400 + 0x20)
+  rdata[:len(debug_dir)] = debug_dir
+
+  export_rva = RDATA_RVA + 0x80
+  functions = export_rva + 40
+  names = functions + 4 * len(exports)
+  ordinals = names + 4 * len(exports)
+  strings = ordinals + 2 * len(exports)
+  export_data = struct.pack("<IIHHIIIIIII", 0, 0, 0, 0, strings, 1,
+                            len(exports), len(exports), functions, names,
+                            ordinals)
+  string_data = path.name.encode() + b"\0"
+  name_rvas = []
+  for name, _ in exports:
+    name_rvas.append(strings + len(string_data))
+    string_data += name + b"\0"
+  export_data += struct.pack(f"<{len(exports)}I",
+                             *(rva for _, rva in exports))
+  export_data += struct.pack(f"<{len(exports)}I", *name_rvas)
+  export_data += struct.pack(f"<{len(exports)}H", *range(len(exports)))
+  export_data += string_data
+  rdata[0x80:0x80 + len(export_data)] = export_data
+
+  data_dirs = [(0, 0)] * 16
+  data_dirs[0] = (export_rva, len(export_data))
+  data_dirs[6] = (RDATA_RVA, len(debug_dir))
+  optional = struct.pack(
+    "<HBBIIIIIQIIHHHHHHIIIIHHQQQQII", 0x20B, 14, 0, SECTION_SIZE,
+    SECTION_SIZE, 0, 0x1010, TEXT_RVA, 0x140000000, 0x1000, 0x200, 6, 0, 0,
+    0, 6, 0, 0, 0x3000, 0x200, 0, 3, 0x8160, 0x100000, 0x1000, 0x100000,
+    0x1000, 0, 16) + b"".join(struct.pack("<II", *d) for d in data_dirs)
+  coff = struct.pack("<HHIIIHH", 0x8664, len(SECTIONS), 0, 0, 0,
+                     len(optional), 0x22)
+  headers = bytearray(0x200)
+  headers[:2] = b"MZ"
+  struct.pack_into("<I", headers, 0x3C, 0x40)
+  nt = b"PE\0\0" + coff + optional + b"".join(SECTIONS)
+  headers[0x40:0x40 + len(nt)] = nt
+  path.write_bytes(bytes(headers) + bytes(text) + bytes(rdata))
+
+
+pdb()
+pe(HERE / "app.exe", AGE)
+pe(HERE / "app-rebuilt.exe", AGE + 1)
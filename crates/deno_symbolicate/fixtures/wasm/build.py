@@ -0,0 +1,102 @@
+#!/usr/bin/env python3
+"""Regenerates the fixtures from lib.ll (what clang emits for lib.c, with
+`helper` inlined into `compute`):
+
+- lib.debug.wasm, a module with DWARF custom sections, and
+- lib.wasm, the same module with its DWARF stripped and an
+  `external_debug_info` section naming lib.debug.wasm.
+
+Both have the same `build_id` section. The tests depend on the code offsets
+in them, which are relative to the start of the module:
+
+- `compute` is at 0x5a..0x97 (lib.c:8-11). `helper` (lib.c:1) is inlined
+  into it at 0x77..0x8f, called from lib.c:10.
+- `exported_only` at 0x98 only has a name in the name section.
+
+Needs `llc` with the WebAssembly target, as there's no wasm-ld here. The
+object it writes is a valid module once the linker's sections are dropped.
+"""
+
+import subprocess
+from pathlib import Path
+
+HERE = Path(__file__).parent
+BUILD_ID = bytes.fromhex("3f5a1c0e9b7d4e2a8c6b1d0f2e4a6c8e")
+
+
+def leb(value):
+  out = bytearray()
+  while True:
+    byte = value & 0x7F
+    value >>= 7
+    if value:
+      out.append(byte | 0x80)
+    else:
+      out.append(byte)
+      return bytes(out)
+
+
+def read_leb(data, offset):
+  value = shift = 0
+  while True:
+    byte = data[offset]
+    offset += 1
+    value |= (byte & 0x7F) << shift
+    shift += 7
+    if byte < 0x80:
+      return value, offset
+
+
+def sections(module):
+  offset = 8
+  while offset < len(module):
+    kind = module[offset]
+    size, start = read_leb(module, offset + 1)
+    name = None
+    if kind == 0:
+      length, name_start = read_leb(module, start)
+      name = module[name_start:name_start + length].decode()
+    yield kind, name, module[offset:start + size]
+    offset = start + size
+
+
+def name_bytes(name):
+  return leb(len(name)) + name.encode()
+
+
+def custom(name, payload):
+  content = name_bytes(name) + payload
+  return b"\0" + leb(len(content)) + content
+
+
+def function_names(names):
+  entries = b"".join(leb(i) + name_bytes(name) for i, name in enumerate(names))
+  subsection = leb(len(names)) + entries
+  return custom("name", b"\1" + leb(len(subsection)) + subsection)
+
+
+def main():
+  obj = HERE / "lib.o"
+  subprocess.run(
+    ["llc", "-O1", "-mtriple=wasm32", "-filetype=obj", HERE / "lib.ll", "-o",
+     obj],
+    check=True)
+  module = obj.read_bytes()
+  obj.unlink()
+
+  # The linker's sections and the producers aren't part of a linked module.
+  kept = [(name, section) for _, name, section in sections(module)
+          if name is None or name.startswith(".debug_")]
+  code = b"".join(section for name, section in kept if name is None)
+  dwarf = b"".join(section for name, section in kept if name is not None)
+  trailer = function_names(["compute", "exported_only"]) + custom("build_id", BUILD_ID)
+
+  header = module[:8]
+  (HERE / "lib.debug.wasm").write_bytes(header + code + dwarf + trailer)
+  (HERE / "lib.wasm").write_bytes(
+    header + code + trailer +
+    custom("external_debug_info", name_bytes("lib.debug.wasm")))
+
+
+if __name__ == "__main__":
+  main()
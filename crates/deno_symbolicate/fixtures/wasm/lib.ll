@@ -0,0 +1,44 @@
+target datalayout = "e-m:e-p:32:32-p10:8:8-p20:8:8-i64:64-n32:64-S128-ni:1:10:20"
+target triple = "wasm32-unknown-unknown"
+
+define i32 @compute(i32 %n) !dbg !10 {
+entry:
+  %c = icmp sgt i32 %n, 0, !dbg !20
+  br i1 %c, label %loop, label %exit, !dbg !20
+
+loop:
+  %i = phi i32 [ 0, %entry ], [ %i.next, %loop ], !dbg !21
+  %acc = phi i32 [ 0, %entry ], [ %acc.next, %loop ], !dbg !21
+  %sq = mul i32 %i, %i, !dbg !21
+  %acc.next = add i32 %acc, %sq, !dbg !21
+  %i.next = add i32 %i, 1, !dbg !22
+  %done = icmp eq i32 %i.next, %n, !dbg !22
+  br i1 %done, label %exit, label %loop, !dbg !22
+
+exit:
+  %r = phi i32 [ 0, %entry ], [ %acc.next, %loop ], !dbg !23
+  %r2 = mul i32 %r, 3, !dbg !23
+  ret i32 %r2, !dbg !23
+}
+
+define i32 @exported_only(i32 %x) {
+  %y = add i32 %x, 7
+  ret i32 %y
+}
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2, !3}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_C11, file: !1, producer: "clang", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "lib.c", directory: "/src")
+!2 = !{i32 7, !"Dwarf Version", i32 4}
+!3 = !{i32 2, !"Debug Info Version", i32 3}
+!4 = !DISubroutineType(types: !5)
+!5 = !{null}
+!10 = distinct !DISubprogram(name: "compute", scope: !1, file: !1, line: 8, type: !4, scopeLine: 8, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!11 = distinct !DISubprogram(name: "helper", scope: !1, file: !1, line: 1, type: !4, scopeLine: 1, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!20 = !DILocation(line: 9, column: 3, scope: !10)
+!24 = distinct !DILocation(line: 10, column: 10, scope: !10)
+!21 = !DILocation(line: 3, column: 5, scope: !11, inlinedAt: !24)
+!22 = !DILocation(line: 2, column: 3, scope: !11, inlinedAt: !24)
+!23 = !DILocation(line: 11, column: 3, scope: !10)
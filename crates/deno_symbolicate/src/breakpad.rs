@@ -0,0 +1,331 @@
+//! Writes Breakpad text symbol files (`.sym`), to share symbols with
+//! Breakpad and Crashpad based tooling.
+//!
+//! The files have `FILE`, `INLINE_ORIGIN`, `FUNC`/`INLINE` with their line
+//! records, and `PUBLIC` records for the symbols no function covers, but no
+//! `STACK` (unwind) records. Breakpad symbol files are read like any other
+//! debug file, e.g. by [`create_symcache`](crate::create_symcache).
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use symbolic::common::ByteView;
+use symbolic::debuginfo::{FileEntry, FileFormat, Function, Object};
+
+use crate::{SliceSelector, SymbolicateError, parse_object};
+
+/// Writes the Breakpad symbols of the object picked by `selector` in
+/// `debug_file` to `out`. `name` is the debug file's name for the `MODULE`
+/// record. For Windows executables, the name of their PDB is used instead,
+/// as Breakpad does.
+pub fn write_breakpad_symbols(
+  debug_file: &[u8],
+  selector: SliceSelector,
+  name: &str,
+  mut out: impl Write,
+) -> Result<(), SymbolicateError> {
+  let byteview = ByteView::from_slice(debug_file);
+  let object = parse_object(&byteview, selector)?;
+  let session = object
+    .debug_session()
+    .map_err(SymbolicateError::InvalidDebugFile)?;
+  let mut functions = session
+    .functions()
+    .filter(|function| !function.as_ref().is_ok_and(|f| f.size == 0))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(SymbolicateError::InvalidDebugFile)?;
+  functions.sort_by_key(|function| function.address);
+
+  let mut records = Records::default();
+  let functions = functions
+    .iter()
+    .map(|function| records.function(function))
+    .collect::<Vec<_>>();
+
+  let pdb_name = match &object {
+    Object::Pe(pe) => pe
+      .debug_file_name()
+      .and_then(|path| path.rsplit(['\\', '/']).next().map(str::to_owned)),
+    _ => None,
+  };
+  let os = match object.file_format() {
+    FileFormat::Elf => "Linux",
+    FileFormat::MachO => "mac",
+    FileFormat::Pe | FileFormat::Pdb => "windows",
+    _ => "unknown",
+  };
+  writeln!(
+    out,
+    "MODULE {os} {} {} {}",
+    object.arch().name(),
+    object.debug_id().breakpad(),
+    pdb_name.as_deref().unwrap_or(name),
+  )?;
+  if let Some(code_id) = object.code_id() {
+    match pdb_name {
+      Some(_) => writeln!(out, "INFO CODE_ID {} {name}", code_id.as_str())?,
+      None => writeln!(out, "INFO CODE_ID {}", code_id.as_str())?,
+    }
+  }
+  for (index, file) in records.files.iter().enumerate() {
+    writeln!(out, "FILE {index} {file}")?;
+  }
+  for (index, origin) in records.origins.iter().enumerate() {
+    writeln!(out, "INLINE_ORIGIN {index} {origin}")?;
+  }
+  for function in &functions {
+    writeln!(
+      out,
+      "FUNC {:x} {:x} 0 {}",
+      function.address, function.size, function.name
+    )?;
+    for inline in &function.inlines {
+      writeln!(
+        out,
+        "INLINE {} {} {} {} {:x} {:x}",
+        inline.depth,
+        inline.call_line,
+        inline.call_file,
+        inline.origin,
+        inline.address,
+        inline.size
+      )?;
+    }
+    for (start, (end, file, line)) in &function.lines {
+      writeln!(out, "{start:x} {:x} {line} {file}", end - start)?;
+    }
+  }
+
+  let ranges = functions
+    .iter()
+    .map(|function| (function.address, function.address + function.size))
+    .collect::<Vec<_>>();
+  let mut symbols = object
+    .symbols()
+    .filter_map(|symbol| Some((symbol.address, symbol.name?)))
+    .collect::<Vec<_>>();
+  symbols.sort_by_key(|&(address, _)| address);
+  symbols.dedup_by_key(|&mut (address, _)| address);
+  for (address, name) in symbols {
+    let index = ranges.partition_point(|&(start, _)| start <= address);
+    let covered = index > 0 && address < ranges[index - 1].1;
+    if !covered {
+      writeln!(out, "PUBLIC {address:x} 0 {name}")?;
+    }
+  }
+  out.flush()?;
+  Ok(())
+}
+
+/// The file and inline origin tables, which the functions refer to by index.
+#[derive(Default)]
+struct Records {
+  files: Vec<String>,
+  file_indices: HashMap<String, usize>,
+  origins: Vec<String>,
+  origin_indices: HashMap<String, usize>,
+}
+
+struct FuncRecord {
+  address: u64,
+  size: u64,
+  name: String,
+  inlines: Vec<InlineRecord>,
+  /// The innermost line at each address, by start address, as `(end, file,
+  /// line)`.
+  lines: BTreeMap<u64, (u64, usize, u64)>,
+}
+
+struct InlineRecord {
+  depth: usize,
+  call_line: u64,
+  call_file: usize,
+  origin: usize,
+  address: u64,
+  size: u64,
+}
+
+/// A line record of a function, with its end address.
+struct Line {
+  start: u64,
+  end: u64,
+  file: usize,
+  line: u64,
+}
+
+fn index_of(
+  values: &mut Vec<String>,
+  indices: &mut HashMap<String, usize>,
+  value: String,
+) -> usize {
+  *indices.entry(value).or_insert_with_key(|value| {
+    values.push(value.clone());
+    values.len() - 1
+  })
+}
+
+impl Records {
+  fn function(&mut self, function: &Function<'_>) -> FuncRecord {
+    let mut record = FuncRecord {
+      address: function.address,
+      size: function.size,
+      name: function.name.as_str().to_owned(),
+      inlines: Vec::new(),
+      lines: BTreeMap::new(),
+    };
+    let lines = self.lines(function);
+    for line in &lines {
+      paint(&mut record.lines, line);
+    }
+    self.inlinees(function, &lines, 0, &mut record);
+    record
+  }
+
+  /// Adds the inlinees of `function`, whose own lines are `lines`, and
+  /// paints their lines over the lines of the functions they're inlined in.
+  fn inlinees(
+    &mut self,
+    function: &Function<'_>,
+    lines: &[Line],
+    depth: usize,
+    record: &mut FuncRecord,
+  ) {
+    for inlinee in &function.inlinees {
+      // The inlining function's line at the inlinee is the call site.
+      let call = lines.iter().find(|line| {
+        line.start <= inlinee.address && inlinee.address < line.end
+      });
+      let origin = index_of(
+        &mut self.origins,
+        &mut self.origin_indices,
+        inlinee.name.as_str().to_owned(),
+      );
+      record.inlines.push(InlineRecord {
+        depth,
+        call_line: call.map_or(0, |line| line.line),
+        call_file: call.map_or(0, |line| line.file),
+        origin,
+        address: inlinee.address,
+        size: inlinee.size,
+      });
+      let inlinee_lines = self.lines(inlinee);
+      for line in &inlinee_lines {
+        paint(&mut record.lines, line);
+      }
+      self.inlinees(inlinee, &inlinee_lines, depth + 1, record);
+    }
+  }
+
+  /// The lines of a function (without its inlinees). Lines without a size
+  /// end where the next one starts.
+  fn lines(&mut self, function: &Function<'_>) -> Vec<Line> {
+    let mut lines = function.lines.iter().collect::<Vec<_>>();
+    lines.sort_by_key(|line| line.address);
+    let mut result = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+      let next = lines
+        .get(index + 1)
+        .map_or(function.end_address(), |next| next.address);
+      let end = match line.size {
+        Some(size) => line.address + size,
+        None => next,
+      };
+      if end <= line.address {
+        continue;
+      }
+      let path = FileEntry::new(
+        Cow::Borrowed(function.compilation_dir),
+        line.file.clone(),
+      )
+      .abs_path_str();
+      let file = index_of(&mut self.files, &mut self.file_indices, path);
+      result.push(Line {
+        start: line.address,
+        end,
+        file,
+        line: line.line,
+      });
+    }
+    result
+  }
+}
+
+/// Adds `line` to the disjoint line records in `lines`, replacing the parts
+/// of the records it overlaps.
+fn paint(lines: &mut BTreeMap<u64, (u64, usize, u64)>, line: &Line) {
+  let overlapping = lines
+    .range(..line.end)
+    .rev()
+    .take_while(|&(_, &(end, ..))| end > line.start)
+    .map(|(&start, _)| start)
+    .collect::<Vec<_>>();
+  for start in overlapping {
+    let (end, file, number) = lines.remove(&start).unwrap();
+    if start < line.start {
+      lines.insert(start, (line.start, file, number));
+    }
+    if end > line.end {
+      lines.insert(line.end, (end, file, number));
+    }
+  }
+  lines.insert(line.start, (line.end, line.file, line.line));
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+  use crate::{create_symcache, parse_symcache};
+
+  fn lookups(
+    symcache: &[u8],
+    addrs: &[u64],
+  ) -> Vec<Vec<(String, String, u32)>> {
+    let symcache = parse_symcache(symcache).unwrap();
+    addrs
+      .iter()
+      .map(|&addr| {
+        symcache
+          .lookup(addr)
+          .map(|location| {
+            (
+              location.function().name().to_owned(),
+              location
+                .file()
+                .map(|file| file.full_path())
+                .unwrap_or_default(),
+              location.line(),
+            )
+          })
+          .collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn round_trip() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    for (path, name, addrs) in [
+      ("elf/plain.debug", "plain", &[0x1160, 0x1170, 0x1190][..]),
+      ("pe/app.pdb", "app.pdb", &[0x1010, 0x101c, 0x102a][..]),
+    ] {
+      let debug_file = std::fs::read(fixtures.join(path)).unwrap();
+      let mut sym = Vec::new();
+      write_breakpad_symbols(&debug_file, SliceSelector::Only, name, &mut sym)
+        .unwrap();
+      let text = String::from_utf8(sym.clone()).unwrap();
+      assert!(text.starts_with("MODULE "), "{text}");
+      assert!(text.contains("\nINLINE_ORIGIN "), "{text}");
+
+      let expected = create_symcache(&debug_file, SliceSelector::Only).unwrap();
+      let actual = create_symcache(&sym, SliceSelector::Only).unwrap();
+      assert_eq!(lookups(&actual, addrs), lookups(&expected, addrs), "{path}");
+      assert_eq!(
+        parse_symcache(&actual).unwrap().debug_id(),
+        parse_symcache(&expected).unwrap().debug_id()
+      );
+    }
+  }
+}
@@ -0,0 +1,529 @@
+//! Finds the separate debug info of stripped ELF objects, the way GDB does.
+//!
+//! Release builds are usually stripped, with the debug info in a separate
+//! file that is looked up by
+//!
+//! - the object's build ID, as `<dir>/.build-id/ab/cdef….debug` in each
+//!   search directory, or
+//! - the file name in its `.gnu_debuglink` section, next to the object, in a
+//!   `.debug` directory next to it, or under `<dir>/<the object's
+//!   directory>`. The file's CRC has to match the debug link.
+//!
+//! Units compiled with `-gsplit-dwarf` only leave a skeleton in the object
+//! (or its debug file). Their debug info is read from a `.dwp` package named
+//! after the object, or else from the `.dwo` files they name, relative to
+//! their compilation directory or in the search directories.
+//!
+//! Windows executables name their PDB, which is looked up next to them or in
+//! the search directories (see [`find_pdb`]). Passing a PDB with its
+//! executable adds the executable's exports.
+//!
+//! WebAssembly modules name their separate DWARF file in their
+//! `external_debug_info` section (see [`find_wasm_debug_file`]).
+
+use std::path::{Path, PathBuf};
+
+use symbolic::common::ByteView;
+use symbolic::debuginfo::elf::ElfObject;
+use symbolic::symcache::SymCacheConverter;
+
+use crate::SymbolicateError;
+
+mod pe;
+mod split_dwarf;
+mod wasm;
+
+pub use pe::find_pdb;
+pub(crate) use pe::{converter_with_executable, pe_converter};
+pub(crate) use wasm::wasm_converter;
+pub use wasm::{external_debug_info, find_wasm_debug_file};
+
+/// Where to look for the companion debug files of an object.
+#[derive(Debug, Clone, Default)]
+pub struct DebugFileSearch {
+  /// Companion files to use: a separate debug file, a `.dwp` package or
+  /// `.dwo` files, the `.pdb` of an executable or the `.exe`/`.dll` of a
+  /// PDB, or the `.wasm` debug file of a wasm module, told apart by their
+  /// extension. A separate debug file or PDB passed here has to belong to
+  /// the object.
+  pub files: Vec<PathBuf>,
+  /// Directories to search, like GDB's `debug-file-directory` (e.g.
+  /// `/usr/lib/debug`).
+  pub dirs: Vec<PathBuf>,
+}
+
+/// The companion files a symcache was created with.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResolvedDebugFiles {
+  /// The separate debug file of a stripped object or wasm module, or an
+  /// executable's PDB.
+  pub debug_file: Option<PathBuf>,
+  /// The executable of a PDB, whose exports were added.
+  pub executable: Option<PathBuf>,
+  /// The package split DWARF units were read from.
+  pub dwp: Option<PathBuf>,
+  /// The `.dwo` files split DWARF units were read from.
+  pub dwo_files: Vec<PathBuf>,
+  /// The `.dwo` files of split DWARF units that weren't found. Their
+  /// functions are only covered by the symbol table.
+  pub missing_dwo: Vec<String>,
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+  path
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut path = path.as_os_str().to_owned();
+  path.push(suffix);
+  path.into()
+}
+
+/// Whether `candidate` is the debug file of `object`: it has the same build
+/// ID, or matches the debug link's CRC.
+fn is_debug_file_of(candidate: &[u8], object: &ElfObject<'_>) -> bool {
+  let Ok(debug_object) = ElfObject::parse(candidate) else {
+    return false;
+  };
+  if !debug_object.has_debug_info() {
+    return false;
+  }
+  if object.code_id().is_some() && debug_object.debug_id() == object.debug_id()
+  {
+    return true;
+  }
+  matches!(
+    object.debug_link(),
+    Ok(Some(link)) if crc32fast::hash(candidate) == link.crc()
+  )
+}
+
+/// Finds the separate debug file of the object at `path`.
+pub fn find_debug_file(
+  object: &ElfObject<'_>,
+  path: &Path,
+  search: &DebugFileSearch,
+) -> Result<Option<PathBuf>, SymbolicateError> {
+  for file in &search.files {
+    if has_extension(file, "dwo") || has_extension(file, "dwp") {
+      continue;
+    }
+    if !is_debug_file_of(&ByteView::open(file)?, object) {
+      return Err(SymbolicateError::UnrelatedDebugFile {
+        path: file.clone(),
+        debug_id: object.debug_id(),
+      });
+    }
+    return Ok(Some(file.clone()));
+  }
+
+  let mut candidates = Vec::new();
+  if let Some(code_id) = object.code_id() {
+    let code_id = code_id.as_str();
+    if code_id.len() > 2 {
+      for dir in &search.dirs {
+        candidates.push(
+          dir
+            .join(".build-id")
+            .join(&code_id[..2])
+            .join(format!("{}.debug", &code_id[2..])),
+        );
+      }
+    }
+  }
+  if let Ok(Some(link)) = object.debug_link()
+    && let Ok(name) = link.filename().to_str()
+  {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    candidates.push(dir.join(name));
+    candidates.push(dir.join(".debug").join(name));
+    if let Ok(dir) = dir.canonicalize() {
+      let relative = dir.strip_prefix("/").unwrap_or(&dir);
+      for search_dir in &search.dirs {
+        candidates.push(search_dir.join(relative).join(name));
+      }
+    }
+  }
+  for candidate in candidates {
+    // The debug link may name the object itself.
+    if candidate.is_file()
+      && candidate.canonicalize().ok() != path.canonicalize().ok()
+      && is_debug_file_of(&ByteView::open(&candidate)?, object)
+    {
+      return Ok(Some(candidate));
+    }
+  }
+  Ok(None)
+}
+
+fn dwarf_error(
+  error: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> SymbolicateError {
+  SymbolicateError::InvalidDwarf(error.into())
+}
+
+fn process_functions(
+  converter: &mut SymCacheConverter<'_>,
+  object: &ElfObject<'_>,
+) -> Result<(), SymbolicateError> {
+  let session = object.debug_session().map_err(dwarf_error)?;
+  for function in session.functions() {
+    converter.process_symbolic_function(&function.map_err(dwarf_error)?);
+  }
+  Ok(())
+}
+
+/// Collects the debug info of an ELF object and its companion files into a
+/// symcache converter.
+pub(crate) fn elf_converter(
+  object: &ElfObject<'_>,
+  path: &Path,
+  search: &DebugFileSearch,
+) -> Result<(SymCacheConverter<'static>, ResolvedDebugFiles), SymbolicateError>
+{
+  let mut resolved = ResolvedDebugFiles::default();
+  if !object.has_debug_info() {
+    resolved.debug_file = find_debug_file(object, path, search)?;
+  }
+  let debug_view = resolved
+    .debug_file
+    .as_ref()
+    .map(ByteView::open)
+    .transpose()?;
+  let debug_object = debug_view
+    .as_ref()
+    .map(|view| ElfObject::parse(view))
+    .transpose()
+    .map_err(dwarf_error)?;
+  if !object.has_debug_info() && !object.has_symbols() && debug_object.is_none()
+  {
+    return Err(SymbolicateError::NoDebugInfo {
+      arch: object.arch(),
+      debug_id: object.debug_id(),
+    });
+  }
+
+  let mut converter = SymCacheConverter::new();
+  // Symbols only fill in the addresses no function covers, so every object's
+  // functions go first.
+  process_functions(&mut converter, object)?;
+  let mut dwp_candidates = vec![with_suffix(path, ".dwp")];
+  let skeletons = match (&debug_object, &resolved.debug_file) {
+    (Some(debug_object), Some(debug_path)) => {
+      process_functions(&mut converter, debug_object)?;
+      dwp_candidates.push(debug_path.with_extension("dwp"));
+      debug_object
+    }
+    _ => object,
+  };
+  split_dwarf::process_split_units(
+    &mut converter,
+    skeletons,
+    object.load_address(),
+    search,
+    &dwp_candidates,
+    &mut resolved,
+  )?;
+  for symbol in object.symbols() {
+    converter.process_symbolic_symbol(&symbol);
+  }
+  if let Some(debug_object) = &debug_object {
+    for symbol in debug_object.symbols() {
+      converter.process_symbolic_symbol(&symbol);
+    }
+  }
+  converter.set_arch(object.arch());
+  converter.set_debug_id(object.debug_id());
+  Ok((converter, resolved))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use symbolic::debuginfo::Object;
+
+  use crate::tests::TempDir;
+  use crate::{SliceSelector, parse_symcache, write_symcache_with_debug_files};
+
+  fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("fixtures")
+      .join(name)
+  }
+
+  fn fixture_dir() -> DebugFileSearch {
+    DebugFileSearch {
+      files: Vec::new(),
+      dirs: vec![fixture("elf")],
+    }
+  }
+
+  /// Creates a symcache for `path` and looks up `addr` in it, as
+  /// `(function, file, line)` from the innermost frame out.
+  fn lookup(
+    path: &Path,
+    search: &DebugFileSearch,
+    addr: u64,
+  ) -> (Vec<(String, String, u32)>, ResolvedDebugFiles) {
+    let mut symcache = Vec::new();
+    let (_, resolved) = write_symcache_with_debug_files(
+      path,
+      SliceSelector::Only,
+      search,
+      &mut symcache,
+    )
+    .unwrap();
+    let symcache = parse_symcache(&symcache).unwrap();
+    let frames = symcache
+      .lookup(addr)
+      .map(|location| {
+        (
+          location.function().name().to_owned(),
+          location
+            .file()
+            .map(|file| file.full_path())
+            .unwrap_or_default(),
+          location.line(),
+        )
+      })
+      .collect();
+    (frames, resolved)
+  }
+
+  /// The loop of `helper`, inlined into `compute`.
+  const INLINED_LOOP: u64 = 0x1170;
+
+  fn inlined_loop() -> Vec<(String, String, u32)> {
+    vec![
+      ("helper".into(), "lib.c".into(), 3),
+      ("compute".into(), "lib.c".into(), 10),
+    ]
+  }
+
+  #[test]
+  fn debug_link() {
+    let (frames, resolved) =
+      lookup(&fixture("elf/plain"), &Default::default(), INLINED_LOOP);
+    assert_eq!(frames, inlined_loop());
+    assert_eq!(resolved.debug_file, Some(fixture("elf/plain.debug")));
+
+    let search = DebugFileSearch {
+      files: vec![fixture("elf/split-stripped.debug")],
+      dirs: Vec::new(),
+    };
+    let mut symcache = Vec::new();
+    let result = write_symcache_with_debug_files(
+      &fixture("elf/plain"),
+      SliceSelector::Only,
+      &search,
+      &mut symcache,
+    );
+    assert_eq!(result.unwrap_err().code(), "unrelated_debug_file");
+  }
+
+  #[test]
+  fn build_id_dir() {
+    let data = std::fs::read(fixture("elf/plain")).unwrap();
+    let object = ElfObject::parse(&data).unwrap();
+    let code_id = object.code_id().unwrap();
+    let dir = TempDir::new("build-id");
+    let path = dir.0.join("plain");
+    std::fs::write(&path, &data).unwrap();
+    let debug_dir = dir.0.join("debug/.build-id").join(&code_id.as_str()[..2]);
+    std::fs::create_dir_all(&debug_dir).unwrap();
+    let debug_file =
+      debug_dir.join(format!("{}.debug", &code_id.as_str()[2..]));
+    std::fs::copy(fixture("elf/plain.debug"), &debug_file).unwrap();
+
+    let search = DebugFileSearch {
+      files: Vec::new(),
+      dirs: vec![dir.0.join("debug")],
+    };
+    assert_eq!(
+      find_debug_file(&object, &path, &search).unwrap(),
+      Some(debug_file)
+    );
+    assert_eq!(
+      find_debug_file(&object, &path, &Default::default()).unwrap(),
+      None
+    );
+  }
+
+  #[test]
+  fn split_dwarf() {
+    let dwo_files = vec![fixture("elf/lib.dwo"), fixture("elf/main.dwo")];
+    let (frames, resolved) =
+      lookup(&fixture("elf/split"), &fixture_dir(), INLINED_LOOP);
+    assert_eq!(frames, inlined_loop());
+    assert_eq!(resolved.dwo_files, dwo_files);
+
+    let search = DebugFileSearch {
+      files: dwo_files.clone(),
+      dirs: Vec::new(),
+    };
+    let (frames, _) = lookup(&fixture("elf/split"), &search, INLINED_LOOP);
+    assert_eq!(frames, inlined_loop());
+
+    // Without the `.dwo` files, only the symbol table is left.
+    let (frames, resolved) =
+      lookup(&fixture("elf/split"), &Default::default(), INLINED_LOOP);
+    assert_eq!(frames, [("compute".into(), "".into(), 0)]);
+    assert_eq!(resolved.missing_dwo, ["lib.dwo", "main.dwo"]);
+
+    let (frames, resolved) =
+      lookup(&fixture("elf/split-stripped"), &fixture_dir(), INLINED_LOOP);
+    assert_eq!(frames, inlined_loop());
+    assert_eq!(
+      resolved.debug_file,
+      Some(fixture("elf/split-stripped.debug"))
+    );
+    assert_eq!(resolved.dwo_files, dwo_files);
+
+    let (frames, resolved) =
+      lookup(&fixture("elf/split4"), &Default::default(), INLINED_LOOP);
+    assert_eq!(frames, inlined_loop());
+    assert_eq!(resolved.dwp, Some(fixture("elf/split4.dwp")));
+  }
+
+  #[test]
+  fn pdb() {
+    let compute = |inline_name: &str| {
+      vec![
+        (inline_name.into(), r"C:\src\lib.c".into(), 3),
+        ("compute".into(), r"C:\src\lib.c".into(), 10),
+      ]
+    };
+    let exported = vec![("exported_only".into(), "".into(), 0)];
+
+    // The PDB is found next to the executable.
+    let (frames, resolved) =
+      lookup(&fixture("pe/app.exe"), &Default::default(), 0x101c);
+    assert_eq!(frames, compute("helper(int)"));
+    assert_eq!(resolved.debug_file, Some(fixture("pe/app.pdb")));
+    let (frames, _) =
+      lookup(&fixture("pe/app.exe"), &Default::default(), 0x1080);
+    assert_eq!(frames, exported);
+
+    // Only the executable adds its exports.
+    let (frames, _) =
+      lookup(&fixture("pe/app.pdb"), &Default::default(), 0x1080);
+    assert_eq!(frames, []);
+    let search = DebugFileSearch {
+      files: vec![fixture("pe/app.exe")],
+      dirs: Vec::new(),
+    };
+    let (frames, resolved) = lookup(&fixture("pe/app.pdb"), &search, 0x1080);
+    assert_eq!(frames, exported);
+    assert_eq!(resolved.executable, Some(fixture("pe/app.exe")));
+
+    // A rebuilt executable has the same GUID, but another age.
+    let (frames, resolved) =
+      lookup(&fixture("pe/app-rebuilt.exe"), &Default::default(), 0x101c);
+    assert_eq!(frames, [("compute".into(), "".into(), 0)]);
+    assert_eq!(resolved.debug_file, None);
+    let search = DebugFileSearch {
+      files: vec![fixture("pe/app-rebuilt.exe")],
+      dirs: Vec::new(),
+    };
+    let mut symcache = Vec::new();
+    let result = write_symcache_with_debug_files(
+      &fixture("pe/app.pdb"),
+      SliceSelector::Only,
+      &search,
+      &mut symcache,
+    );
+    assert_eq!(result.unwrap_err().code(), "unrelated_executable");
+  }
+
+  #[test]
+  fn pdb_in_symbol_server_layout() {
+    let data = std::fs::read(fixture("pe/app.exe")).unwrap();
+    let Object::Pe(executable) = Object::parse(&data).unwrap() else {
+      panic!("not a PE");
+    };
+    let dir = TempDir::new("symbol-server");
+    let pdb_dir = dir
+      .0
+      .join("app.pdb")
+      .join(executable.debug_id().breakpad().to_string());
+    std::fs::create_dir_all(&pdb_dir).unwrap();
+    std::fs::copy(fixture("pe/app.pdb"), pdb_dir.join("app.pdb")).unwrap();
+    let path = dir.0.join("bin/app.exe");
+
+    let search = DebugFileSearch {
+      files: Vec::new(),
+      dirs: vec![dir.0.clone()],
+    };
+    assert_eq!(
+      find_pdb(&executable, &path, &search).unwrap(),
+      Some(pdb_dir.join("app.pdb"))
+    );
+    assert_eq!(
+      find_pdb(&executable, &path, &Default::default()).unwrap(),
+      None
+    );
+  }
+
+  #[test]
+  fn wasm_external_debug_info() {
+    let compute = vec![
+      ("helper".into(), "/src/lib.c".into(), 3),
+      ("compute".into(), "/src/lib.c".into(), 10),
+    ];
+    let exported = vec![("exported_only".into(), "".into(), 0)];
+
+    // Addresses are offsets into the module.
+    let (frames, resolved) =
+      lookup(&fixture("wasm/lib.debug.wasm"), &Default::default(), 0x80);
+    assert_eq!(frames, compute);
+    assert_eq!(resolved, ResolvedDebugFiles::default());
+
+    // The stripped module names its debug file, and adds its name section.
+    let (frames, resolved) =
+      lookup(&fixture("wasm/lib.wasm"), &Default::default(), 0x80);
+    assert_eq!(frames, compute);
+    assert_eq!(resolved.debug_file, Some(fixture("wasm/lib.debug.wasm")));
+    let (frames, _) =
+      lookup(&fixture("wasm/lib.wasm"), &Default::default(), 0x99);
+    assert_eq!(frames, exported);
+
+    // The file name of the debug file is looked up in the search
+    // directories.
+    let dir = TempDir::new("wasm-debug-dir");
+    let path = dir.0.join("lib.wasm");
+    std::fs::copy(fixture("wasm/lib.wasm"), &path).unwrap();
+    let data = std::fs::read(&path).unwrap();
+    let Object::Wasm(module) = Object::parse(&data).unwrap() else {
+      panic!("not a wasm module");
+    };
+    assert_eq!(external_debug_info(&module), Some("lib.debug.wasm"));
+    assert_eq!(
+      find_wasm_debug_file(&module, &path, &Default::default()).unwrap(),
+      None
+    );
+    let search = DebugFileSearch {
+      files: Vec::new(),
+      dirs: vec![fixture("wasm")],
+    };
+    assert_eq!(
+      find_wasm_debug_file(&module, &path, &search).unwrap(),
+      Some(fixture("wasm/lib.debug.wasm"))
+    );
+
+    // A debug file passed explicitly has to be the module's.
+    let search = DebugFileSearch {
+      files: vec![fixture("wasm/lib.wasm")],
+      dirs: Vec::new(),
+    };
+    let mut symcache = Vec::new();
+    let result = write_symcache_with_debug_files(
+      &path,
+      SliceSelector::Only,
+      &search,
+      &mut symcache,
+    );
+    assert_eq!(result.unwrap_err().code(), "unrelated_debug_file");
+  }
+}
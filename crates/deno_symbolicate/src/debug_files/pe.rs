@@ -0,0 +1,131 @@
+//! Pairs Windows executables (PE images) with their PDBs.
+//!
+//! An executable names its PDB, along with the PDB's GUID and age, in its
+//! CodeView record. The PDB has the functions and line tables, and the
+//! executable adds its exports, which cover functions the PDB has no debug
+//! info for (e.g. from libraries linked without it).
+
+use std::path::{Path, PathBuf};
+
+use symbolic::common::ByteView;
+use symbolic::debuginfo::Object;
+use symbolic::debuginfo::pe::PeObject;
+use symbolic::symcache::SymCacheConverter;
+
+use super::{DebugFileSearch, ResolvedDebugFiles, has_extension};
+use crate::{SymbolicateError, object_converter};
+
+/// Collects the debug info of a PDB (or any other debug file) and the
+/// symbols of its executable into a symcache converter. The executable has
+/// to have the debug file's debug ID.
+pub(crate) fn converter_with_executable(
+  debug_file: &Object<'_>,
+  executable: &Object<'_>,
+) -> Result<SymCacheConverter<'static>, SymbolicateError> {
+  if executable.debug_id() != debug_file.debug_id() {
+    return Err(SymbolicateError::UnrelatedExecutable {
+      debug_id: debug_file.debug_id(),
+      executable: executable.debug_id(),
+    });
+  }
+  // Functions take precedence over symbols, so the exports only fill in
+  // what the debug file doesn't cover.
+  let mut converter = object_converter(debug_file)?;
+  for symbol in executable.symbols() {
+    converter.process_symbolic_symbol(&symbol);
+  }
+  Ok(converter)
+}
+
+/// Finds the PDB of the executable at `path`: one in `search.files`, or the
+/// file the executable names next to it or in the search directories, also
+/// in symbol server layout (`<dir>/<name>/<DEBUG_ID>/<name>`).
+pub fn find_pdb(
+  executable: &PeObject<'_>,
+  path: &Path,
+  search: &DebugFileSearch,
+) -> Result<Option<PathBuf>, SymbolicateError> {
+  let debug_id = executable.debug_id();
+  if let Some(file) = search.files.iter().find(|f| has_extension(f, "pdb")) {
+    let view = ByteView::open(file)?;
+    if !Object::parse(&view).is_ok_and(|pdb| pdb.debug_id() == debug_id) {
+      return Err(SymbolicateError::UnrelatedDebugFile {
+        path: file.clone(),
+        debug_id,
+      });
+    }
+    return Ok(Some(file.clone()));
+  }
+
+  // The name is the PDB's path on the machine that built it.
+  let Some(name) = executable
+    .debug_file_name()
+    .and_then(|name| name.rsplit(['\\', '/']).next().map(str::to_owned))
+    .filter(|name| !name.is_empty())
+  else {
+    return Ok(None);
+  };
+  let mut candidates = vec![path.with_file_name(&name)];
+  for dir in &search.dirs {
+    candidates.push(dir.join(&name));
+    candidates.push(
+      dir
+        .join(&name)
+        .join(debug_id.breakpad().to_string())
+        .join(&name),
+    );
+  }
+  for candidate in candidates {
+    if !candidate.is_file() {
+      continue;
+    }
+    let view = ByteView::open(&candidate)?;
+    // A PDB of another build of the executable has another age.
+    if Object::parse(&view).is_ok_and(|pdb| pdb.debug_id() == debug_id) {
+      return Ok(Some(candidate));
+    }
+  }
+  Ok(None)
+}
+
+/// Collects the debug info of a PE executable or a PDB into a symcache
+/// converter, along with the PDB or executable found through `search`.
+pub(crate) fn pe_converter(
+  object: &Object<'_>,
+  path: &Path,
+  search: &DebugFileSearch,
+) -> Result<(SymCacheConverter<'static>, ResolvedDebugFiles), SymbolicateError>
+{
+  let mut resolved = ResolvedDebugFiles::default();
+  let converter = match object {
+    Object::Pe(executable) => {
+      resolved.debug_file = find_pdb(executable, path, search)?;
+      match &resolved.debug_file {
+        Some(pdb_path) => {
+          let view = ByteView::open(pdb_path)?;
+          let pdb =
+            Object::parse(&view).map_err(SymbolicateError::InvalidDebugFile)?;
+          converter_with_executable(&pdb, object)?
+        }
+        None => object_converter(object)?,
+      }
+    }
+    _ => {
+      resolved.executable = search
+        .files
+        .iter()
+        .find(|file| has_extension(file, "exe") || has_extension(file, "dll"))
+        .cloned();
+      match &resolved.executable {
+        Some(executable_path) => {
+          let view = ByteView::open(executable_path)?;
+          let executable =
+            Object::parse(&view).map_err(SymbolicateError::InvalidDebugFile)?;
+          converter_with_executable(object, &executable)?
+        }
+        None => object_converter(object)?,
+      }
+    }
+  };
+  Ok((converter, resolved))
+}
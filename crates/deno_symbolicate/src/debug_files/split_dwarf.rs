@@ -0,0 +1,513 @@
+//! Reads split DWARF units, of which `symbolic` only sees the skeletons.
+//!
+//! A skeleton unit names its `.dwo` file and has the ID of the split unit in
+//! it. The split unit has the functions, but its addresses, ranges and line
+//! table are in (or relative to) the object with the skeleton.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use gimli::{
+  AttributeValue, DebuggingInformationEntry, DwarfPackageSections,
+  DwarfSections, EndianSlice, LineProgramHeader, RunTimeEndian, SectionId,
+};
+use symbolic::common::{ByteView, Language, Name, NameMangling};
+use symbolic::debuginfo::FileInfo;
+use symbolic::debuginfo::dwarf::Dwarf as _;
+use symbolic::debuginfo::elf::ElfObject;
+use symbolic::debuginfo::function_builder::FunctionBuilder;
+use symbolic::symcache::SymCacheConverter;
+
+use super::{DebugFileSearch, ResolvedDebugFiles, dwarf_error, has_extension};
+use crate::SymbolicateError;
+
+type Reader<'d> = EndianSlice<'d, RunTimeEndian>;
+type Dwarf<'d> = gimli::Dwarf<Reader<'d>>;
+type Unit<'d> = gimli::Unit<Reader<'d>>;
+
+/// How many `DW_AT_abstract_origin` or `DW_AT_specification` references are
+/// followed to find a function's name.
+const MAX_NAME_REFERENCES: usize = 8;
+
+/// Loads the DWARF sections of an object, or the `.dwo` sections of a split
+/// DWARF file. Compressed sections are decompressed.
+fn load_sections<'d>(
+  object: &ElfObject<'d>,
+  dwo: bool,
+) -> DwarfSections<Cow<'d, [u8]>> {
+  let Ok(sections) = DwarfSections::load(|id| {
+    Ok::<_, std::convert::Infallible>(section(object, id, dwo))
+  });
+  sections
+}
+
+fn section<'d>(
+  object: &ElfObject<'d>,
+  id: SectionId,
+  dwo: bool,
+) -> Cow<'d, [u8]> {
+  let name = if dwo { id.dwo_name() } else { Some(id.name()) };
+  // `symbolic` takes section names without the leading dot.
+  name
+    .and_then(|name| object.section(&name[1..]))
+    .map_or(Cow::Borrowed(&[]), |section| section.data)
+}
+
+/// Reads the split units of the skeleton units in `object` into the
+/// converter, from a `.dwp` package (given in `search` or the first of
+/// `dwp_candidates` that exists) or their `.dwo` files.
+pub(super) fn process_split_units(
+  converter: &mut SymCacheConverter<'_>,
+  object: &ElfObject<'_>,
+  load_address: u64,
+  search: &DebugFileSearch,
+  dwp_candidates: &[PathBuf],
+  resolved: &mut ResolvedDebugFiles,
+) -> Result<(), SymbolicateError> {
+  let endian = object.endianity();
+  let sections = load_sections(object, false);
+  let dwarf = sections.borrow(|section| EndianSlice::new(section, endian));
+  let mut skeletons = Vec::new();
+  let mut headers = dwarf.units();
+  while let Some(header) = headers.next().map_err(dwarf_error)? {
+    let unit = dwarf.unit(header).map_err(dwarf_error)?;
+    if unit.dwo_id.is_some() {
+      skeletons.push(unit);
+    }
+  }
+  if skeletons.is_empty() {
+    return Ok(());
+  }
+
+  let dwp_path = search
+    .files
+    .iter()
+    .find(|file| has_extension(file, "dwp"))
+    .or_else(|| dwp_candidates.iter().find(|path| path.is_file()));
+  let dwp_view = dwp_path.map(ByteView::open).transpose()?;
+  let dwp_object = dwp_view
+    .as_ref()
+    .map(|view| ElfObject::parse(view))
+    .transpose()
+    .map_err(dwarf_error)?;
+  let dwp_sections = dwp_object
+    .as_ref()
+    .map(|object| {
+      DwarfPackageSections::load(|id| {
+        Ok::<_, gimli::Error>(section(object, id, true))
+      })
+    })
+    .transpose()
+    .map_err(dwarf_error)?;
+  let package = dwp_sections
+    .as_ref()
+    .map(|sections| {
+      sections.borrow(
+        |section| EndianSlice::new(section, endian),
+        EndianSlice::new(&[], endian),
+      )
+    })
+    .transpose()
+    .map_err(dwarf_error)?;
+  if package.is_some() {
+    resolved.dwp = dwp_path.cloned();
+  }
+
+  for skeleton in &skeletons {
+    let dwo_id = skeleton.dwo_id.unwrap();
+    if let Some(package) = &package
+      && let Some(split) =
+        package.find_cu(dwo_id, &dwarf).map_err(dwarf_error)?
+      && process_split_unit(converter, &dwarf, &split, skeleton, load_address)?
+    {
+      continue;
+    }
+
+    let dwo_name = skeleton
+      .dwo_name()
+      .ok()
+      .flatten()
+      .and_then(|name| dwarf.attr_string(skeleton, name).ok())
+      .map(|name| name.to_string_lossy().into_owned());
+    let Some(dwo_name) = dwo_name else {
+      resolved.missing_dwo.push(format!("{:#018x}", dwo_id.0));
+      continue;
+    };
+    let comp_dir = skeleton.comp_dir.map(|dir| dir.to_string_lossy());
+    let mut found = false;
+    for candidate in dwo_candidates(&dwo_name, comp_dir.as_deref(), search) {
+      if !candidate.is_file() {
+        continue;
+      }
+      let view = ByteView::open(&candidate)?;
+      let Ok(dwo_object) = ElfObject::parse(&view) else {
+        continue;
+      };
+      let dwo_sections = load_sections(&dwo_object, true);
+      let mut split =
+        dwo_sections.borrow(|section| EndianSlice::new(section, endian));
+      split.make_dwo(&dwarf);
+      // A `.dwo` file from another build has a different ID.
+      if process_split_unit(converter, &dwarf, &split, skeleton, load_address)?
+      {
+        resolved.dwo_files.push(candidate);
+        found = true;
+        break;
+      }
+    }
+    if !found {
+      resolved.missing_dwo.push(dwo_name);
+    }
+  }
+  Ok(())
+}
+
+fn dwo_candidates(
+  dwo_name: &str,
+  comp_dir: Option<&str>,
+  search: &DebugFileSearch,
+) -> Vec<PathBuf> {
+  let dwo_name = Path::new(dwo_name);
+  let file_name = dwo_name.file_name().unwrap_or_default();
+  let mut candidates = search
+    .files
+    .iter()
+    .filter(|file| file.file_name() == Some(file_name))
+    .cloned()
+    .collect::<Vec<_>>();
+  match comp_dir {
+    Some(comp_dir) => candidates.push(Path::new(comp_dir).join(dwo_name)),
+    None => candidates.push(dwo_name.to_path_buf()),
+  }
+  for dir in &search.dirs {
+    if dwo_name.is_relative() {
+      candidates.push(dir.join(dwo_name));
+    }
+    candidates.push(dir.join(file_name));
+  }
+  candidates
+}
+
+/// Processes the split unit for `skeleton` in `split`, if it's there.
+fn process_split_unit(
+  converter: &mut SymCacheConverter<'_>,
+  parent: &Dwarf<'_>,
+  split: &Dwarf<'_>,
+  skeleton: &Unit<'_>,
+  load_address: u64,
+) -> Result<bool, SymbolicateError> {
+  let mut headers = split.units();
+  while let Some(header) = headers.next().map_err(dwarf_error)? {
+    let mut unit = split.unit(header).map_err(dwarf_error)?;
+    if unit.dwo_id != skeleton.dwo_id {
+      continue;
+    }
+    unit.copy_relocated_attributes(skeleton);
+    let functions =
+      UnitReader::new(parent, split, &unit, skeleton, load_address)
+        .and_then(|reader| reader.functions())
+        .map_err(dwarf_error)?;
+    for function in functions {
+      converter.process_symbolic_function(&function.finish());
+    }
+    return Ok(true);
+  }
+  Ok(false)
+}
+
+/// A line table row, with absolute addresses.
+struct Row {
+  address: u64,
+  end: u64,
+  file: u64,
+  line: u64,
+}
+
+/// Reads the functions of a split unit.
+struct UnitReader<'a, 'd> {
+  /// The DWARF of the object with the skeleton, which has the strings of the
+  /// skeleton's line table.
+  parent: &'a Dwarf<'d>,
+  dwarf: &'a Dwarf<'d>,
+  unit: &'a Unit<'d>,
+  skeleton: &'a Unit<'d>,
+  load_address: u64,
+  language: Language,
+  /// The line table is the skeleton's, as the split unit has no code.
+  line_header: Option<LineProgramHeader<Reader<'d>>>,
+  rows: Vec<Row>,
+  files: HashMap<u64, FileInfo<'static>>,
+}
+
+impl<'a, 'd> UnitReader<'a, 'd> {
+  fn new(
+    parent: &'a Dwarf<'d>,
+    dwarf: &'a Dwarf<'d>,
+    unit: &'a Unit<'d>,
+    skeleton: &'a Unit<'d>,
+    load_address: u64,
+  ) -> Result<Self, gimli::Error> {
+    let mut reader = UnitReader {
+      parent,
+      dwarf,
+      unit,
+      skeleton,
+      load_address,
+      language: Language::Unknown,
+      line_header: None,
+      rows: Vec::new(),
+      files: HashMap::new(),
+    };
+    let mut entries = unit.entries();
+    if let Some((_, root)) = entries.next_dfs()?
+      && let Some(AttributeValue::Language(language)) =
+        root.attr_value(gimli::DW_AT_language)?
+    {
+      reader.language = symbolic_language(language);
+    }
+    if let Some(program) = skeleton.line_program.clone() {
+      reader.line_header = Some(program.header().clone());
+      reader.rows = line_rows(program)?;
+    }
+    Ok(reader)
+  }
+
+  fn functions(mut self) -> Result<Vec<FunctionBuilder<'d>>, gimli::Error> {
+    let comp_dir = self
+      .skeleton
+      .comp_dir
+      .or(self.unit.comp_dir)
+      .map_or(&[][..], |dir| dir.slice());
+    let mut functions: Vec<(FunctionBuilder<'d>, Vec<gimli::Range>)> =
+      Vec::new();
+    // The DIE depth, the function and the inlining depth of the enclosing
+    // entries.
+    let mut scopes: Vec<(isize, Option<usize>, u32)> = Vec::new();
+    let mut depth = 0;
+    let mut entries = self.unit.entries();
+    while let Some((delta, entry)) = entries.next_dfs()? {
+      depth += delta;
+      while scopes.last().is_some_and(|&(scope, ..)| scope >= depth) {
+        scopes.pop();
+      }
+      let (function, inline_depth) = scopes
+        .last()
+        .map_or((None, 0), |&(_, function, inline_depth)| {
+          (function, inline_depth)
+        });
+      let scope = match entry.tag() {
+        gimli::DW_TAG_subprogram => {
+          let ranges = self.ranges(entry)?;
+          // Declarations and abstract instances have no code.
+          match (ranges.first(), ranges.iter().map(|range| range.end).max()) {
+            (Some(first), Some(end)) => {
+              let builder = FunctionBuilder::new(
+                self.name(entry)?,
+                comp_dir,
+                first.begin - self.load_address,
+                end - first.begin,
+              );
+              functions.push((builder, ranges));
+              (Some(functions.len() - 1), 0)
+            }
+            _ => (None, 0),
+          }
+        }
+        gimli::DW_TAG_inlined_subroutine => match function {
+          Some(index) => {
+            let name = self.name(entry)?;
+            let call_file = match entry.attr_value(gimli::DW_AT_call_file)? {
+              Some(AttributeValue::FileIndex(index)) => self.file(index),
+              _ => FileInfo::default(),
+            };
+            let call_line = entry
+              .attr_value(gimli::DW_AT_call_line)?
+              .and_then(|line| line.udata_value())
+              .unwrap_or(0);
+            for range in self.ranges(entry)? {
+              functions[index].0.add_inlinee(
+                inline_depth,
+                name.clone(),
+                range.begin - self.load_address,
+                range.end - range.begin,
+                call_file.clone(),
+                call_line,
+              );
+            }
+            (Some(index), inline_depth + 1)
+          }
+          None => (None, 0),
+        },
+        _ => (function, inline_depth),
+      };
+      scopes.push((depth, scope.0, scope.1));
+    }
+
+    let rows = std::mem::take(&mut self.rows);
+    Ok(
+      functions
+        .into_iter()
+        .map(|(mut builder, ranges)| {
+          for range in ranges {
+            let start = rows.partition_point(|row| row.end <= range.begin);
+            for row in rows[start..]
+              .iter()
+              .take_while(|row| row.address < range.end)
+            {
+              let address = row.address.max(range.begin);
+              let size = row.end.min(range.end) - address;
+              builder.add_leaf_line(
+                address - self.load_address,
+                Some(size),
+                self.file(row.file),
+                row.line,
+              );
+            }
+          }
+          builder
+        })
+        .collect(),
+    )
+  }
+
+  /// The code ranges of an entry, leaving out code that was removed by the
+  /// linker (which is left at address 0).
+  fn ranges(
+    &self,
+    entry: &DebuggingInformationEntry<'_, '_, Reader<'d>>,
+  ) -> Result<Vec<gimli::Range>, gimli::Error> {
+    let mut ranges = Vec::new();
+    let mut iter = self.dwarf.die_ranges(self.unit, entry)?;
+    while let Some(range) = iter.next()? {
+      if range.begin != 0
+        && range.begin >= self.load_address
+        && range.begin < range.end
+      {
+        ranges.push(range);
+      }
+    }
+    ranges.sort_by_key(|range| range.begin);
+    Ok(ranges)
+  }
+
+  /// The linkage name or else the name of an entry, looking through the
+  /// abstract instance or declaration it refers to.
+  fn name(
+    &self,
+    entry: &DebuggingInformationEntry<'_, '_, Reader<'d>>,
+  ) -> Result<Name<'static>, gimli::Error> {
+    let mut offset = None;
+    for _ in 0..MAX_NAME_REFERENCES {
+      let owned;
+      let entry = match offset {
+        Some(offset) => {
+          owned = self.unit.entry(offset)?;
+          &owned
+        }
+        None => entry,
+      };
+      for attr in [
+        gimli::DW_AT_linkage_name,
+        gimli::DW_AT_MIPS_linkage_name,
+        gimli::DW_AT_name,
+      ] {
+        if let Some(value) = entry.attr_value(attr)?
+          && let Ok(name) = self.dwarf.attr_string(self.unit, value)
+        {
+          return Ok(Name::new(
+            name.to_string_lossy().into_owned(),
+            NameMangling::Unknown,
+            self.language,
+          ));
+        }
+      }
+      offset = [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification]
+        .into_iter()
+        .find_map(|attr| match entry.attr_value(attr) {
+          Ok(Some(AttributeValue::UnitRef(offset))) => Some(offset),
+          _ => None,
+        });
+      if offset.is_none() {
+        break;
+      }
+    }
+    Ok(Name::new("", NameMangling::Unknown, self.language))
+  }
+
+  fn file(&mut self, index: u64) -> FileInfo<'static> {
+    if let Some(file) = self.files.get(&index) {
+      return file.clone();
+    }
+    let string = |value| {
+      self
+        .parent
+        .attr_string(self.skeleton, value)
+        .map(|string| string.to_string_lossy().into_owned().into_bytes())
+        .unwrap_or_default()
+    };
+    let file = match self
+      .line_header
+      .as_ref()
+      .and_then(|header| Some((header, header.file(index)?)))
+    {
+      Some((header, file)) => FileInfo::new(
+        Cow::Owned(file.directory(header).map(string).unwrap_or_default()),
+        Cow::Owned(string(file.path_name())),
+      ),
+      None => FileInfo::default(),
+    };
+    self.files.insert(index, file.clone());
+    file
+  }
+}
+
+/// Reads the rows of a line program, sorted by address. Each row ends where
+/// the next one in its sequence starts.
+fn line_rows(
+  program: gimli::IncompleteLineProgram<Reader<'_>>,
+) -> Result<Vec<Row>, gimli::Error> {
+  let mut rows = Vec::new();
+  let mut pending: Option<Row> = None;
+  let mut iter = program.rows();
+  while let Some((_, row)) = iter.next_row()? {
+    let address = row.address();
+    // Of several rows at the same address, the last one applies.
+    if let Some(mut previous) = pending.take()
+      && address > previous.address
+    {
+      previous.end = address;
+      rows.push(previous);
+    }
+    if !row.end_sequence() {
+      pending = Some(Row {
+        address,
+        end: address,
+        file: row.file_index(),
+        line: row.line().map_or(0, |line| line.get()),
+      });
+    }
+  }
+  rows.sort_by_key(|row| row.address);
+  Ok(rows)
+}
+
+fn symbolic_language(language: gimli::DwLang) -> Language {
+  match language {
+    gimli::DW_LANG_C
+    | gimli::DW_LANG_C89
+    | gimli::DW_LANG_C99
+    | gimli::DW_LANG_C11 => Language::C,
+    gimli::DW_LANG_C_plus_plus
+    | gimli::DW_LANG_C_plus_plus_03
+    | gimli::DW_LANG_C_plus_plus_11
+    | gimli::DW_LANG_C_plus_plus_14 => Language::Cpp,
+    gimli::DW_LANG_ObjC => Language::ObjC,
+    gimli::DW_LANG_ObjC_plus_plus => Language::ObjCpp,
+    gimli::DW_LANG_Rust => Language::Rust,
+    gimli::DW_LANG_Swift => Language::Swift,
+    gimli::DW_LANG_Go => Language::Go,
+    gimli::DW_LANG_D => Language::D,
+    _ => Language::Unknown,
+  }
+}
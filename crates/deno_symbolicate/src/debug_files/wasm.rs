@@ -0,0 +1,165 @@
+//! Pairs WebAssembly modules with their separate DWARF files.
+//!
+//! Toolchains like Emscripten (`-gseparate-dwarf`) move a module's DWARF
+//! custom sections into a separate wasm file, and name it in the module's
+//! `external_debug_info` section, usually as a URL. The debug file keeps the
+//! module's code section, so both agree on code offsets. The module adds the
+//! function names of its name section.
+
+use std::path::{Path, PathBuf};
+
+use symbolic::common::ByteView;
+use symbolic::debuginfo::Object;
+use symbolic::debuginfo::wasm::WasmObject;
+use symbolic::symcache::SymCacheConverter;
+
+use super::{
+  DebugFileSearch, ResolvedDebugFiles, converter_with_executable, has_extension,
+};
+use crate::{SymbolicateError, object_converter};
+
+fn read_leb(data: &[u8], offset: &mut usize) -> Option<u64> {
+  let mut value = 0;
+  for shift in (0..64).step_by(7) {
+    let byte = *data.get(*offset)?;
+    *offset += 1;
+    value |= u64::from(byte & 0x7f) << shift;
+    if byte & 0x80 == 0 {
+      return Some(value);
+    }
+  }
+  None
+}
+
+fn read_bytes<'d>(data: &'d [u8], offset: &mut usize) -> Option<&'d [u8]> {
+  let len = usize::try_from(read_leb(data, offset)?).ok()?;
+  let bytes = data.get(*offset..offset.checked_add(len)?)?;
+  *offset += len;
+  Some(bytes)
+}
+
+/// The contents of the first custom section called `name` in a module.
+fn custom_section<'d>(module: &'d [u8], name: &str) -> Option<&'d [u8]> {
+  // Skip the magic number and version.
+  let mut offset = 8;
+  while offset < module.len() {
+    let id = module[offset];
+    offset += 1;
+    let contents = read_bytes(module, &mut offset)?;
+    if id == 0 {
+      let mut name_offset = 0;
+      let section_name = read_bytes(contents, &mut name_offset)?;
+      if section_name == name.as_bytes() {
+        return Some(&contents[name_offset..]);
+      }
+    }
+  }
+  None
+}
+
+/// The URL or path of the module's separate debug file, from its
+/// `external_debug_info` section.
+pub fn external_debug_info<'d>(module: &WasmObject<'d>) -> Option<&'d str> {
+  let contents = custom_section(module.data(), "external_debug_info")?;
+  let url = read_bytes(contents, &mut 0)?;
+  std::str::from_utf8(url).ok().filter(|url| !url.is_empty())
+}
+
+/// Whether `candidate` has the DWARF of `module`.
+fn is_debug_file_of(candidate: &[u8], module: &WasmObject<'_>) -> bool {
+  WasmObject::parse(candidate).is_ok_and(|debug_file| {
+    debug_file.has_debug_info() && debug_file.debug_id() == module.debug_id()
+  })
+}
+
+/// Finds the separate debug file of the module at `path`: a `.wasm` file in
+/// `search.files`, or the file its `external_debug_info` section names,
+/// relative to the module or by file name in the search directories.
+pub fn find_wasm_debug_file(
+  module: &WasmObject<'_>,
+  path: &Path,
+  search: &DebugFileSearch,
+) -> Result<Option<PathBuf>, SymbolicateError> {
+  if let Some(file) = search.files.iter().find(|f| has_extension(f, "wasm")) {
+    if !is_debug_file_of(&ByteView::open(file)?, module) {
+      return Err(SymbolicateError::UnrelatedDebugFile {
+        path: file.clone(),
+        debug_id: module.debug_id(),
+      });
+    }
+    return Ok(Some(file.clone()));
+  }
+
+  let Some(url) = external_debug_info(module) else {
+    return Ok(None);
+  };
+  let mut candidates = Vec::new();
+  // A URL is only looked up by its file name.
+  let name = match url.split_once("://") {
+    Some((_, rest)) => rest.split(['?', '#']).next().unwrap_or(rest),
+    None => {
+      candidates.push(path.with_file_name(url));
+      url
+    }
+  };
+  if let Some(name) = name.rsplit(['/', '\\']).next()
+    && !name.is_empty()
+  {
+    candidates.push(path.with_file_name(name));
+    candidates.extend(search.dirs.iter().map(|dir| dir.join(name)));
+  }
+  candidates.dedup();
+  for candidate in candidates {
+    if candidate.is_file()
+      && candidate.canonicalize().ok() != path.canonicalize().ok()
+      && is_debug_file_of(&ByteView::open(&candidate)?, module)
+    {
+      return Ok(Some(candidate));
+    }
+  }
+  Ok(None)
+}
+
+/// Collects the debug info of a wasm module into a symcache converter,
+/// along with its separate debug file if its DWARF was stripped.
+pub(crate) fn wasm_converter(
+  object: &Object<'_>,
+  module: &WasmObject<'_>,
+  path: &Path,
+  search: &DebugFileSearch,
+) -> Result<(SymCacheConverter<'static>, ResolvedDebugFiles), SymbolicateError>
+{
+  let mut resolved = ResolvedDebugFiles::default();
+  if !module.has_debug_info() {
+    resolved.debug_file = find_wasm_debug_file(module, path, search)?;
+  }
+  let converter = match &resolved.debug_file {
+    Some(debug_path) => {
+      let view = ByteView::open(debug_path)?;
+      let debug_file =
+        Object::parse(&view).map_err(SymbolicateError::InvalidDebugFile)?;
+      converter_with_executable(&debug_file, object)?
+    }
+    None => object_converter(object)?,
+  };
+  Ok((converter, resolved))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn custom_sections() {
+    let module = [
+      b"\0asm\x01\0\0\0".as_slice(),
+      // A type section, then a custom section with a 3 byte name.
+      b"\x01\x01\x00\x00\x08\x03abc\x04def",
+    ]
+    .concat();
+    assert_eq!(custom_section(&module, "abc"), Some(b"\x04def".as_slice()));
+    assert_eq!(custom_section(&module, "ab"), None);
+    // A section that runs past the end of the module.
+    assert_eq!(custom_section(&module[..module.len() - 1], "abc"), None);
+  }
+}
@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use symbolic::common::DebugId;
+use symbolic::debuginfo::ObjectError;
+use symbolic::symcache::{self, ErrorKind};
+
+use crate::SliceSelector;
+
+/// Everything that can go wrong while creating or using a symcache.
+///
+/// Each variant has a stable [`code`](SymbolicateError::code), so callers (e.g.
+/// an HTTP server) can map errors to responses without matching on messages.
+#[derive(Debug, thiserror::Error)]
+pub enum SymbolicateError {
+  /// The file isn't an object file format we can read.
+  #[error("not a supported debug file: {0}")]
+  InvalidDebugFile(#[source] ObjectError),
+  /// The object has neither debug info nor a symbol table, e.g. a stripped
+  /// executable instead of its debug file.
+  #[error("the {arch} object ({debug_id}) has no debug info or symbols")]
+  NoDebugInfo {
+    arch: symbolic::common::Arch,
+    debug_id: DebugId,
+  },
+  /// A fat archive contains more than one object, and no slice was selected.
+  #[error(
+    "the debug file is a fat archive with {count} objects, select one by architecture or debug ID: {objects}"
+  )]
+  FatArchive { count: usize, objects: String },
+  /// No object in the debug file matches the selected slice.
+  #[error("no object in the debug file matches {selector:?}, found: {objects}")]
+  NoMatchingSlice {
+    selector: SliceSelector,
+    objects: String,
+  },
+  /// Converting the debug info into a symcache failed.
+  #[error("failed to convert the debug file to a symcache: {0}")]
+  Conversion(#[source] symcache::Error),
+  /// The symcache couldn't be parsed.
+  #[error("corrupt symcache: {0}")]
+  CorruptSymCache(#[source] symcache::Error),
+  /// The symcache was written by an incompatible version of `symbolic`.
+  #[error("unsupported symcache version")]
+  UnsupportedSymCacheVersion(#[source] symcache::Error),
+  #[error(transparent)]
+  DebugIdMismatch(#[from] DebugIdMismatch),
+  /// The stack trace is for a different OS or architecture than the symcache.
+  #[error("the stack trace is for {trace}, but the symcache is for {symcache}")]
+  TargetMismatch { trace: String, symcache: String },
+  /// A debug file passed explicitly belongs to a different object.
+  #[error("{} isn't a debug file of the object ({debug_id})", path.display())]
+  UnrelatedDebugFile { path: PathBuf, debug_id: DebugId },
+  /// The executable passed along with a debug file has another debug ID.
+  #[error(
+    "the executable ({executable}) isn't the one the debug file ({debug_id}) is for"
+  )]
+  UnrelatedExecutable {
+    debug_id: DebugId,
+    executable: DebugId,
+  },
+  /// The DWARF debug info of the object or its companion files couldn't be
+  /// read.
+  #[error("invalid DWARF debug info: {0}")]
+  InvalidDwarf(#[source] Box<dyn std::error::Error + Send + Sync>),
+  /// The unwind info (CFI or compact unwind info) of an object couldn't be
+  /// read.
+  #[error("invalid unwind info: {0}")]
+  InvalidUnwindInfo(#[source] Box<dyn std::error::Error + Send + Sync>),
+  /// Stack memory can't be unwound for the trace's architecture.
+  #[error("unwinding stack memory isn't supported on {0}")]
+  UnsupportedUnwindArch(String),
+  /// A debug file name or version can't be used as a symbol store path.
+  #[error("invalid {kind} for the symbol store: {value:?}")]
+  InvalidStoreKey { kind: &'static str, value: String },
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+}
+
+impl SymbolicateError {
+  /// A short, stable identifier for the kind of error.
+  pub fn code(&self) -> &'static str {
+    match self {
+      SymbolicateError::InvalidDebugFile(_) => "invalid_debug_file",
+      SymbolicateError::NoDebugInfo { .. } => "no_debug_info",
+      SymbolicateError::FatArchive { .. } => "fat_archive",
+      SymbolicateError::NoMatchingSlice { .. } => "no_matching_slice",
+      SymbolicateError::Conversion(_) => "conversion_failed",
+      SymbolicateError::CorruptSymCache(_) => "corrupt_symcache",
+      SymbolicateError::UnsupportedSymCacheVersion(_) => {
+        "unsupported_symcache_version"
+      }
+      SymbolicateError::DebugIdMismatch(_) => "debug_id_mismatch",
+      SymbolicateError::TargetMismatch { .. } => "target_mismatch",
+      SymbolicateError::UnrelatedDebugFile { .. } => "unrelated_debug_file",
+      SymbolicateError::UnrelatedExecutable { .. } => "unrelated_executable",
+      SymbolicateError::InvalidDwarf(_) => "invalid_dwarf",
+      SymbolicateError::InvalidUnwindInfo(_) => "invalid_unwind_info",
+      SymbolicateError::UnsupportedUnwindArch(_) => "unsupported_unwind_arch",
+      SymbolicateError::InvalidStoreKey { .. } => "invalid_store_key",
+      SymbolicateError::Io(_) => "io",
+    }
+  }
+
+  /// Classifies an error from parsing a symcache.
+  pub(crate) fn from_symcache_parse(error: symcache::Error) -> Self {
+    match error.kind() {
+      ErrorKind::WrongVersion => {
+        SymbolicateError::UnsupportedSymCacheVersion(error)
+      }
+      _ => SymbolicateError::CorruptSymCache(error),
+    }
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+  "the stack trace is from a build with debug ID {expected}, but the symcache has debug ID {actual}"
+)]
+pub struct DebugIdMismatch {
+  pub expected: DebugId,
+  pub actual: DebugId,
+}
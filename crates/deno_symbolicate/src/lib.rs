@@ -1,74 +1,611 @@
-use std::io::Cursor;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+use deno_stable_stacktrace::encode::{
+  self, FrameAddr, SignalInfo, StackTrace, ThreadInfo,
+};
+use deno_stable_stacktrace::{BuildId, Module};
 use symbolic::{
-  common::ByteView,
-  debuginfo::Archive,
+  common::{Arch, ByteView, CpuFamily, DebugId, Language, Uuid},
+  debuginfo::{Archive, Object},
   symcache::{SymCache, SymCacheConverter},
 };
 use symbolic_demangle::{Demangle, DemangleOptions};
 
-/// Creates an encoded `SymCache` from the contents of the debug info.
+use crate::debug_files::{DebugFileSearch, ResolvedDebugFiles};
+
+pub mod breakpad;
+pub mod debug_files;
+mod error;
+pub use error::{DebugIdMismatch, SymbolicateError};
+pub mod output;
+pub mod symstore;
+pub mod unwind;
+
+/// Selects which object of a debug file to build a symcache from. Fat (or
+/// universal) Mach-O files contain one object per architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SliceSelector {
+  /// The only object in the file. Errors for fat archives with more than one.
+  #[default]
+  Only,
+  /// The object for an architecture. If there's no exact match, an object
+  /// with a compatible CPU family is used (e.g. `arm64e` for `arm64`), as long
+  /// as there's only one.
+  Arch(Arch),
+  /// The object with the given debug ID.
+  DebugId(DebugId),
+}
+
+impl SliceSelector {
+  /// Selects the object matching the architecture from a stack trace header.
+  /// Returns `None` if `symbolic` doesn't know the architecture.
+  pub fn for_trace_arch(arch: &encode::Arch) -> Option<Self> {
+    symbolic_arch(arch).map(SliceSelector::Arch)
+  }
+}
+
+/// Converts the architecture recorded in a stack trace header into the one
+/// `symbolic` reports for debug files.
+pub fn symbolic_arch(arch: &encode::Arch) -> Option<Arch> {
+  match arch {
+    encode::Arch::X86_64 => Some(Arch::Amd64),
+    encode::Arch::Aarch64 => Some(Arch::Arm64),
+    encode::Arch::Other(other) => other.as_str().parse().ok(),
+  }
+}
+
+fn describe_objects(objects: &[Object<'_>]) -> String {
+  objects
+    .iter()
+    .map(|object| format!("{} ({})", object.arch(), object.debug_id()))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+fn select_object<'d>(
+  mut objects: Vec<Object<'d>>,
+  selector: SliceSelector,
+) -> Result<Object<'d>, SymbolicateError> {
+  let index = match selector {
+    SliceSelector::Only => {
+      if objects.len() != 1 {
+        return Err(SymbolicateError::FatArchive {
+          count: objects.len(),
+          objects: describe_objects(&objects),
+        });
+      }
+      Some(0)
+    }
+    SliceSelector::Arch(arch) => {
+      objects.iter().position(|o| o.arch() == arch).or_else(|| {
+        let family = arch.cpu_family();
+        let mut compatible = objects
+          .iter()
+          .enumerate()
+          .filter(|(_, o)| o.arch().cpu_family() == family)
+          .map(|(i, _)| i);
+        match (compatible.next(), compatible.next()) {
+          (Some(i), None) => Some(i),
+          _ => None,
+        }
+      })
+    }
+    SliceSelector::DebugId(debug_id) => {
+      objects.iter().position(|o| o.debug_id() == debug_id)
+    }
+  };
+  match index {
+    Some(index) => Ok(objects.swap_remove(index)),
+    None => Err(SymbolicateError::NoMatchingSlice {
+      selector,
+      objects: describe_objects(&objects),
+    }),
+  }
+}
+
+fn object_converter(
+  object: &Object<'_>,
+) -> Result<SymCacheConverter<'static>, SymbolicateError> {
+  if !object.has_debug_info() && !object.has_symbols() {
+    return Err(SymbolicateError::NoDebugInfo {
+      arch: object.arch(),
+      debug_id: object.debug_id(),
+    });
+  }
+  let mut converter = SymCacheConverter::new();
+  converter
+    .process_object(object)
+    .map_err(SymbolicateError::Conversion)?;
+  Ok(converter)
+}
+
+fn object_symcache(object: &Object<'_>) -> Result<Vec<u8>, SymbolicateError> {
+  let mut result = Vec::new();
+  object_converter(object)?.serialize(&mut result)?;
+  Ok(result)
+}
+
+fn parse_object<'d>(
+  byteview: &'d ByteView<'_>,
+  selector: SliceSelector,
+) -> Result<Object<'d>, SymbolicateError> {
+  let fat_obj =
+    Archive::parse(byteview).map_err(SymbolicateError::InvalidDebugFile)?;
+  let objects = fat_obj
+    .objects()
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(SymbolicateError::InvalidDebugFile)?;
+  select_object(objects, selector)
+}
+
+/// Creates an encoded `SymCache` from the contents of the debug info, using
+/// the object picked by `selector`.
 ///
 /// The encoded symcache can then be consumed through the `OwnedSymcache::parse`
 /// method, to get a lifetime-less value, or through `parse_symcache` for a borrowed
 /// version.
-pub fn create_symcache(debug_file: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+pub fn create_symcache(
+  debug_file: &[u8],
+  selector: SliceSelector,
+) -> Result<Vec<u8>, SymbolicateError> {
   let byteview = ByteView::from_slice(debug_file);
-  let fat_obj = Archive::parse(&byteview)?;
-  let objects: Result<Vec<_>, _> = fat_obj.objects().collect();
-  let objects = objects?;
-  if objects.len() != 1 {
-    anyhow::bail!("Fat archives are not supported currently");
-  }
-  let object = &objects[0];
-  let mut converter = SymCacheConverter::new();
-  converter.process_object(object)?;
+  object_symcache(&parse_object(&byteview, selector)?)
+}
 
+/// Like [`create_symcache`], but also adds the symbols of the executable the
+/// debug file is for, such as the exports of the PE image a PDB belongs to,
+/// or the name section of a wasm module whose DWARF was split off. They cover
+/// the functions the debug file has no debug info for.
+///
+/// The executable has to have the debug ID of the debug file's object. For
+/// fat (universal) executables, the slice with that debug ID is used.
+pub fn create_symcache_with_executable(
+  debug_file: &[u8],
+  selector: SliceSelector,
+  executable: &[u8],
+) -> Result<Vec<u8>, SymbolicateError> {
+  let debug_view = ByteView::from_slice(debug_file);
+  let object = parse_object(&debug_view, selector)?;
+  let executable_view = ByteView::from_slice(executable);
+  let executable =
+    parse_object(&executable_view, SliceSelector::DebugId(object.debug_id()))
+      .map_err(|error| match error {
+      SymbolicateError::NoMatchingSlice { .. } => {
+        let executable = parse_object(&executable_view, SliceSelector::Only)
+          .map_or_else(|_| DebugId::nil(), |object| object.debug_id());
+        SymbolicateError::UnrelatedExecutable {
+          debug_id: object.debug_id(),
+          executable,
+        }
+      }
+      error => error,
+    })?;
+  let converter = debug_files::converter_with_executable(&object, &executable)?;
   let mut result = Vec::new();
-  converter.serialize(&mut Cursor::new(&mut result))?;
+  converter.serialize(&mut result)?;
   Ok(result)
 }
 
+/// What [`write_symcache_from_path`] took to create a symcache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymCacheStats {
+  pub debug_file_size: u64,
+  pub symcache_size: u64,
+  /// Time spent parsing the debug file and converting its debug info.
+  pub convert_time: Duration,
+  /// Time spent writing the symcache to the sink.
+  pub write_time: Duration,
+  /// The peak resident set size of the process so far, in bytes. It covers
+  /// everything the process did before, and the pages of the debug file that
+  /// were read. `None` on platforms without `getrusage`.
+  pub peak_rss: Option<u64>,
+}
+
+/// Like [`create_symcache`], but maps the debug file at `path` into memory
+/// instead of reading it, and writes the symcache to `out` as it is
+/// serialized. This keeps the memory needed for multi-gigabyte debug files
+/// down to what the conversion itself takes.
+///
+/// `out` receives many small writes, so it should be buffered. The debug
+/// info of stripped ELF objects is looked up next to them, see
+/// [`write_symcache_with_debug_files`].
+pub fn write_symcache_from_path(
+  path: &Path,
+  selector: SliceSelector,
+  out: impl Write,
+) -> Result<SymCacheStats, SymbolicateError> {
+  let search = DebugFileSearch::default();
+  write_symcache_with_debug_files(path, selector, &search, out)
+    .map(|(stats, _)| stats)
+}
+
+/// Like [`write_symcache_from_path`], but also reads the companion files
+/// found through `search` into one symcache: the separate debug file and
+/// split DWARF units of ELF objects, the PDB of a Windows executable (or
+/// the executable of a PDB), and the separate DWARF file of a wasm module.
+/// See the [`debug_files`] module for how they are found.
+pub fn write_symcache_with_debug_files(
+  path: &Path,
+  selector: SliceSelector,
+  search: &DebugFileSearch,
+  out: impl Write,
+) -> Result<(SymCacheStats, ResolvedDebugFiles), SymbolicateError> {
+  let start = Instant::now();
+  let byteview = ByteView::open(path)?;
+  let object = parse_object(&byteview, selector)?;
+  let (converter, resolved) = match &object {
+    Object::Elf(elf) => debug_files::elf_converter(elf, path, search)?,
+    Object::Pe(_) | Object::Pdb(_) => {
+      debug_files::pe_converter(&object, path, search)?
+    }
+    Object::Wasm(wasm) => {
+      debug_files::wasm_converter(&object, wasm, path, search)?
+    }
+    _ => (object_converter(&object)?, ResolvedDebugFiles::default()),
+  };
+  let convert_time = start.elapsed();
+
+  let start = Instant::now();
+  let mut out = CountingWriter {
+    inner: out,
+    count: 0,
+  };
+  converter.serialize(&mut out)?;
+  out.flush()?;
+  let stats = SymCacheStats {
+    debug_file_size: byteview.len() as u64,
+    symcache_size: out.count,
+    convert_time,
+    write_time: start.elapsed(),
+    peak_rss: peak_rss(),
+  };
+  Ok((stats, resolved))
+}
+
+struct CountingWriter<W> {
+  inner: W,
+  count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    let written = self.inner.write(buf)?;
+    self.count += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[cfg(unix)]
+fn peak_rss() -> Option<u64> {
+  // SAFETY: `getrusage` only writes to the struct passed to it.
+  let usage = unsafe {
+    let mut usage = std::mem::zeroed::<libc::rusage>();
+    if libc::getrusage(libc::RUSAGE_SELF, &mut usage) != 0 {
+      return None;
+    }
+    usage
+  };
+  let max_rss = u64::try_from(usage.ru_maxrss).ok()?;
+  // macOS reports bytes, other unixes KiB.
+  if cfg!(target_vendor = "apple") {
+    Some(max_rss)
+  } else {
+    Some(max_rss * 1024)
+  }
+}
+
+#[cfg(not(unix))]
+fn peak_rss() -> Option<u64> {
+  None
+}
+
+/// A symcache for one object of a (possibly fat) debug file.
+pub struct SymCacheSlice {
+  pub arch: Arch,
+  pub debug_id: DebugId,
+  pub symcache: Vec<u8>,
+}
+
+/// Creates one symcache per object in the debug file, so a single universal
+/// dSYM covers every architecture it was built for.
+pub fn create_symcaches(
+  debug_file: &[u8],
+) -> Result<Vec<SymCacheSlice>, SymbolicateError> {
+  let byteview = ByteView::from_slice(debug_file);
+  let fat_obj =
+    Archive::parse(&byteview).map_err(SymbolicateError::InvalidDebugFile)?;
+  fat_obj
+    .objects()
+    .map(|object| {
+      let object = object.map_err(SymbolicateError::InvalidDebugFile)?;
+      Ok(SymCacheSlice {
+        arch: object.arch(),
+        debug_id: object.debug_id(),
+        symcache: object_symcache(&object)?,
+      })
+    })
+    .collect()
+}
+
 pub fn parse_symcache(
   symcache: &[u8],
-) -> Result<symbolic::symcache::SymCache<'_>, symbolic::symcache::Error> {
-  SymCache::parse(symcache)
+) -> Result<SymCache<'_>, SymbolicateError> {
+  SymCache::parse(symcache).map_err(SymbolicateError::from_symcache_parse)
 }
 
-#[derive(serde::Serialize)]
+/// The symcache format version written by [`create_symcache`].
+pub const SYMCACHE_VERSION: u32 = symbolic::symcache::SYMCACHE_VERSION;
+
+/// The format version of an encoded symcache, see [`symcache_format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymCacheFormat {
+  pub version: u32,
+  /// Whether the symcache can still be parsed. Unsupported symcaches have to
+  /// be created again from their debug file.
+  pub supported: bool,
+  /// Whether the symcache is in the format [`create_symcache`] writes. Older
+  /// formats that are still supported may lack information, e.g. inlinee
+  /// ranges, so they are worth recreating when the debug file is at hand.
+  pub latest: bool,
+}
+
+/// Reads the format version of an encoded symcache, e.g. one cached by an
+/// older version of this crate.
+///
+/// Errors if the data isn't a symcache at all, or is corrupt.
+pub fn symcache_format(
+  symcache: &[u8],
+) -> Result<SymCacheFormat, SymbolicateError> {
+  let (version, supported) = match parse_symcache(symcache) {
+    Ok(symcache) => (symcache.version(), true),
+    // The magic was checked before the version, so the header is there.
+    Err(SymbolicateError::UnsupportedSymCacheVersion(_)) => (
+      u32::from_le_bytes(symcache[4..8].try_into().unwrap()),
+      false,
+    ),
+    Err(error) => return Err(error),
+  };
+  Ok(SymCacheFormat {
+    version,
+    supported,
+    latest: version == SYMCACHE_VERSION,
+  })
+}
+
+/// Converts a build ID recorded at capture time into the debug ID `symbolic`
+/// computes for the corresponding debug file.
+pub fn build_id_to_debug_id(build_id: &BuildId) -> DebugId {
+  match build_id {
+    BuildId::Gnu(bytes) => {
+      // Mirrors `ElfObject::debug_id`: the first 16 bytes of the build ID,
+      // interpreted as a little endian GUID.
+      let mut data = [0; 16];
+      let len = bytes.len().min(16);
+      data[..len].copy_from_slice(&bytes[..len]);
+      DebugId::from_guid_age(&data, 0).unwrap_or_default()
+    }
+    BuildId::MachO(uuid) => DebugId::from_uuid(Uuid::from_bytes(*uuid)),
+    BuildId::Pdb { guid, age } => {
+      DebugId::from_guid_age(guid, *age).unwrap_or_default()
+    }
+  }
+}
+
+/// Checks that the symcache was created from the debug file for the binary
+/// identified by `build_id`. Symbolicating with the wrong symcache produces
+/// plausible looking, but wrong, results.
+pub fn check_build_id(
+  build_id: &BuildId,
+  symcache: impl AsSymcache,
+) -> Result<(), DebugIdMismatch> {
+  let expected = build_id_to_debug_id(build_id);
+  let actual = symcache.as_symcache().debug_id();
+  if expected != actual {
+    return Err(DebugIdMismatch { expected, actual });
+  }
+  Ok(())
+}
+
+/// Checks that a stack trace was captured on the target the symcache is for.
+///
+/// Symcaches only record their architecture, so the OS is checked against the
+/// kind of the trace's build ID instead, which the debug ID is derived from.
+pub fn check_target(
+  header: &encode::Header,
+  symcache: impl AsSymcache,
+) -> Result<(), SymbolicateError> {
+  let symcache_arch = symcache.as_symcache().arch();
+  if symcache_arch != Arch::Unknown
+    && symbolic_arch(header.arch()).map(|arch| arch.cpu_family())
+      != Some(symcache_arch.cpu_family())
+  {
+    return Err(SymbolicateError::TargetMismatch {
+      trace: header.arch().to_string(),
+      symcache: symcache_arch.to_string(),
+    });
+  }
+  if let Some(build_id) = header.build_id() {
+    let os = match build_id {
+      BuildId::Gnu(_) => encode::Os::Linux,
+      BuildId::MachO(_) => encode::Os::Mac,
+      BuildId::Pdb { .. } => encode::Os::Windows,
+    };
+    // Other unixes also use GNU build IDs, so only known OSes are checked.
+    if !matches!(header.os(), encode::Os::Other(_)) && *header.os() != os {
+      return Err(SymbolicateError::TargetMismatch {
+        trace: header.os().to_string(),
+        symcache: os.to_string(),
+      });
+    }
+  }
+  Ok(())
+}
+
+/// The kind of code a [`FrameLocation`] is in, which tells what its
+/// addresses are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CodeKind {
+  /// Machine code of an executable or shared library. Addresses are relative
+  /// to the module's load address.
+  #[default]
+  Native,
+  /// A WebAssembly module. Addresses are byte offsets into the module file,
+  /// as in V8's `wasm-function[3]:0x1a4`.
+  Wasm,
+}
+
+impl CodeKind {
+  /// The kind of code a symcache for `arch` is for.
+  pub fn for_arch(arch: Arch) -> Self {
+    match arch.cpu_family() {
+      CpuFamily::Wasm32 => CodeKind::Wasm,
+      _ => CodeKind::Native,
+    }
+  }
+
+  pub fn is_native(&self) -> bool {
+    *self == CodeKind::Native
+  }
+}
+
+/// One entry of the inline chain for an address.
+///
+/// Symcaches only store a file's joined path and no columns, so the
+/// compilation directory, the file name as written in the debug info and the
+/// column aren't available.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FrameLocation {
   pub demangled_name: String,
   pub name: String,
   pub language: String,
-  pub full_path: String,
-  pub line: u32,
+  /// Left out of the JSON for native code.
+  #[serde(skip_serializing_if = "CodeKind::is_native")]
+  pub code: CodeKind,
+  /// Whether the function was inlined into the next entry of the chain. The
+  /// last entry is the outermost function, which isn't inlined.
+  pub inlined: bool,
+  /// Address of the function's first instruction, relative to the module
+  /// (see [`CodeKind`]).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub function_start: Option<u64>,
+  /// Offset of the looked up address from `function_start`, as in `fn+0x1a`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub offset: Option<u64>,
+  /// The source file's path, joined with its directory and the compilation
+  /// directory.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub full_path: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub line: Option<u32>,
+  /// Whether the location is the closest symbol table entry before the
+  /// address, with no language, file or line, rather than from debug info.
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub symbol_table: bool,
+}
+
+/// Formats as `name+0x1a (path/to/file.rs:10)`, leaving out what's unknown.
+impl std::fmt::Display for FrameLocation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.demangled_name)?;
+    if let Some(offset) = self.offset {
+      write!(f, "+{offset:#x}")?;
+    }
+    match (&self.full_path, self.line) {
+      (Some(path), Some(line)) => write!(f, " ({path}:{line})"),
+      (Some(path), None) => write!(f, " ({path})"),
+      (None, _) => Ok(()),
+    }
+  }
+}
+
+/// An address to symbolicate, relative to the module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionAddr {
+  pub addr: u64,
+  /// Whether `addr` is a return address, which points after the call
+  /// instruction and is looked up at `addr - 1`, like capturing does for
+  /// version 0 traces.
+  pub call_site: bool,
+}
+
+impl InstructionAddr {
+  /// The address of an instruction that was executing, e.g. the faulting
+  /// instruction of a crash, or an address that was already adjusted.
+  pub fn exact(addr: u64) -> Self {
+    Self {
+      addr,
+      call_site: false,
+    }
+  }
+
+  /// A return address, e.g. from a caller's frame.
+  pub fn call_site(addr: u64) -> Self {
+    Self {
+      addr,
+      call_site: true,
+    }
+  }
+
+  fn lookup_addr(&self) -> u64 {
+    if self.call_site {
+      self.addr.saturating_sub(1)
+    } else {
+      self.addr
+    }
+  }
 }
 
 pub fn symbolicate_addrs(
-  addrs: &[u64],
+  addrs: &[InstructionAddr],
   symcache: impl AsSymcache,
-) -> Result<Vec<Vec<FrameLocation>>, anyhow::Error> {
+) -> Result<Vec<Vec<FrameLocation>>, SymbolicateError> {
   let symcache = symcache.as_symcache();
+  let code = CodeKind::for_arch(symcache.arch());
   let mut out = Vec::new();
-  for &addr in addrs {
+  for instruction in addrs {
+    let addr = instruction.lookup_addr();
     let syms = symcache.lookup(addr).collect::<Vec<_>>();
+    let count = syms.len();
     out.push(
       syms
         .into_iter()
-        .map(|sym| FrameLocation {
-          demangled_name: sym
-            .function()
-            .name_for_demangling()
-            .try_demangle(DemangleOptions::name_only())
-            .into_owned(),
-          name: sym.function().name().into(),
-          language: sym.function().language().to_string(),
-          full_path: sym
+        .enumerate()
+        .map(|(i, sym)| {
+          let function = sym.function();
+          let function_start = (function.entry_pc() != u32::MAX)
+            .then_some(function.entry_pc() as u64);
+          let full_path = sym
             .file()
             .map(|file| file.full_path())
-            .unwrap_or_else(|| "<unknown file>".into()),
-          line: sym.line(),
+            .filter(|path| !path.is_empty());
+          let line = (sym.line() != 0).then_some(sym.line());
+          FrameLocation {
+            demangled_name: function
+              .name_for_demangling()
+              .try_demangle(DemangleOptions::name_only())
+              .into_owned(),
+            name: function.name().into(),
+            language: function.language().to_string(),
+            code,
+            inlined: i + 1 < count,
+            function_start,
+            offset: function_start.and_then(|start| addr.checked_sub(start)),
+            symbol_table: line.is_none()
+              && full_path.is_none()
+              && function.language() == Language::Unknown,
+            full_path,
+            line,
+          }
         })
         .collect(),
     );
@@ -77,6 +614,193 @@ pub fn symbolicate_addrs(
   Ok(out)
 }
 
+/// How much of a frame could be symbolicated, from best to worst. Reports
+/// and grouping can tell low-confidence frames apart by it.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum FrameQuality {
+  /// The function, file and line, from debug info.
+  DebugInfo,
+  /// Like `DebugInfo`, in a function inlined into others.
+  Inlined,
+  /// Only the function, from debug info without a line for the address.
+  SymbolOnly,
+  /// Only the closest entry of the symbol table (e.g. of a stripped binary)
+  /// before the address. Symcaches don't record symbol sizes, so the address
+  /// may be past the symbol's end, e.g. in the PLT or padding.
+  NearestSymbol,
+  /// The address was looked up, but nothing is known about it.
+  Unresolved,
+  /// The frame wasn't looked up, since there's no symcache for the module
+  /// it's in, or its address is unknown.
+  Skipped,
+}
+
+impl FrameQuality {
+  /// The quality of a looked up frame with the inline chain `locations`,
+  /// innermost function first.
+  pub fn of(locations: &[FrameLocation]) -> Self {
+    match locations {
+      [] => FrameQuality::Unresolved,
+      [innermost, ..] if innermost.symbol_table => FrameQuality::NearestSymbol,
+      [innermost, ..] if innermost.line.is_none() => FrameQuality::SymbolOnly,
+      [_] => FrameQuality::DebugInfo,
+      [_, ..] => FrameQuality::Inlined,
+    }
+  }
+}
+
+/// A frame of a [`SymbolicatedStackTrace`].
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolicatedFrame {
+  #[serde(flatten)]
+  pub frame: FrameAddr,
+  /// The inline chain for the frame's address, innermost function first.
+  /// Empty if the address couldn't be symbolicated, or there's no symcache
+  /// for the module the frame is in.
+  pub locations: Vec<FrameLocation>,
+  /// See [`FrameQuality::of`]. Frames without a symcache for their module
+  /// are `Skipped`.
+  pub quality: FrameQuality,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolicatedStackTrace {
+  pub header: encode::Header,
+  pub frames: Vec<SymbolicatedFrame>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub modules: Vec<Module>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub thread: Option<ThreadInfo>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signal: Option<SignalInfo>,
+  /// The stacks of the trace's other threads.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub threads: Vec<SymbolicatedThread>,
+}
+
+/// Another thread's stack in a [`SymbolicatedStackTrace`].
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolicatedThread {
+  pub thread: ThreadInfo,
+  pub frames: Vec<SymbolicatedFrame>,
+}
+
+/// Symbolicates the stable frames of a stack trace. Other frames are kept, but
+/// get no locations.
+pub fn symbolicate_frames(
+  frames: &[FrameAddr],
+  symcache: impl AsSymcache,
+) -> Result<Vec<SymbolicatedFrame>, SymbolicateError> {
+  symbolicate_frames_with_modules(frames, symcache, &no_modules())
+}
+
+/// Symbolicates the stable frames of a stack trace with `symcache`, and the
+/// frames in other modules with the symcache in `module_symcaches` for their
+/// module's index in [`StackTrace::modules`]. Frames without a symcache for
+/// their module are kept, but get no locations.
+pub fn symbolicate_frames_with_modules<S: AsSymcache>(
+  frames: &[FrameAddr],
+  symcache: impl AsSymcache,
+  module_symcaches: &BTreeMap<usize, S>,
+) -> Result<Vec<SymbolicatedFrame>, SymbolicateError> {
+  let symcache = symcache.as_symcache();
+  frames
+    .iter()
+    .map(|frame| {
+      let lookup = match frame {
+        FrameAddr::Stable { addr, .. } => Some((symcache, *addr)),
+        FrameAddr::InModule { module, addr, .. } => module_symcaches
+          .get(module)
+          .map(|symcache| (symcache.as_symcache(), *addr)),
+        FrameAddr::Absolute { .. } | FrameAddr::Unknown => None,
+      };
+      let Some((symcache, addr)) = lookup else {
+        return Ok(SymbolicatedFrame {
+          frame: *frame,
+          locations: Vec::new(),
+          quality: FrameQuality::Skipped,
+        });
+      };
+      let addr = InstructionAddr {
+        addr,
+        call_site: frame.is_call_site(),
+      };
+      let locations = symbolicate_addrs(&[addr], symcache)?
+        .pop()
+        .unwrap_or_default();
+      Ok(SymbolicatedFrame {
+        frame: *frame,
+        quality: FrameQuality::of(&locations),
+        locations,
+      })
+    })
+    .collect()
+}
+
+fn no_modules() -> BTreeMap<usize, &'static SymCache<'static>> {
+  BTreeMap::new()
+}
+
+/// Symbolicates a decoded stack trace, after checking that the symcache is
+/// for the binary (or at least the target) it was captured from.
+pub fn symbolicate_stack_trace(
+  stack_trace: &StackTrace,
+  symcache: impl AsSymcache,
+) -> Result<SymbolicatedStackTrace, SymbolicateError> {
+  symbolicate_stack_trace_with_modules(stack_trace, symcache, &no_modules())
+}
+
+/// Like [`symbolicate_stack_trace`], but also symbolicates the frames in the
+/// trace's other modules (e.g. shared libraries), with the symcaches in
+/// `module_symcaches` by module index. Each is checked against the build ID
+/// of its module, if the trace recorded one.
+pub fn symbolicate_stack_trace_with_modules<S: AsSymcache>(
+  stack_trace: &StackTrace,
+  symcache: impl AsSymcache,
+  module_symcaches: &BTreeMap<usize, S>,
+) -> Result<SymbolicatedStackTrace, SymbolicateError> {
+  let symcache = symcache.as_symcache();
+  check_target(stack_trace.header(), symcache)?;
+  if let Some(build_id) = stack_trace.header().build_id() {
+    check_build_id(build_id, symcache)?;
+  }
+  for (index, module_symcache) in module_symcaches {
+    let build_id = stack_trace
+      .modules()
+      .get(*index)
+      .and_then(|module| module.build_id.as_ref());
+    if let Some(build_id) = build_id {
+      check_build_id(build_id, module_symcache.as_symcache())?;
+    }
+  }
+  let symbolicate = |frames| {
+    symbolicate_frames_with_modules(frames, symcache, module_symcaches)
+  };
+  Ok(SymbolicatedStackTrace {
+    header: stack_trace.header().clone(),
+    frames: symbolicate(stack_trace.frames())?,
+    modules: stack_trace.modules().to_vec(),
+    thread: stack_trace.thread().cloned(),
+    signal: stack_trace.signal().copied(),
+    threads: stack_trace
+      .threads()
+      .iter()
+      .map(|thread| {
+        Ok(SymbolicatedThread {
+          thread: thread.thread.clone(),
+          frames: symbolicate(&thread.frames)?,
+        })
+      })
+      .collect::<Result<_, SymbolicateError>>()?,
+  })
+}
+
 use yoke::Yoke;
 
 #[derive(yoke::Yokeable)]
@@ -98,6 +822,12 @@ impl AsSymcache for &'_ OwnedSymCache {
   }
 }
 
+impl AsSymcache for std::sync::Arc<OwnedSymCache> {
+  fn as_symcache(&self) -> &SymCache<'_> {
+    self.as_ref().as_ref()
+  }
+}
+
 impl AsSymcache for SymCache<'_> {
   fn as_symcache(&self) -> &SymCache<'_> {
     self
@@ -113,10 +843,9 @@ impl AsSymcache for &'_ SymCache<'_> {
 pub struct OwnedSymCache(Yoke<SymCacheWrapper<'static>, Vec<u8>>);
 
 impl OwnedSymCache {
-  pub fn parse(bytes: Vec<u8>) -> Result<Self, anyhow::Error> {
+  pub fn parse(bytes: Vec<u8>) -> Result<Self, SymbolicateError> {
     Ok(Self(Yoke::try_attach_to_cart(bytes, |bytes| {
-      let cache = SymCache::parse(bytes)?;
-      Ok::<_, anyhow::Error>(SymCacheWrapper(cache))
+      parse_symcache(bytes).map(SymCacheWrapper)
     })?))
   }
 
@@ -125,3 +854,586 @@ impl OwnedSymCache {
     &self.0.get().0
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+  const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+  /// A minimal 64-bit Mach-O dSYM with only `LC_UUID` and `LC_SYMTAB` load
+  /// commands.
+  fn macho(cpu_type: u32, cpu_subtype: u32, uuid: [u8; 16]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for field in [0xfeed_facf, cpu_type, cpu_subtype, 0xa, 2, 48, 0, 0] {
+      buf.extend(u32::to_le_bytes(field));
+    }
+    buf.extend(0x1bu32.to_le_bytes());
+    buf.extend(24u32.to_le_bytes());
+    buf.extend(uuid);
+    // An empty LC_SYMTAB, so the object isn't rejected for having no symbols.
+    for field in [0x2, 24, 0, 0, 0, 0] {
+      buf.extend(u32::to_le_bytes(field));
+    }
+    buf
+  }
+
+  /// A universal binary containing `slices`, as `(cpu type, subtype, data)`.
+  fn fat(slices: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+    const ALIGN: usize = 0x1000;
+    let mut buf = Vec::new();
+    buf.extend(0xcafe_babeu32.to_be_bytes());
+    buf.extend((slices.len() as u32).to_be_bytes());
+    let mut offset = ALIGN;
+    for (cpu_type, cpu_subtype, data) in slices {
+      for field in [*cpu_type, *cpu_subtype, offset as u32, data.len() as u32] {
+        buf.extend(field.to_be_bytes());
+      }
+      buf.extend(12u32.to_be_bytes());
+      offset += data.len().next_multiple_of(ALIGN);
+    }
+    for (_, _, data) in slices {
+      buf.resize(buf.len().next_multiple_of(ALIGN), 0);
+      buf.extend(data);
+    }
+    buf
+  }
+
+  pub(crate) fn universal() -> Vec<u8> {
+    fat(&[
+      (CPU_TYPE_X86_64, 3, macho(CPU_TYPE_X86_64, 3, [1; 16])),
+      (CPU_TYPE_ARM64, 0, macho(CPU_TYPE_ARM64, 0, [2; 16])),
+    ])
+  }
+
+  /// A directory that's removed when dropped.
+  pub(crate) struct TempDir(pub std::path::PathBuf);
+
+  impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+      let path = std::env::temp_dir()
+        .join(format!("deno-symbolicate-{name}-{}", std::process::id()));
+      let _ = std::fs::remove_dir_all(&path);
+      std::fs::create_dir_all(&path).unwrap();
+      TempDir(path)
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn symcache_debug_id(symcache: &[u8]) -> DebugId {
+    parse_symcache(symcache).unwrap().debug_id()
+  }
+
+  #[test]
+  fn select_fat_archive_slice() {
+    let data = universal();
+    let x86_64 = DebugId::from_uuid(Uuid::from_bytes([1; 16]));
+    let arm64 = DebugId::from_uuid(Uuid::from_bytes([2; 16]));
+
+    let result = create_symcache(&data, SliceSelector::Only);
+    assert!(
+      matches!(result, Err(SymbolicateError::FatArchive { count: 2, .. })),
+      "{result:?}",
+    );
+    let symcache = create_symcache(&data, SliceSelector::Arch(Arch::Amd64));
+    assert_eq!(symcache_debug_id(&symcache.unwrap()), x86_64);
+    let symcache = create_symcache(
+      &data,
+      SliceSelector::for_trace_arch(&"aarch64".into()).unwrap(),
+    );
+    assert_eq!(symcache_debug_id(&symcache.unwrap()), arm64);
+    // arm64e isn't in the archive, but arm64 is compatible.
+    let symcache = create_symcache(&data, SliceSelector::Arch(Arch::Arm64e));
+    assert_eq!(symcache_debug_id(&symcache.unwrap()), arm64);
+    let symcache = create_symcache(&data, SliceSelector::DebugId(x86_64));
+    assert_eq!(symcache_debug_id(&symcache.unwrap()), x86_64);
+    let result = create_symcache(&data, SliceSelector::Arch(Arch::X86));
+    assert!(
+      matches!(result, Err(SymbolicateError::NoMatchingSlice { .. })),
+      "{result:?}",
+    );
+
+    // A thin file works without picking a slice.
+    let thin = macho(CPU_TYPE_ARM64, 0, [2; 16]);
+    let symcache = create_symcache(&thin, SliceSelector::Only);
+    assert_eq!(symcache_debug_id(&symcache.unwrap()), arm64);
+  }
+
+  #[test]
+  fn error_kinds() {
+    let result = create_symcache(b"not a debug file", SliceSelector::Only);
+    let error = result.unwrap_err();
+    assert_eq!(error.code(), "invalid_debug_file", "{error}");
+
+    // Drop the LC_SYMTAB command.
+    let mut stripped = macho(CPU_TYPE_ARM64, 0, [2; 16]);
+    stripped[16..24].copy_from_slice(&[1, 0, 0, 0, 24, 0, 0, 0]);
+    stripped.truncate(56);
+    let error = create_symcache(&stripped, SliceSelector::Only).unwrap_err();
+    assert_eq!(error.code(), "no_debug_info", "{error}");
+
+    let mut symcache =
+      create_symcache(&universal(), SliceSelector::Arch(Arch::Arm64)).unwrap();
+    let error = OwnedSymCache::parse(symcache[1..].to_vec()).err().unwrap();
+    assert_eq!(error.code(), "corrupt_symcache", "{error}");
+    let error = symcache_format(&symcache[1..]).unwrap_err();
+    assert_eq!(error.code(), "corrupt_symcache", "{error}");
+    // The format version follows the magic.
+    symcache[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+    let error = OwnedSymCache::parse(symcache).err().unwrap();
+    assert_eq!(error.code(), "unsupported_symcache_version", "{error}");
+  }
+
+  #[test]
+  fn symcache_format_versions() {
+    let mut symcache =
+      create_symcache(&universal(), SliceSelector::Arch(Arch::Arm64)).unwrap();
+    let format = symcache_format(&symcache).unwrap();
+    assert_eq!(
+      format,
+      SymCacheFormat {
+        version: SYMCACHE_VERSION,
+        supported: true,
+        latest: true,
+      }
+    );
+
+    // Written by an older `symbolic`.
+    symcache[4..8].copy_from_slice(&6u32.to_le_bytes());
+    let format = symcache_format(&symcache).unwrap();
+    assert_eq!(
+      format,
+      SymCacheFormat {
+        version: 6,
+        supported: false,
+        latest: false,
+      }
+    );
+  }
+
+  #[inline(never)]
+  fn capture() -> deno_stable_stacktrace::CapturedStackTrace {
+    deno_stable_stacktrace::stable_stacktrace()
+  }
+
+  #[test]
+  fn symbolicate_captured_stack_trace() {
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    let symcache = create_symcache(&exe, SliceSelector::Only).unwrap();
+    let symcache = OwnedSymCache::parse(symcache).unwrap();
+
+    let version = encode::Version {
+      major: 2,
+      minor: 0,
+      patch: 0,
+      canary_hash: encode::CanaryHash::none(),
+      dev_build: true,
+    };
+    let stack_trace = StackTrace::from_captured(&capture(), version.clone());
+    let stack_trace = StackTrace::decode(&stack_trace.encode()).unwrap();
+    let symbolicated =
+      symbolicate_stack_trace(&stack_trace, &symcache).unwrap();
+    assert_eq!(&symbolicated.header, stack_trace.header());
+    assert_eq!(symbolicated.frames.len(), stack_trace.frames().len());
+    let capture = symbolicated
+      .frames
+      .iter()
+      .flat_map(|frame| &frame.locations)
+      .find(|location| location.demangled_name.ends_with("tests::capture"))
+      .unwrap_or_else(|| panic!("{symbolicated:#?}"));
+    assert!(
+      capture.full_path.as_deref().unwrap().ends_with("lib.rs"),
+      "{capture:#?}"
+    );
+    assert!(capture.line.is_some());
+    assert!(capture.function_start.is_some() && capture.offset.is_some());
+    for frame in &symbolicated.frames {
+      if let Some(outermost) = frame.locations.last() {
+        assert!(!outermost.inlined, "{frame:#?}");
+      }
+    }
+
+    // Return addresses are looked up at the call before them, so one at the
+    // start of `capture` belongs to whatever precedes it.
+    let start = self::capture as *const () as usize as u64
+      - stack_trace.modules()[0].base;
+    let is_capture = |location: &FrameLocation| {
+      location.demangled_name.ends_with("tests::capture")
+    };
+    let locations = symbolicate_addrs(
+      &[
+        InstructionAddr::exact(start),
+        InstructionAddr::call_site(start),
+      ],
+      &symcache,
+    )
+    .unwrap();
+    assert!(
+      locations[0]
+        .iter()
+        .any(|location| is_capture(location) && location.offset == Some(0)),
+      "{locations:#?}"
+    );
+    assert!(!locations[1].iter().any(is_capture), "{locations:#?}");
+
+    let serialized = serde_json::to_value(&symbolicated).unwrap();
+    assert!(serialized["frames"][0]["kind"].is_string(), "{serialized}");
+    assert_eq!(serialized["frames"][0]["callSite"], true, "{serialized}");
+
+    let other_arch = if cfg!(target_arch = "aarch64") {
+      "x86_64"
+    } else {
+      "aarch64"
+    };
+    let wrong_arch = StackTrace::new(vec![], other_arch, "linux", version);
+    let error = symbolicate_stack_trace(&wrong_arch, &symcache).unwrap_err();
+    assert_eq!(error.code(), "target_mismatch", "{error}");
+  }
+
+  #[cfg(target_os = "linux")]
+  #[inline(never)]
+  fn park_until(done: std::sync::mpsc::Receiver<()>) {
+    done.recv().unwrap();
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn symbolicate_all_threads() {
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    let symcache = create_symcache(&exe, SliceSelector::Only).unwrap();
+    let symcache = OwnedSymCache::parse(symcache).unwrap();
+
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    let thread = std::thread::Builder::new()
+      .name("parked".into())
+      .spawn(move || {
+        ready_tx.send(()).unwrap();
+        park_until(done_rx);
+      })
+      .unwrap();
+    ready_rx.recv().unwrap();
+    let captured = deno_stable_stacktrace::all_threads_stacktraces();
+    done_tx.send(()).unwrap();
+    thread.join().unwrap();
+
+    let version = encode::Version {
+      major: 2,
+      minor: 0,
+      patch: 0,
+      canary_hash: encode::CanaryHash::none(),
+      dev_build: true,
+    };
+    let stack_trace = StackTrace::from_captured_threads(&captured, version);
+    let stack_trace = StackTrace::decode(&stack_trace.encode()).unwrap();
+    assert!(stack_trace.thread().is_some());
+    let symbolicated =
+      symbolicate_stack_trace(&stack_trace, &symcache).unwrap();
+    let parked = symbolicated
+      .threads
+      .iter()
+      .find(|thread| thread.thread.name.as_deref() == Some("parked"))
+      .unwrap_or_else(|| panic!("{symbolicated:#?}"));
+    assert!(
+      parked
+        .frames
+        .iter()
+        .flat_map(|frame| &frame.locations)
+        .any(|location| location.demangled_name.ends_with("park_until")),
+      "{parked:#?}"
+    );
+  }
+
+  #[test]
+  fn display_frame_location() {
+    let mut location = FrameLocation {
+      demangled_name: "foo::bar".into(),
+      name: "_ZN3foo3bar".into(),
+      language: "rust".into(),
+      code: CodeKind::Native,
+      inlined: false,
+      function_start: Some(0x1000),
+      offset: Some(0x1a),
+      full_path: Some("/src/foo/src/x.rs".into()),
+      line: Some(10),
+      symbol_table: false,
+    };
+    assert_eq!(location.to_string(), "foo::bar+0x1a (/src/foo/src/x.rs:10)");
+    location.offset = None;
+    location.line = None;
+    assert_eq!(location.to_string(), "foo::bar (/src/foo/src/x.rs)");
+    location.full_path = None;
+    assert_eq!(location.to_string(), "foo::bar");
+  }
+
+  #[test]
+  fn symcache_per_slice() {
+    let slices = create_symcaches(&universal()).unwrap();
+    let slices = slices
+      .iter()
+      .map(|slice| {
+        assert_eq!(symcache_debug_id(&slice.symcache), slice.debug_id);
+        (slice.arch, slice.debug_id)
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      slices,
+      [
+        (Arch::Amd64, DebugId::from_uuid(Uuid::from_bytes([1; 16]))),
+        (Arch::Arm64, DebugId::from_uuid(Uuid::from_bytes([2; 16]))),
+      ]
+    );
+  }
+
+  #[test]
+  fn symcache_from_path() {
+    let dir = TempDir::new("from-path");
+    let path = dir.0.join("universal");
+    std::fs::write(&path, universal()).unwrap();
+    let selector = SliceSelector::Arch(Arch::Arm64);
+    let mut symcache = Vec::new();
+    let stats =
+      write_symcache_from_path(&path, selector, &mut symcache).unwrap();
+    assert_eq!(symcache, create_symcache(&universal(), selector).unwrap());
+    assert_eq!(stats.debug_file_size, universal().len() as u64);
+    assert_eq!(stats.symcache_size, symcache.len() as u64);
+    assert_eq!(stats.peak_rss.is_some(), cfg!(unix));
+
+    let missing = dir.0.join("missing");
+    let result =
+      write_symcache_from_path(&missing, SliceSelector::Only, &mut symcache);
+    assert_eq!(result.unwrap_err().code(), "io");
+  }
+
+  #[test]
+  fn build_id_matches_debug_id() {
+    let Some(build_id) = deno_stable_stacktrace::main_module_build_id() else {
+      // Not every linker emits a build ID by default.
+      return;
+    };
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    let object = Object::parse(&exe).unwrap();
+    assert_eq!(build_id_to_debug_id(&build_id), object.debug_id());
+  }
+
+  #[test]
+  fn pdb_with_executable() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/pe");
+    let pdb = std::fs::read(fixtures.join("app.pdb")).unwrap();
+    let exe = std::fs::read(fixtures.join("app.exe")).unwrap();
+    let symcache =
+      create_symcache_with_executable(&pdb, SliceSelector::Only, &exe).unwrap();
+    let symcache = parse_symcache(&symcache).unwrap();
+    let names = |addr| {
+      symcache
+        .lookup(addr)
+        .map(|location| location.function().name().to_owned())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(names(0x101c), ["helper(int)", "compute"]);
+    assert_eq!(names(0x1080), ["exported_only"]);
+
+    // Windows traces carry the GUID and age of the executable's CodeView
+    // record, which follows its `RSDS` magic at .rdata+0x20.
+    let codeview = &exe[0x424..0x438];
+    let build_id = BuildId::Pdb {
+      guid: codeview[..16].try_into().unwrap(),
+      age: u32::from_le_bytes(codeview[16..].try_into().unwrap()),
+    };
+    check_build_id(&build_id, &symcache).unwrap();
+
+    let rebuilt = std::fs::read(fixtures.join("app-rebuilt.exe")).unwrap();
+    let result =
+      create_symcache_with_executable(&pdb, SliceSelector::Only, &rebuilt);
+    assert_eq!(result.unwrap_err().code(), "unrelated_executable");
+  }
+
+  #[test]
+  fn wasm_code_offsets() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/wasm");
+    let debug_file = std::fs::read(fixtures.join("lib.debug.wasm")).unwrap();
+    let module = std::fs::read(fixtures.join("lib.wasm")).unwrap();
+    let symcache = create_symcache_with_executable(
+      &debug_file,
+      SliceSelector::Only,
+      &module,
+    )
+    .unwrap();
+    let symcache = parse_symcache(&symcache).unwrap();
+    let locations = symbolicate_addrs(
+      &[
+        InstructionAddr::exact(0x80),
+        InstructionAddr::call_site(0x9a),
+      ],
+      &symcache,
+    )
+    .unwrap();
+    let functions = locations
+      .iter()
+      .map(|chain| {
+        chain
+          .iter()
+          .map(|location| {
+            assert_eq!(location.code, CodeKind::Wasm);
+            (
+              location.name.as_str(),
+              location.function_start,
+              location.offset,
+            )
+          })
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      functions,
+      [
+        vec![("helper", None, None), ("compute", Some(0x5a), Some(0x26))],
+        vec![("exported_only", Some(0x98), Some(0x1))],
+      ]
+    );
+  }
+
+  #[test]
+  fn frame_quality() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/elf");
+    let symbolicate = |file: &str| {
+      let debug_file = std::fs::read(fixtures.join(file)).unwrap();
+      let symcache = create_symcache(&debug_file, SliceSelector::Only).unwrap();
+      let symcache = parse_symcache(&symcache).unwrap();
+      let frames = [Some(0x1060), Some(0x1170), Some(0x1040), Some(0x10), None]
+        .map(FrameAddr::from);
+      symbolicate_frames(&frames, &symcache).unwrap()
+    };
+
+    let frames = symbolicate("plain.debug");
+    assert_eq!(
+      frames.iter().map(|frame| frame.quality).collect::<Vec<_>>(),
+      [
+        FrameQuality::DebugInfo,
+        FrameQuality::Inlined,
+        FrameQuality::NearestSymbol,
+        FrameQuality::Unresolved,
+        FrameQuality::Skipped,
+      ]
+    );
+    // The PLT has no symbol of its own, so it falls back to `_init`, which
+    // doesn't contain it.
+    let location = &frames[2].locations[0];
+    assert_eq!(location.name, "_init");
+    assert_eq!(
+      (location.function_start, location.offset),
+      (Some(0x1000), Some(0x40))
+    );
+
+    // Without debug info, only the symbol table is left.
+    let frames = symbolicate("symtab");
+    assert_eq!(
+      frames.iter().map(|frame| frame.quality).collect::<Vec<_>>(),
+      [
+        FrameQuality::NearestSymbol,
+        FrameQuality::NearestSymbol,
+        FrameQuality::NearestSymbol,
+        FrameQuality::Unresolved,
+        FrameQuality::Skipped,
+      ]
+    );
+    let location = &frames[1].locations[0];
+    assert_eq!(location.name, "compute");
+    assert_eq!(location.offset, Some(0x10));
+    assert_eq!((&location.full_path, location.line), (&None, None));
+  }
+
+  #[test]
+  fn symbolicate_other_modules() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    let debug_file = std::fs::read(fixtures.join("elf/plain.debug")).unwrap();
+    let symcache = create_symcache(&debug_file, SliceSelector::Only).unwrap();
+    let symcache = parse_symcache(&symcache).unwrap();
+    let pdb = std::fs::read(fixtures.join("pe/app.pdb")).unwrap();
+    let exe = std::fs::read(fixtures.join("pe/app.exe")).unwrap();
+    let module_symcache =
+      create_symcache_with_executable(&pdb, SliceSelector::Only, &exe).unwrap();
+    let module_symcache = parse_symcache(&module_symcache).unwrap();
+    let codeview = &exe[0x424..0x438];
+    let module_build_id = BuildId::Pdb {
+      guid: codeview[..16].try_into().unwrap(),
+      age: u32::from_le_bytes(codeview[16..].try_into().unwrap()),
+    };
+
+    let frames = vec![
+      FrameAddr::InModule {
+        module: 1,
+        addr: 0x1080,
+        call_site: false,
+      },
+      FrameAddr::Stable {
+        addr: 0x1061,
+        call_site: true,
+      },
+      FrameAddr::InModule {
+        module: 2,
+        addr: 0x1080,
+        call_site: true,
+      },
+    ];
+    let version = encode::Version {
+      major: 2,
+      minor: 0,
+      patch: 0,
+      canary_hash: encode::CanaryHash::none(),
+      dev_build: false,
+    };
+    let module = |path: &str, build_id| Module {
+      path: path.into(),
+      base: 0x7f00_0000_0000,
+      build_id,
+    };
+    let stack_trace = StackTrace::from_frames(
+      frames, "x86_64", "linux", version,
+    )
+    .with_modules(vec![
+      module("plain", None),
+      module("app.exe", Some(module_build_id)),
+      module("libother.so", None),
+    ]);
+    let module_symcaches = BTreeMap::from([(1, &module_symcache)]);
+    let symbolicated = symbolicate_stack_trace_with_modules(
+      &stack_trace,
+      &symcache,
+      &module_symcaches,
+    )
+    .unwrap();
+    let names = symbolicated
+      .frames
+      .iter()
+      .map(|frame| frame.locations.first().map(|l| l.name.as_str()))
+      .collect::<Vec<_>>();
+    assert_eq!(names, [Some("exported_only"), Some("main"), None]);
+    assert_eq!(symbolicated.frames[2].quality, FrameQuality::Skipped);
+
+    // Without the module's symcache, only the stable frame is looked up.
+    let symbolicated =
+      symbolicate_stack_trace(&stack_trace, &symcache).unwrap();
+    assert!(symbolicated.frames[0].locations.is_empty());
+    assert!(!symbolicated.frames[1].locations.is_empty());
+
+    // Module symcaches are checked against the module's build ID.
+    let mut stack_trace = stack_trace;
+    let mut modules = stack_trace.modules().to_vec();
+    modules[1].build_id = Some(BuildId::Gnu(vec![1, 2, 3]));
+    stack_trace = stack_trace.with_modules(modules);
+    let error = symbolicate_stack_trace_with_modules(
+      &stack_trace,
+      &symcache,
+      &module_symcaches,
+    )
+    .unwrap_err();
+    assert_eq!(error.code(), "debug_id_mismatch", "{error}");
+  }
+}
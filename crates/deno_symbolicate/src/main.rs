@@ -0,0 +1,520 @@
+use std::collections::{BTreeMap, btree_map};
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use deno_stable_stacktrace::encode::{
+  FrameAddr, StackTrace, find_banner_trace,
+};
+use deno_symbolicate::breakpad::write_breakpad_symbols;
+use deno_symbolicate::debug_files::DebugFileSearch;
+use deno_symbolicate::output::TraceOutput;
+use deno_symbolicate::symstore::SymbolStore;
+use deno_symbolicate::unwind::unwind_stack_trace_with_files;
+use deno_symbolicate::{
+  OwnedSymCache, SliceSelector, SymCacheStats, build_id_to_debug_id,
+  create_symcaches, symbolic_arch, symbolicate_frames,
+  symbolicate_stack_trace_with_modules, write_symcache_with_debug_files,
+};
+use symbolic::common::{Arch, ByteView, DebugId};
+
+#[derive(Parser)]
+#[command(name = "deno-symbolicate", version, about)]
+struct Cli {
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Build a symcache from a debug file (ELF, Mach-O, PE, PDB, wasm, Breakpad
+  /// .sym or a .dSYM bundle)
+  Symcache {
+    debug_file: PathBuf,
+    /// Where to write the symcache. Defaults to `<debug_file>.symcache`, or
+    /// `<debug_file>.<arch>.symcache` with `--all-slices`
+    #[arg(short, long, conflicts_with = "all_slices")]
+    output: Option<PathBuf>,
+    /// Use the slice for this architecture of a fat (universal) debug file
+    #[arg(long, value_parser = parse_arch, conflicts_with = "debug_id")]
+    arch: Option<Arch>,
+    /// Use the slice with this debug ID of a fat (universal) debug file
+    #[arg(long)]
+    debug_id: Option<DebugId>,
+    /// Write a symcache for every slice of a fat (universal) debug file
+    #[arg(long, conflicts_with_all = ["arch", "debug_id"])]
+    all_slices: bool,
+    /// Print the time and peak memory it took to stderr
+    #[arg(long, conflicts_with = "all_slices")]
+    stats: bool,
+    /// A companion debug file: the separate debug file, .dwp package or .dwo
+    /// files of a stripped ELF object, the .pdb of a Windows executable, the
+    /// .exe/.dll of a PDB, to add its exports, or the .wasm debug file of a
+    /// wasm module. Can be repeated
+    #[arg(long, conflicts_with = "all_slices")]
+    with_debug_file: Vec<PathBuf>,
+    /// A directory to look for the separate debug file (by build ID, debug
+    /// link or external_debug_info), .dwo files or PDB in, e.g.
+    /// /usr/lib/debug or a symbol store.
+    /// Can be repeated
+    #[arg(long, conflicts_with = "all_slices")]
+    debug_dir: Vec<PathBuf>,
+  },
+  /// Write the Breakpad symbols (.sym) of a debug file
+  Breakpad {
+    debug_file: PathBuf,
+    /// Where to write the symbols. Defaults to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Use the slice for this architecture of a fat (universal) debug file
+    #[arg(long, value_parser = parse_arch, conflicts_with = "debug_id")]
+    arch: Option<Arch>,
+    /// Use the slice with this debug ID of a fat (universal) debug file
+    #[arg(long)]
+    debug_id: Option<DebugId>,
+  },
+  /// Symbolicate an encoded (base64url) stack trace
+  Trace {
+    #[arg(short, long, required_unless_present = "store")]
+    symcache: Option<PathBuf>,
+    /// Look up the symcache in a symbol store, by the trace's build ID or
+    /// version
+    #[arg(long, conflicts_with = "symcache")]
+    store: Option<PathBuf>,
+    /// The encoded stack trace, or crash output containing one between
+    /// banner lines. Read from stdin if omitted or `-`
+    trace: Option<String>,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Unwind the stack memory recorded in the trace again, with the unwind
+    /// info of this file of the executable (the executable itself or its
+    /// debug file). Can be repeated
+    #[arg(long, value_name = "FILE")]
+    unwind: Vec<PathBuf>,
+    /// The symcache of another module of the trace, e.g. a shared library,
+    /// matched to it by build ID. With `--store`, modules are also looked up
+    /// in the store. Can be repeated
+    #[arg(long, value_name = "SYMCACHE")]
+    module_symcache: Vec<PathBuf>,
+  },
+  /// Symbolicate a list of hex addresses
+  Addrs {
+    #[arg(short, long)]
+    symcache: PathBuf,
+    #[arg(required = true, value_parser = parse_hex_addr)]
+    addrs: Vec<u64>,
+    /// The addresses are return addresses (e.g. from a debugger's
+    /// backtrace), so look up the call instructions before them
+    #[arg(long)]
+    call_sites: bool,
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+  },
+  /// Manage a symbol store directory
+  Store {
+    /// The store's root directory
+    root: PathBuf,
+    #[command(subcommand)]
+    command: StoreCommand,
+  },
+}
+
+#[derive(Subcommand)]
+enum StoreCommand {
+  /// Add a debug file (ELF, Mach-O, PE, PDB, wasm, Breakpad .sym or a .dSYM
+  /// bundle) and its symcache
+  Ingest {
+    debug_file: PathBuf,
+    /// The name to store it under. Defaults to the debug file's name
+    #[arg(long)]
+    name: Option<String>,
+    /// Add it to the index of this version, for traces without a build ID
+    #[arg(long)]
+    version: Option<String>,
+  },
+  /// List the stored debug files and indexed versions
+  List,
+  /// Recreate the symcaches that were written in an older format
+  Upgrade,
+  /// Remove versions from the index, along with the debug files that no
+  /// remaining version refers to (including those ingested without a
+  /// version)
+  Gc {
+    /// A version to keep. Every other version is removed. Can be repeated
+    #[arg(long, required_unless_present = "all")]
+    keep: Vec<String>,
+    /// Remove every version and debug file
+    #[arg(long, conflicts_with = "keep")]
+    all: bool,
+    /// Only print what would be removed
+    #[arg(long)]
+    dry_run: bool,
+  },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+  Text,
+  Json,
+}
+
+fn parse_hex_addr(s: &str) -> Result<u64, String> {
+  let digits = s
+    .strip_prefix("0x")
+    .or_else(|| s.strip_prefix("0X"))
+    .unwrap_or(s);
+  u64::from_str_radix(digits, 16)
+    .map_err(|e| format!("invalid hex address `{s}`: {e}"))
+}
+
+/// Accepts both `symbolic`'s architecture names and the ones in stack trace
+/// headers (i.e. Rust's `target_arch`).
+fn parse_arch(s: &str) -> Result<Arch, String> {
+  symbolic_arch(&s.into()).ok_or_else(|| format!("unknown architecture `{s}`"))
+}
+
+/// Resolves `Foo.dSYM` bundles to the DWARF file inside of them.
+fn resolve_dsym(path: &Path) -> PathBuf {
+  if path.is_dir()
+    && path.extension().is_some_and(|ext| ext == "dSYM")
+    && let Some(name) = path.file_stem()
+  {
+    let inner = path.join("Contents/Resources/DWARF").join(name);
+    if inner.is_file() {
+      return inner;
+    }
+  }
+  path.to_path_buf()
+}
+
+fn read_trace(trace: Option<String>) -> Result<String, anyhow::Error> {
+  match trace {
+    Some(trace) if trace != "-" => Ok(trace),
+    _ => {
+      let mut buf = String::new();
+      std::io::stdin().read_to_string(&mut buf)?;
+      Ok(buf)
+    }
+  }
+}
+
+fn load_symcache(path: &Path) -> Result<OwnedSymCache, anyhow::Error> {
+  let bytes = std::fs::read(path)
+    .with_context(|| format!("failed to read {}", path.display()))?;
+  OwnedSymCache::parse(bytes)
+    .with_context(|| format!("failed to parse symcache {}", path.display()))
+}
+
+/// Loads the symcaches of the trace's other modules: those given on the
+/// command line, matched to the modules by build ID, and then those found in
+/// the store.
+fn load_module_symcaches(
+  stack_trace: &StackTrace,
+  paths: &[PathBuf],
+  store: Option<&SymbolStore>,
+) -> Result<BTreeMap<usize, OwnedSymCache>, anyhow::Error> {
+  let mut symcaches = BTreeMap::new();
+  for path in paths {
+    let symcache = load_symcache(path)?;
+    let debug_id = symcache.as_ref().debug_id();
+    let index = stack_trace.modules().iter().position(|module| {
+      module
+        .build_id
+        .as_ref()
+        .is_some_and(|build_id| build_id_to_debug_id(build_id) == debug_id)
+    });
+    match index {
+      Some(index) => {
+        symcaches.insert(index, symcache);
+      }
+      None => eprintln!(
+        "warning: {} ({debug_id}) matches no module of the trace",
+        path.display()
+      ),
+    }
+  }
+  if let Some(store) = store {
+    for (index, file) in store.lookup_modules(stack_trace)? {
+      if let btree_map::Entry::Vacant(entry) = symcaches.entry(index) {
+        entry.insert(store.load_symcache(&file)?);
+      }
+    }
+  }
+  Ok(symcaches)
+}
+
+fn print(output: TraceOutput<'_>, format: Format) -> Result<(), anyhow::Error> {
+  let mut out = std::io::stdout().lock();
+  match format {
+    Format::Text => output.write_text(&mut out)?,
+    Format::Json => output.write_json(&mut out)?,
+  }
+  Ok(())
+}
+
+fn print_stats(stats: &SymCacheStats) {
+  const MIB: f64 = (1 << 20) as f64;
+  eprintln!("debug file: {:.1} MiB", stats.debug_file_size as f64 / MIB);
+  eprintln!("symcache: {:.1} MiB", stats.symcache_size as f64 / MIB);
+  eprintln!("convert: {:.2?}", stats.convert_time);
+  eprintln!("write: {:.2?}", stats.write_time);
+  if let Some(peak_rss) = stats.peak_rss {
+    eprintln!("peak memory: {:.1} MiB", peak_rss as f64 / MIB);
+  }
+}
+
+fn slice_selector(
+  arch: Option<Arch>,
+  debug_id: Option<DebugId>,
+) -> SliceSelector {
+  match (arch, debug_id) {
+    (Some(arch), _) => SliceSelector::Arch(arch),
+    (_, Some(debug_id)) => SliceSelector::DebugId(debug_id),
+    (None, None) => SliceSelector::Only,
+  }
+}
+
+fn main() -> Result<(), anyhow::Error> {
+  let cli = Cli::parse();
+  match cli.command {
+    Command::Symcache {
+      debug_file,
+      output,
+      arch,
+      debug_id,
+      all_slices,
+      stats,
+      with_debug_file,
+      debug_dir,
+    } => {
+      let resolved = resolve_dsym(&debug_file);
+      let output_path = |suffix: &str| -> PathBuf {
+        let mut path = debug_file.clone().into_os_string();
+        path.push(suffix);
+        path.into()
+      };
+      if all_slices {
+        let contents = std::fs::read(&resolved)
+          .with_context(|| format!("failed to read {}", resolved.display()))?;
+        for slice in create_symcaches(&contents)? {
+          let output = output_path(&format!(".{}.symcache", slice.arch));
+          std::fs::write(&output, slice.symcache)
+            .with_context(|| format!("failed to write {}", output.display()))?;
+          println!("{} ({}): {}", slice.arch, slice.debug_id, output.display());
+        }
+        return Ok(());
+      }
+      let selector = slice_selector(arch, debug_id);
+      let output = output.unwrap_or_else(|| output_path(".symcache"));
+      let file = File::create(&output)
+        .with_context(|| format!("failed to create {}", output.display()))?;
+      let search = DebugFileSearch {
+        files: with_debug_file,
+        dirs: debug_dir,
+      };
+      let result = write_symcache_with_debug_files(
+        &resolved,
+        selector,
+        &search,
+        BufWriter::new(file),
+      );
+      let (symcache_stats, debug_files) = match result {
+        Ok(result) => result,
+        Err(error) => {
+          let _ = std::fs::remove_file(&output);
+          return Err(anyhow::Error::from(error)).with_context(|| {
+            format!("failed to create a symcache from {}", resolved.display())
+          });
+        }
+      };
+      for dwo in &debug_files.missing_dwo {
+        eprintln!("warning: split DWARF unit {dwo} not found");
+      }
+      if stats {
+        let used = debug_files
+          .debug_file
+          .iter()
+          .chain(&debug_files.executable)
+          .chain(&debug_files.dwp)
+          .chain(&debug_files.dwo_files);
+        for path in used {
+          eprintln!("using {}", path.display());
+        }
+        print_stats(&symcache_stats);
+      }
+    }
+    Command::Breakpad {
+      debug_file,
+      output,
+      arch,
+      debug_id,
+    } => {
+      let resolved = resolve_dsym(&debug_file);
+      let contents = ByteView::open(&resolved)
+        .with_context(|| format!("failed to read {}", resolved.display()))?;
+      let name = resolved
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+      let selector = slice_selector(arch, debug_id);
+      let result = match &output {
+        Some(output) => {
+          let file = File::create(output).with_context(|| {
+            format!("failed to create {}", output.display())
+          })?;
+          write_breakpad_symbols(
+            &contents,
+            selector,
+            &name,
+            BufWriter::new(file),
+          )
+        }
+        None => write_breakpad_symbols(
+          &contents,
+          selector,
+          &name,
+          std::io::stdout().lock(),
+        ),
+      };
+      result.with_context(|| {
+        format!(
+          "failed to write the Breakpad symbols of {}",
+          resolved.display()
+        )
+      })?;
+    }
+    Command::Trace {
+      symcache,
+      store,
+      trace,
+      format,
+      unwind,
+      module_symcache,
+    } => {
+      let trace = read_trace(trace)?;
+      let trace = find_banner_trace(&trace).unwrap_or(&trace);
+      let mut stack_trace = StackTrace::decode_base64url(trace)
+        .context("failed to decode stack trace")?;
+      if !unwind.is_empty() {
+        stack_trace = unwind_stack_trace_with_files(&stack_trace, &unwind)?
+          .context("the stack trace has no stack memory to unwind")?;
+      }
+      let store = store.map(SymbolStore::open).transpose()?;
+      let symcache = match (symcache, &store) {
+        (Some(symcache), _) => load_symcache(&symcache)?,
+        (None, store) => {
+          let store = store.as_ref().unwrap();
+          let file = store.lookup_trace(stack_trace.header())?;
+          let file = file.with_context(|| {
+            format!(
+              "no debug file for deno {} in the symbol store",
+              stack_trace.header().version()
+            )
+          })?;
+          store.load_symcache(&file)?
+        }
+      };
+      let module_symcaches =
+        load_module_symcaches(&stack_trace, &module_symcache, store.as_ref())?;
+      let symbolicated = symbolicate_stack_trace_with_modules(
+        &stack_trace,
+        &symcache,
+        &module_symcaches,
+      )?;
+      print((&symbolicated).into(), format)?;
+    }
+    Command::Addrs {
+      symcache,
+      addrs,
+      call_sites,
+      format,
+    } => {
+      let frames = addrs
+        .into_iter()
+        .map(|addr| FrameAddr::Stable {
+          addr,
+          call_site: call_sites,
+        })
+        .collect::<Vec<_>>();
+      let symcache = load_symcache(&symcache)?;
+      let frames = symbolicate_frames(&frames, &symcache)?;
+      let output = TraceOutput {
+        header: None,
+        modules: &[],
+        frames: &frames,
+        threads: &[],
+      };
+      print(output, format)?;
+    }
+    Command::Store { root, command } => {
+      let store = SymbolStore::open(&root)?;
+      match command {
+        StoreCommand::Ingest {
+          debug_file,
+          name,
+          version,
+        } => {
+          let name = match name {
+            Some(name) => name,
+            None => debug_file
+              .file_name()
+              .and_then(|name| name.to_str())
+              .context("the debug file's name isn't UTF-8, pass --name")?
+              .trim_end_matches(".dSYM")
+              .to_owned(),
+          };
+          let resolved = resolve_dsym(&debug_file);
+          let contents = ByteView::open(&resolved).with_context(|| {
+            format!("failed to read {}", resolved.display())
+          })?;
+          for object in store.ingest(&name, &contents, version.as_deref())? {
+            println!(
+              "{} ({}): {}",
+              object.arch, object.file_format, object.file
+            );
+          }
+        }
+        StoreCommand::List => {
+          for file in store.list()? {
+            println!("{file}");
+          }
+          for version in store.versions()? {
+            println!("\nversion {version}:");
+            for object in store.version_index(&version)? {
+              println!(
+                "  {} ({}): {}",
+                object.arch, object.file_format, object.file
+              );
+            }
+          }
+        }
+        StoreCommand::Upgrade => {
+          for file in store.upgrade_symcaches()? {
+            println!("recreated the symcache of {file}");
+          }
+        }
+        StoreCommand::Gc {
+          keep,
+          all: _,
+          dry_run,
+        } => {
+          let keep_version = |version: &str| keep.iter().any(|k| k == version);
+          let (report, removed) = if dry_run {
+            (store.gc_report(keep_version)?, "would remove")
+          } else {
+            (store.gc(keep_version)?, "removed")
+          };
+          for version in report.versions {
+            println!("{removed} version {version}");
+          }
+          for file in report.files {
+            println!("{removed} {file}");
+          }
+        }
+      }
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,203 @@
+//! The text and JSON output formats of symbolicated stack traces, shared by
+//! the `deno-symbolicate` CLI and the symbolication server.
+
+use std::io::Write;
+
+use deno_stable_stacktrace::Module;
+use deno_stable_stacktrace::encode::{FrameAddr, Header, ThreadInfo};
+
+use crate::{
+  FrameLocation, FrameQuality, SymbolicatedFrame, SymbolicatedStackTrace,
+  SymbolicatedThread,
+};
+
+/// The parts of a symbolicated stack trace that get printed. Addresses
+/// symbolicated on their own have no header or module table.
+#[derive(Debug, Clone, Copy)]
+pub struct TraceOutput<'a> {
+  pub header: Option<&'a Header>,
+  pub modules: &'a [Module],
+  pub frames: &'a [SymbolicatedFrame],
+  pub threads: &'a [SymbolicatedThread],
+}
+
+impl<'a> From<&'a SymbolicatedStackTrace> for TraceOutput<'a> {
+  fn from(stack_trace: &'a SymbolicatedStackTrace) -> Self {
+    TraceOutput {
+      header: Some(&stack_trace.header),
+      modules: &stack_trace.modules,
+      frames: &stack_trace.frames,
+      threads: &stack_trace.threads,
+    }
+  }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonFrame<'a> {
+  kind: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  addr: Option<String>,
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  call_site: bool,
+  /// Path of the module a non-main-executable frame is in, if known.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  module: Option<&'a str>,
+  locations: &'a [FrameLocation],
+  quality: FrameQuality,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonThread<'a> {
+  thread: &'a ThreadInfo,
+  frames: Vec<JsonFrame<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonStackTrace<'a> {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  header: Option<&'a Header>,
+  frames: Vec<JsonFrame<'a>>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  threads: Vec<JsonThread<'a>>,
+}
+
+/// Returns the path of module `index` in the trace's module table.
+fn module_path(modules: &[Module], index: usize) -> Option<&str> {
+  modules.get(index).map(|module| module.path.as_str())
+}
+
+fn json_frames<'a>(
+  modules: &'a [Module],
+  frames: &'a [SymbolicatedFrame],
+) -> Vec<JsonFrame<'a>> {
+  frames
+    .iter()
+    .map(|symbolicated| {
+      let SymbolicatedFrame {
+        frame,
+        locations,
+        quality,
+      } = symbolicated;
+      let (kind, addr, module) = match frame {
+        FrameAddr::Stable { addr, .. } => ("stable", Some(addr), None),
+        FrameAddr::Absolute { addr, .. } => ("absolute", Some(addr), None),
+        FrameAddr::InModule { module, addr, .. } => {
+          ("inModule", Some(addr), module_path(modules, *module))
+        }
+        FrameAddr::Unknown => ("unknown", None, None),
+      };
+      JsonFrame {
+        kind,
+        addr: addr.map(|addr| format!("{addr:#x}")),
+        call_site: frame.is_call_site(),
+        module,
+        locations,
+        quality: *quality,
+      }
+    })
+    .collect()
+}
+
+impl TraceOutput<'_> {
+  /// Writes the trace as pretty-printed JSON. Addresses are hex strings, as
+  /// JSON numbers can't hold every 64-bit value.
+  pub fn write_json(&self, out: &mut impl Write) -> std::io::Result<()> {
+    let value = JsonStackTrace {
+      header: self.header,
+      frames: json_frames(self.modules, self.frames),
+      threads: self
+        .threads
+        .iter()
+        .map(|thread| JsonThread {
+          thread: &thread.thread,
+          frames: json_frames(self.modules, &thread.frames),
+        })
+        .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &value)?;
+    writeln!(out)
+  }
+
+  /// Writes the trace as a numbered list of frames, one line per entry of
+  /// each frame's inline chain. Frames without debug info are marked
+  /// `[symbol only]`, or `[nearest symbol]` if the symbol may not contain the
+  /// address.
+  pub fn write_text(&self, out: &mut impl Write) -> std::io::Result<()> {
+    if let Some(header) = self.header {
+      writeln!(
+        out,
+        "deno {} ({} {})",
+        header.version(),
+        header.os(),
+        header.arch()
+      )?;
+    }
+    write_text_frames(out, self.modules, self.frames)?;
+    for SymbolicatedThread { thread, frames } in self.threads {
+      match &thread.name {
+        Some(name) => writeln!(out, "\nthread {} ({name}):", thread.id)?,
+        None => writeln!(out, "\nthread {}:", thread.id)?,
+      }
+      if frames.is_empty() {
+        writeln!(out, "  <not captured>")?;
+      }
+      write_text_frames(out, self.modules, frames)?;
+    }
+    Ok(())
+  }
+}
+
+fn write_text_frames(
+  out: &mut impl Write,
+  modules: &[Module],
+  frames: &[SymbolicatedFrame],
+) -> std::io::Result<()> {
+  for (i, symbolicated) in frames.iter().enumerate() {
+    let SymbolicatedFrame {
+      frame,
+      locations,
+      quality,
+    } = symbolicated;
+    match frame {
+      FrameAddr::Stable { addr, .. } => write!(out, "{i:>3}: {addr:#018x}")?,
+      FrameAddr::Absolute { addr, .. } => {
+        writeln!(out, "{i:>3}: {addr:#018x} <outside of main executable>")?;
+        continue;
+      }
+      FrameAddr::InModule { module, addr, .. } => {
+        let path = module_path(modules, *module).unwrap_or("<unknown module>");
+        writeln!(out, "{i:>3}: {addr:#018x} in {path}")?;
+        // The locations, if the module was symbolicated, go on the lines
+        // below.
+        if locations.is_empty() {
+          continue;
+        }
+        write!(out, "{:>23}", "")?;
+      }
+      FrameAddr::Unknown => {
+        writeln!(out, "{i:>3}: <unknown frame>")?;
+        continue;
+      }
+    }
+    if locations.is_empty() {
+      writeln!(out, " <unknown>")?;
+      continue;
+    }
+    for (j, location) in locations.iter().enumerate() {
+      if j > 0 {
+        write!(out, "{:>23}", "")?;
+      }
+      let marker = match quality {
+        _ if location.inlined => " [inlined]",
+        FrameQuality::SymbolOnly => " [symbol only]",
+        FrameQuality::NearestSymbol => " [nearest symbol]",
+        _ => "",
+      };
+      writeln!(out, " at {location}{marker}")?;
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,666 @@
+//! A symbol store: a plain directory of debug files and their symcaches,
+//! keyed by debug ID like Microsoft's symstore and Breakpad's symbol
+//! directories.
+//!
+//! ```text
+//! <root>/<name>/<DEBUG_ID>/<name>           the debug file as ingested
+//! <root>/<name>/<DEBUG_ID>/<name>.symcache  its symcache
+//! <root>/.versions/<version>                the debug files of a version
+//! ```
+//!
+//! `DEBUG_ID` is the Breakpad form of the debug ID (uppercase hex, without
+//! dashes). Traces with a build ID are looked up by it directly, and older
+//! traces through the version index, which lists a debug file per
+//! architecture and file format with lines of `<arch> <format> <ID> <name>`.
+//! When files of several names share a debug ID, e.g. a stripped `deno` and
+//! its `deno.debug`, lookups prefer one with debug info.
+//!
+//! Every file is written to a temporary file and renamed into place, so
+//! readers never see partially written files. Symcaches in an older format
+//! (see [`symcache_format`](crate::symcache_format)) or that are corrupt are
+//! recreated from the stored debug file when loaded, or all at once with
+//! [`SymbolStore::upgrade_symcaches`].
+
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use deno_stable_stacktrace::encode;
+use symbolic::common::{Arch, ByteView, DebugId};
+use symbolic::debuginfo::{Archive, FileFormat};
+
+use crate::{
+  OwnedSymCache, SliceSelector, SymbolicateError, build_id_to_debug_id,
+  create_symcache, object_symcache, parse_object, symbolic_arch,
+  symcache_format,
+};
+
+const VERSIONS_DIR: &str = ".versions";
+
+/// A debug file in a [`SymbolStore`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StoredDebugFile {
+  pub name: String,
+  pub debug_id: DebugId,
+}
+
+/// Formats as the file's directory relative to the store, `name/DEBUG_ID`.
+impl std::fmt::Display for StoredDebugFile {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}/{}", self.name, self.debug_id.breakpad())
+  }
+}
+
+/// An object of an ingested debug file, or an entry of the version index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+  pub arch: Arch,
+  pub file_format: FileFormat,
+  pub file: StoredDebugFile,
+}
+
+/// What [`SymbolStore::gc`] removed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GcReport {
+  pub versions: Vec<String>,
+  pub files: Vec<StoredDebugFile>,
+}
+
+/// A symbol store on disk, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct SymbolStore {
+  root: PathBuf,
+}
+
+impl SymbolStore {
+  /// Opens the store at `root`, creating the directory if needed.
+  pub fn open(root: impl Into<PathBuf>) -> Result<Self, SymbolicateError> {
+    let root = root.into();
+    std::fs::create_dir_all(root.join(VERSIONS_DIR))?;
+    Ok(Self { root })
+  }
+
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  fn dir(&self, file: &StoredDebugFile) -> PathBuf {
+    self
+      .root
+      .join(&file.name)
+      .join(file.debug_id.breakpad().to_string())
+  }
+
+  /// The path of the debug file as it was ingested.
+  pub fn debug_file_path(&self, file: &StoredDebugFile) -> PathBuf {
+    self.dir(file).join(&file.name)
+  }
+
+  pub fn symcache_path(&self, file: &StoredDebugFile) -> PathBuf {
+    self.dir(file).join(format!("{}.symcache", file.name))
+  }
+
+  /// Stores every object of a (possibly fat) debug file along with its
+  /// symcache, and adds them to the index of `version`, if given, replacing
+  /// the version's previous files for the same architecture and format.
+  ///
+  /// `name` is the file name to store it under, usually the debug file's own
+  /// name (e.g. `deno` or `deno.debug`). Fat debug files are stored in full
+  /// for each of their objects.
+  pub fn ingest(
+    &self,
+    name: &str,
+    debug_file: &[u8],
+    version: Option<&str>,
+  ) -> Result<Vec<StoredObject>, SymbolicateError> {
+    check_key("debug file name", name)?;
+    if let Some(version) = version {
+      check_key("version", version)?;
+    }
+    let byteview = ByteView::from_slice(debug_file);
+    let archive =
+      Archive::parse(&byteview).map_err(SymbolicateError::InvalidDebugFile)?;
+    let mut stored = Vec::new();
+    for object in archive.objects() {
+      let object = object.map_err(SymbolicateError::InvalidDebugFile)?;
+      let file = StoredDebugFile {
+        name: name.to_owned(),
+        debug_id: object.debug_id(),
+      };
+      let symcache = object_symcache(&object)?;
+      std::fs::create_dir_all(self.dir(&file))?;
+      write_atomic(&self.debug_file_path(&file), debug_file)?;
+      write_atomic(&self.symcache_path(&file), &symcache)?;
+      stored.push(StoredObject {
+        arch: object.arch(),
+        file_format: object.file_format(),
+        file,
+      });
+    }
+
+    if let Some(version) = version {
+      let mut index = self.version_index(version)?;
+      index.retain(|entry| {
+        !stored.iter().any(|object| {
+          object.arch == entry.arch && object.file_format == entry.file_format
+        })
+      });
+      index.extend(stored.iter().cloned());
+      self.write_version_index(version, &index)?;
+    }
+    Ok(stored)
+  }
+
+  /// Finds the debug file with `debug_id`, whatever name it was stored
+  /// under. If several names have it, the first (by name) with debug info
+  /// rather than only a symbol table is returned.
+  pub fn lookup(
+    &self,
+    debug_id: DebugId,
+  ) -> Result<Option<StoredDebugFile>, SymbolicateError> {
+    let mut names = list_dir(&self.root)?;
+    names.sort();
+    let mut found = Vec::new();
+    for name in names {
+      if name.starts_with('.') {
+        continue;
+      }
+      let file = StoredDebugFile { name, debug_id };
+      if self.symcache_path(&file).is_file() {
+        found.push(file);
+      }
+    }
+    if found.len() > 1
+      && let Some(index) =
+        found.iter().position(|file| self.has_debug_info(file))
+    {
+      return Ok(Some(found.swap_remove(index)));
+    }
+    Ok(found.into_iter().next())
+  }
+
+  /// Whether the stored debug file has debug info for its object, unlike
+  /// e.g. a stripped executable.
+  fn has_debug_info(&self, file: &StoredDebugFile) -> bool {
+    let Ok(byteview) = ByteView::open(self.debug_file_path(file)) else {
+      return false;
+    };
+    parse_object(&byteview, SliceSelector::DebugId(file.debug_id))
+      .is_ok_and(|object| object.has_debug_info())
+  }
+
+  /// Looks up the debug file for a version's build for `os` and `arch`.
+  pub fn lookup_version(
+    &self,
+    version: &str,
+    os: &encode::Os,
+    arch: &encode::Arch,
+  ) -> Result<Option<StoredDebugFile>, SymbolicateError> {
+    let file_formats: &[FileFormat] = match os {
+      encode::Os::Linux => &[FileFormat::Elf],
+      encode::Os::Mac => &[FileFormat::MachO],
+      encode::Os::Windows => &[FileFormat::Pdb, FileFormat::Pe],
+      encode::Os::Other(_) => &[],
+    };
+    let Some(arch) = symbolic_arch(arch) else {
+      return Ok(None);
+    };
+    let entry = self.version_index(version)?.into_iter().find(|entry| {
+      entry.arch.cpu_family() == arch.cpu_family()
+        && (file_formats.is_empty()
+          || file_formats.contains(&entry.file_format))
+    });
+    Ok(entry.map(|entry| entry.file))
+  }
+
+  /// Finds the debug file for the binary a stack trace was captured from, by
+  /// its build ID or else its version.
+  pub fn lookup_trace(
+    &self,
+    header: &encode::Header,
+  ) -> Result<Option<StoredDebugFile>, SymbolicateError> {
+    if let Some(build_id) = header.build_id() {
+      return self.lookup(build_id_to_debug_id(build_id));
+    }
+    self.lookup_version(
+      &header.version().to_string(),
+      header.os(),
+      header.arch(),
+    )
+  }
+
+  /// Finds the debug files of the modules that a stack trace's `InModule`
+  /// frames (including other threads') are in, by the modules' build IDs,
+  /// keyed by module index. Modules without a build ID, or whose debug file
+  /// isn't stored, are left out.
+  pub fn lookup_modules(
+    &self,
+    stack_trace: &encode::StackTrace,
+  ) -> Result<BTreeMap<usize, StoredDebugFile>, SymbolicateError> {
+    let frames = stack_trace
+      .frames()
+      .iter()
+      .chain(stack_trace.threads().iter().flat_map(|t| &t.frames));
+    let mut files = BTreeMap::new();
+    for frame in frames {
+      let encode::FrameAddr::InModule { module, .. } = *frame else {
+        continue;
+      };
+      if files.contains_key(&module) {
+        continue;
+      }
+      let build_id = stack_trace
+        .modules()
+        .get(module)
+        .and_then(|module| module.build_id.as_ref());
+      if let Some(build_id) = build_id
+        && let Some(file) = self.lookup(build_id_to_debug_id(build_id))?
+      {
+        files.insert(module, file);
+      }
+    }
+    Ok(files)
+  }
+
+  /// Loads the symcache of `file`, first recreating it from the debug file
+  /// if it isn't in the latest format or is corrupt. Without the debug file,
+  /// symcaches in an older but still supported format are used as they are.
+  pub fn load_symcache(
+    &self,
+    file: &StoredDebugFile,
+  ) -> Result<OwnedSymCache, SymbolicateError> {
+    let mut symcache = std::fs::read(self.symcache_path(file))?;
+    if is_stale(&symcache)
+      && let Some(recreated) = self.recreate_symcache(file)?
+    {
+      symcache = recreated;
+    }
+    OwnedSymCache::parse(symcache)
+  }
+
+  /// Recreates every symcache that isn't in the latest format or is corrupt,
+  /// e.g. after upgrading `symbolic`, and returns the files whose symcache
+  /// was recreated. Symcaches of debug files that are missing are left alone.
+  pub fn upgrade_symcaches(
+    &self,
+  ) -> Result<Vec<StoredDebugFile>, SymbolicateError> {
+    let mut upgraded = Vec::new();
+    for file in self.list()? {
+      let symcache = match std::fs::read(self.symcache_path(&file)) {
+        Ok(symcache) => symcache,
+        Err(error) if error.kind() == ErrorKind::NotFound => continue,
+        Err(error) => return Err(error.into()),
+      };
+      if !is_stale(&symcache) {
+        continue;
+      }
+      if self.recreate_symcache(&file)?.is_some() {
+        upgraded.push(file);
+      }
+    }
+    Ok(upgraded)
+  }
+
+  /// Creates the symcache of `file` from its debug file again, or returns
+  /// `None` if the debug file is gone.
+  fn recreate_symcache(
+    &self,
+    file: &StoredDebugFile,
+  ) -> Result<Option<Vec<u8>>, SymbolicateError> {
+    // Debug files can be gigabytes large, so map them rather than read them.
+    let debug_file = match ByteView::open(self.debug_file_path(file)) {
+      Ok(debug_file) => debug_file,
+      Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+      Err(error) => return Err(error.into()),
+    };
+    let symcache =
+      create_symcache(&debug_file, SliceSelector::DebugId(file.debug_id))?;
+    write_atomic(&self.symcache_path(file), &symcache)?;
+    Ok(Some(symcache))
+  }
+
+  /// Lists the stored debug files, sorted by name and debug ID.
+  pub fn list(&self) -> Result<Vec<StoredDebugFile>, SymbolicateError> {
+    let mut files = Vec::new();
+    for name in list_dir(&self.root)? {
+      if name.starts_with('.') {
+        continue;
+      }
+      for debug_id in list_dir(&self.root.join(&name))? {
+        if let Ok(debug_id) = DebugId::from_breakpad(&debug_id) {
+          files.push(StoredDebugFile {
+            name: name.clone(),
+            debug_id,
+          });
+        }
+      }
+    }
+    files.sort_by(|a, b| (&a.name, a.debug_id).cmp(&(&b.name, b.debug_id)));
+    Ok(files)
+  }
+
+  /// Lists the versions in the index, sorted.
+  pub fn versions(&self) -> Result<Vec<String>, SymbolicateError> {
+    let mut versions = list_dir(&self.root.join(VERSIONS_DIR))?;
+    versions.retain(|version| check_key("version", version).is_ok());
+    versions.sort();
+    Ok(versions)
+  }
+
+  /// Returns the debug files indexed for `version`, or nothing if it isn't
+  /// in the index.
+  pub fn version_index(
+    &self,
+    version: &str,
+  ) -> Result<Vec<StoredObject>, SymbolicateError> {
+    check_key("version", version)?;
+    let index = match std::fs::read_to_string(self.version_path(version)) {
+      Ok(index) => index,
+      Err(error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+      Err(error) => return Err(error.into()),
+    };
+    // Skip lines that don't parse rather than failing, e.g. ones written by
+    // a newer version of this crate.
+    Ok(index.lines().filter_map(parse_index_line).collect())
+  }
+
+  fn version_path(&self, version: &str) -> PathBuf {
+    self.root.join(VERSIONS_DIR).join(version)
+  }
+
+  fn write_version_index(
+    &self,
+    version: &str,
+    index: &[StoredObject],
+  ) -> Result<(), SymbolicateError> {
+    let contents = index
+      .iter()
+      .map(|entry| {
+        format!(
+          "{} {} {} {}\n",
+          entry.arch,
+          entry.file_format,
+          entry.file.debug_id.breakpad(),
+          entry.file.name
+        )
+      })
+      .collect::<String>();
+    write_atomic(&self.version_path(version), contents.as_bytes())?;
+    Ok(())
+  }
+
+  /// Removes the versions `keep_version` returns `false` for from the index,
+  /// and then every debug file that no remaining version refers to. That
+  /// includes debug files that were ingested without a version.
+  pub fn gc(
+    &self,
+    keep_version: impl FnMut(&str) -> bool,
+  ) -> Result<GcReport, SymbolicateError> {
+    let report = self.gc_report(keep_version)?;
+    for version in &report.versions {
+      std::fs::remove_file(self.version_path(version))?;
+    }
+    for file in &report.files {
+      std::fs::remove_dir_all(self.dir(file))?;
+      // Only succeeds once the name's last debug file is gone.
+      let _ = std::fs::remove_dir(self.root.join(&file.name));
+    }
+    Ok(report)
+  }
+
+  /// Returns what [`gc`](Self::gc) would remove, without removing anything.
+  pub fn gc_report(
+    &self,
+    mut keep_version: impl FnMut(&str) -> bool,
+  ) -> Result<GcReport, SymbolicateError> {
+    let mut report = GcReport::default();
+    let mut referenced = std::collections::HashSet::new();
+    for version in self.versions()? {
+      if keep_version(&version) {
+        referenced.extend(
+          self
+            .version_index(&version)?
+            .into_iter()
+            .map(|entry| entry.file),
+        );
+      } else {
+        report.versions.push(version);
+      }
+    }
+    for file in self.list()? {
+      if !referenced.contains(&file) {
+        report.files.push(file);
+      }
+    }
+    Ok(report)
+  }
+}
+
+/// Whether a symcache should be recreated from its debug file: when it isn't
+/// in the latest format, or is corrupt.
+fn is_stale(symcache: &[u8]) -> bool {
+  !symcache_format(symcache).is_ok_and(|format| format.latest)
+}
+
+/// Names and versions end up as path components, so only allow what they
+/// consist of.
+fn check_key(kind: &'static str, value: &str) -> Result<(), SymbolicateError> {
+  let valid = !value.is_empty()
+    && !value.starts_with('.')
+    && value.bytes().all(|b| {
+      b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-' | b'+')
+    });
+  if !valid {
+    return Err(SymbolicateError::InvalidStoreKey {
+      kind,
+      value: value.to_owned(),
+    });
+  }
+  Ok(())
+}
+
+fn parse_index_line(line: &str) -> Option<StoredObject> {
+  let mut fields = line.split(' ');
+  let arch = fields.next()?.parse().ok()?;
+  let file_format = fields.next()?.parse().ok()?;
+  let debug_id = DebugId::from_breakpad(fields.next()?).ok()?;
+  let name = fields.next()?;
+  check_key("debug file name", name).ok()?;
+  Some(StoredObject {
+    arch,
+    file_format,
+    file: StoredDebugFile {
+      name: name.to_owned(),
+      debug_id,
+    },
+  })
+}
+
+/// Returns the names of the entries of `dir`, or nothing if it doesn't exist.
+fn list_dir(dir: &Path) -> Result<Vec<String>, SymbolicateError> {
+  let entries = match std::fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+    Err(error) => return Err(error.into()),
+  };
+  let mut names = Vec::new();
+  for entry in entries {
+    if let Ok(name) = entry?.file_name().into_string() {
+      names.push(name);
+    }
+  }
+  Ok(names)
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+  static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+  let mut temp_path = path.as_os_str().to_owned();
+  temp_path.push(format!(
+    ".tmp-{}-{}",
+    std::process::id(),
+    TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+  ));
+  std::fs::write(&temp_path, contents)?;
+  std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tests::{TempDir, universal};
+
+  #[test]
+  fn ingest_lookup_and_gc() {
+    let root = TempDir::new("symstore");
+    let store = SymbolStore::open(&root.0).unwrap();
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+
+    let stored = store.ingest("deno", &exe, Some("1.11.0")).unwrap();
+    assert_eq!(stored.len(), 1);
+    let file = &stored[0].file;
+    assert!(store.debug_file_path(file).is_file());
+    let symcache = store.load_symcache(file).unwrap();
+    assert_eq!(symcache.as_ref().debug_id(), file.debug_id);
+    assert_eq!(store.lookup(file.debug_id).unwrap().as_ref(), Some(file));
+    assert_eq!(store.lookup(DebugId::nil()).unwrap(), None);
+
+    let os: encode::Os = std::env::consts::OS.to_string().into();
+    let arch: encode::Arch = std::env::consts::ARCH.into();
+    assert_eq!(
+      store.lookup_version("1.11.0", &os, &arch).unwrap().as_ref(),
+      Some(file)
+    );
+    // Versions don't run into each other.
+    assert_eq!(store.lookup_version("1.1.10", &os, &arch).unwrap(), None);
+    if let Some(build_id) = deno_stable_stacktrace::main_module_build_id() {
+      let version = encode::Version {
+        major: 0,
+        minor: 0,
+        patch: 0,
+        canary_hash: encode::CanaryHash::none(),
+        dev_build: false,
+      };
+      let stack_trace = encode::StackTrace::new(
+        vec![],
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        version,
+      )
+      .with_build_id(build_id.clone());
+      assert_eq!(
+        store.lookup_trace(stack_trace.header()).unwrap().as_ref(),
+        Some(file)
+      );
+
+      // Frames in other modules are looked up by the module's build ID.
+      let module = |build_id| deno_stable_stacktrace::Module {
+        path: "lib".into(),
+        base: 0,
+        build_id,
+      };
+      let in_module = |module| encode::FrameAddr::InModule {
+        module,
+        addr: 0,
+        call_site: false,
+      };
+      let stack_trace = stack_trace
+        .with_frames(vec![in_module(1), in_module(2), in_module(3)])
+        .with_modules(vec![
+          module(None),
+          module(Some(build_id)),
+          module(None),
+          module(Some(deno_stable_stacktrace::BuildId::Gnu(vec![1]))),
+        ]);
+      assert_eq!(
+        store.lookup_modules(&stack_trace).unwrap(),
+        BTreeMap::from([(1, file.clone())])
+      );
+    }
+
+    // Each slice of a universal binary gets its own entry.
+    let slices = store.ingest("universal", &universal(), Some("2.0.0"));
+    let slices = slices.unwrap();
+    assert_eq!(slices.len(), 2);
+    assert_eq!(store.version_index("2.0.0").unwrap(), slices);
+    let mac = encode::Os::Mac;
+    let arm64 = store
+      .lookup_version("2.0.0", &mac, &"aarch64".into())
+      .unwrap()
+      .unwrap();
+    assert_eq!(
+      arm64,
+      slices.iter().find(|s| s.arch == Arch::Arm64).unwrap().file
+    );
+    assert_eq!(store.versions().unwrap(), ["1.11.0", "2.0.0"]);
+    assert_eq!(store.list().unwrap().len(), 3);
+
+    let report = store.gc_report(|version| version != "2.0.0").unwrap();
+    assert_eq!(store.list().unwrap().len(), 3);
+    assert_eq!(store.gc(|version| version != "2.0.0").unwrap(), report);
+    assert_eq!(report.versions, ["2.0.0"]);
+    assert_eq!(report.files.len(), 2);
+    assert_eq!(store.list().unwrap(), std::slice::from_ref(file));
+    assert!(!root.0.join("universal").exists());
+
+    let error = store.ingest("../deno", &exe, None).unwrap_err();
+    assert_eq!(error.code(), "invalid_store_key");
+  }
+
+  #[test]
+  fn lookup_prefers_debug_info() {
+    let root = TempDir::new("symstore-stripped");
+    let store = SymbolStore::open(&root.0).unwrap();
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    let debug_file = std::fs::read(fixtures.join("elf/plain.debug")).unwrap();
+    let stripped = std::fs::read(fixtures.join("elf/plain")).unwrap();
+
+    let stored = store.ingest("plain.debug", &debug_file, None).unwrap();
+    let file = &stored[0].file;
+    // The stripped executable has the same debug ID, and its name sorts
+    // first.
+    let stripped = store.ingest("plain", &stripped, None).unwrap();
+    assert_eq!(stripped[0].file.debug_id, file.debug_id);
+    assert_eq!(store.lookup(file.debug_id).unwrap().as_ref(), Some(file));
+  }
+
+  #[test]
+  fn recreate_stale_symcaches() {
+    let root = TempDir::new("symstore-stale");
+    let store = SymbolStore::open(&root.0).unwrap();
+    let stored = store.ingest("universal", &universal(), None).unwrap();
+    let file = &stored[0].file;
+    let symcache_path = store.symcache_path(file);
+    let make_stale = || {
+      let mut symcache = std::fs::read(&symcache_path).unwrap();
+      // A format version `symbolic` no longer reads.
+      symcache[4..8].copy_from_slice(&6u32.to_le_bytes());
+      std::fs::write(&symcache_path, symcache).unwrap();
+    };
+
+    make_stale();
+    let symcache = store.load_symcache(file).unwrap();
+    assert_eq!(symcache.as_ref().debug_id(), file.debug_id);
+    let format = symcache_format(&std::fs::read(&symcache_path).unwrap());
+    assert!(format.unwrap().latest);
+
+    // So is a corrupt one.
+    std::fs::write(&symcache_path, b"garbage").unwrap();
+    let symcache = store.load_symcache(file).unwrap();
+    assert_eq!(symcache.as_ref().debug_id(), file.debug_id);
+
+    make_stale();
+    assert_eq!(
+      store.upgrade_symcaches().unwrap(),
+      std::slice::from_ref(file)
+    );
+    assert_eq!(store.upgrade_symcaches().unwrap(), []);
+
+    // Without the debug file, there's nothing to recreate it from.
+    make_stale();
+    std::fs::remove_file(store.debug_file_path(file)).unwrap();
+    let error = store.load_symcache(file).err().unwrap();
+    assert_eq!(error.code(), "unsupported_symcache_version", "{error}");
+    assert_eq!(store.upgrade_symcaches().unwrap(), []);
+  }
+}
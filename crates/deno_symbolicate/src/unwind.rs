@@ -0,0 +1,907 @@
+//! Unwinds stacks offline, from the registers and stack memory a client
+//! captured along with a trace (see [`StackMemory`]).
+//!
+//! Client-side unwinding stops at frames without unwind info or with a
+//! clobbered frame pointer. Here the main executable's call frame information
+//! (CFI) is read from its files instead: `.eh_frame` and `.debug_frame`, and
+//! the compact unwind info of Mach-O executables. Frames in other modules, or
+//! without CFI, fall back to following the frame pointer.
+//!
+//! Only the captured slice of the stack can be unwound, so the client's
+//! frames are kept after the last frame both found (see
+//! [`unwind_stack_trace`]).
+
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use deno_stable_stacktrace::encode::{self, FrameAddr, StackTrace};
+use deno_stable_stacktrace::{Module, StackMemory};
+use gimli::{
+  BaseAddresses, CfaRule, DebugFrame, EhFrame, EndianSlice, RegisterRule,
+  RunTimeEndian, UnwindContext, UnwindSection, UnwindTableRow,
+};
+use symbolic::common::ByteView;
+use symbolic::debuginfo::Object;
+use symbolic::debuginfo::dwarf::Dwarf;
+use symbolic::debuginfo::macho::{
+  CompactCfiOp, CompactCfiRegister, CompactUnwindInfoEntry,
+  CompactUnwindInfoIter, CompactUnwindOp, MachObject,
+};
+
+use crate::{
+  DebugIdMismatch, SliceSelector, SymbolicateError, build_id_to_debug_id,
+  parse_object,
+};
+
+type Reader<'d> = EndianSlice<'d, RunTimeEndian>;
+
+/// Unwinding stops after this many frames, in case the stack is corrupt in a
+/// way that looks like an endless loop of frames.
+const MAX_FRAMES: usize = 256;
+
+/// Registers are tracked by DWARF number, up to `sp` on aarch64. The others
+/// (e.g. vector registers) don't matter for unwinding.
+const REGISTER_COUNT: usize = 32;
+
+type Registers = [Option<u64>; REGISTER_COUNT];
+
+/// The registers unwinding needs, by DWARF number.
+struct ArchRegisters {
+  stack_pointer: u16,
+  frame_pointer: u16,
+  /// The register holding the return address on entry to a function: the
+  /// instruction pointer on x86_64 (which isn't captured), and the link
+  /// register on aarch64.
+  return_address: u16,
+  /// The bits of a return address that hold the address, which excludes
+  /// pointer authentication codes on aarch64.
+  address_mask: u64,
+}
+
+impl ArchRegisters {
+  fn for_arch(arch: &encode::Arch) -> Result<Self, SymbolicateError> {
+    match arch {
+      encode::Arch::X86_64 => Ok(ArchRegisters {
+        stack_pointer: 7,
+        frame_pointer: 6,
+        return_address: 16,
+        address_mask: u64::MAX,
+      }),
+      encode::Arch::Aarch64 => Ok(ArchRegisters {
+        stack_pointer: 31,
+        frame_pointer: 29,
+        return_address: 30,
+        address_mask: (1 << 48) - 1,
+      }),
+      encode::Arch::Other(other) => Err(
+        SymbolicateError::UnsupportedUnwindArch(other.as_str().into()),
+      ),
+    }
+  }
+
+  /// The rules for a frame that saved the caller's frame pointer and return
+  /// address right below its own frame pointer, which both x86_64 and
+  /// aarch64 prologues do when frame pointers are enabled.
+  fn frame_pointer_rules(&self) -> FrameRules {
+    FrameRules {
+      cfa: (self.frame_pointer, 16),
+      return_address: Some(Rule::At(-8)),
+      registers: vec![(self.frame_pointer, Rule::At(-16))],
+    }
+  }
+}
+
+/// How to recover a value of the caller from the callee's registers and the
+/// canonical frame address (CFA), i.e. the stack pointer before the call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+  Undefined,
+  SameValue,
+  /// Saved at the CFA plus an offset.
+  At(i64),
+  /// The CFA plus an offset.
+  Is(i64),
+  /// In another register of the callee.
+  Register(u16),
+}
+
+/// How to recover the caller's registers at an address, from CFI or compact
+/// unwind info. Registers without a rule keep their value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FrameRules {
+  /// The register the CFA is relative to, and the offset.
+  cfa: (u16, i64),
+  /// `None` if there's no rule for the return address, as in leaf functions
+  /// on aarch64, which leave it in the link register.
+  return_address: Option<Rule>,
+  registers: Vec<(u16, Rule)>,
+}
+
+impl FrameRules {
+  fn from_row(
+    row: &UnwindTableRow<usize>,
+    return_address_register: gimli::Register,
+  ) -> Option<Self> {
+    let cfa = match *row.cfa() {
+      CfaRule::RegisterAndOffset { register, offset } => (register.0, offset),
+      CfaRule::Expression(_) => return None,
+    };
+    let convert = |rule: &RegisterRule<usize>| match *rule {
+      RegisterRule::SameValue => Rule::SameValue,
+      RegisterRule::Offset(offset) => Rule::At(offset),
+      RegisterRule::ValOffset(offset) => Rule::Is(offset),
+      RegisterRule::Register(register) => Rule::Register(register.0),
+      // Expressions aren't evaluated, so the value is unknown.
+      _ => Rule::Undefined,
+    };
+    // gimli reports registers without a rule as undefined, but only an
+    // explicit rule marks the outermost frame.
+    let return_address = row
+      .registers()
+      .find(|(register, _)| *register == return_address_register)
+      .map(|(_, rule)| convert(rule));
+    Some(FrameRules {
+      cfa,
+      return_address,
+      registers: row
+        .registers()
+        .filter(|(register, _)| *register != return_address_register)
+        .map(|(register, rule)| (register.0, convert(rule)))
+        .collect(),
+    })
+  }
+
+  /// Recovers the caller's return address and registers, or `None` if the
+  /// return address is undefined (at the outermost frame) or can't be read.
+  ///
+  /// Without a rule for the return address, it's still in its register, but
+  /// only in the `innermost` frame: callers have called another function
+  /// since, which overwrote it.
+  fn caller(
+    &self,
+    registers: &Registers,
+    stack_memory: &StackMemory,
+    arch: &ArchRegisters,
+    innermost: bool,
+  ) -> Option<(u64, Registers)> {
+    let get = |number: u16| *registers.get(number as usize)?;
+    let cfa = get(self.cfa.0)?.checked_add_signed(self.cfa.1)?;
+    let eval = |number: Option<u16>, rule: Rule| match rule {
+      Rule::Undefined => None,
+      Rule::SameValue => get(number?),
+      Rule::At(offset) => {
+        stack_memory.read_u64(cfa.checked_add_signed(offset)?)
+      }
+      Rule::Is(offset) => cfa.checked_add_signed(offset),
+      Rule::Register(register) => get(register),
+    };
+    let mut caller = *registers;
+    for &(number, rule) in &self.registers {
+      if let Some(slot) = caller.get_mut(number as usize) {
+        *slot = eval(Some(number), rule);
+      }
+    }
+    caller[arch.stack_pointer as usize] = Some(cfa);
+    let return_address = match self.return_address {
+      Some(rule) => rule,
+      None if innermost => Rule::Register(arch.return_address),
+      None => Rule::Undefined,
+    };
+    let ip = eval(None, return_address)? & arch.address_mask;
+    Some((ip, caller))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CfiKind {
+  EhFrame,
+  DebugFrame,
+}
+
+/// The FDEs of an `.eh_frame` or `.debug_frame` section.
+struct CfiSection<'d> {
+  kind: CfiKind,
+  data: Cow<'d, [u8]>,
+  endian: RunTimeEndian,
+  bases: BaseAddresses,
+  /// The addresses each FDE covers and its offset, sorted by address.
+  fdes: Vec<(Range<u64>, usize)>,
+}
+
+impl<'d> CfiSection<'d> {
+  fn new(
+    kind: CfiKind,
+    data: Cow<'d, [u8]>,
+    endian: RunTimeEndian,
+    bases: BaseAddresses,
+  ) -> Result<Self, gimli::Error> {
+    let mut cfi = CfiSection {
+      kind,
+      data,
+      endian,
+      bases,
+      fdes: Vec::new(),
+    };
+    let mut fdes = match kind {
+      CfiKind::EhFrame => collect_fdes(cfi.eh_frame(), &cfi.bases)?,
+      CfiKind::DebugFrame => collect_fdes(cfi.debug_frame(), &cfi.bases)?,
+    };
+    fdes.sort_by_key(|(range, _)| range.start);
+    cfi.fdes = fdes;
+    Ok(cfi)
+  }
+
+  fn eh_frame(&self) -> EhFrame<Reader<'_>> {
+    let mut section = EhFrame::new(&self.data, self.endian);
+    section.set_address_size(8);
+    section
+  }
+
+  fn debug_frame(&self) -> DebugFrame<Reader<'_>> {
+    let mut section = DebugFrame::new(&self.data, self.endian);
+    section.set_address_size(8);
+    section
+  }
+
+  fn fde_offset(&self, address: u64) -> Option<usize> {
+    let index = self
+      .fdes
+      .partition_point(|(range, _)| range.start <= address)
+      .checked_sub(1)?;
+    let (range, offset) = &self.fdes[index];
+    range.contains(&address).then_some(*offset)
+  }
+
+  /// The rules of the FDE at `offset` for `address`.
+  fn rules(&self, offset: usize, address: u64) -> Option<FrameRules> {
+    match self.kind {
+      CfiKind::EhFrame => {
+        fde_rules(&self.eh_frame(), &self.bases, offset, address)
+      }
+      CfiKind::DebugFrame => {
+        fde_rules(&self.debug_frame(), &self.bases, offset, address)
+      }
+    }
+  }
+}
+
+fn collect_fdes<'a, S: UnwindSection<Reader<'a>>>(
+  section: S,
+  bases: &BaseAddresses,
+) -> Result<Vec<(Range<u64>, usize)>, gimli::Error> {
+  let mut fdes = Vec::new();
+  let mut entries = section.entries(bases);
+  while let Some(entry) = entries.next()? {
+    if let gimli::CieOrFde::Fde(partial) = entry {
+      let fde = partial.parse(S::cie_from_offset)?;
+      fdes.push((fde.initial_address()..fde.end_address(), fde.offset()));
+    }
+  }
+  Ok(fdes)
+}
+
+fn fde_rules<'a, S: UnwindSection<Reader<'a>>>(
+  section: &S,
+  bases: &BaseAddresses,
+  offset: usize,
+  address: u64,
+) -> Option<FrameRules>
+where
+  S::Offset: From<usize>,
+{
+  let fde = section
+    .fde_from_offset(bases, offset.into(), S::cie_from_offset)
+    .ok()?;
+  let mut context = UnwindContext::new();
+  let row = fde
+    .unwind_info_for_address(section, bases, &mut context, address)
+    .ok()?;
+  FrameRules::from_row(row, fde.cie().return_address_register())
+}
+
+/// The compact unwind info (`__unwind_info`) of a Mach-O executable.
+struct CompactUnwind<'d> {
+  iter: CompactUnwindInfoIter<'d>,
+  /// Sorted by address, which is relative to the image like stable addresses.
+  entries: Vec<CompactUnwindInfoEntry>,
+}
+
+impl<'d> CompactUnwind<'d> {
+  fn new(object: &MachObject<'d>) -> Result<Option<Self>, SymbolicateError> {
+    let Some(mut iter) = object.compact_unwind_info().map_err(unwind_error)?
+    else {
+      return Ok(None);
+    };
+    let template = iter.clone();
+    let mut entries = Vec::new();
+    while let Some(entry) = iter.next().map_err(unwind_error)? {
+      entries.push(entry);
+    }
+    entries.sort_by_key(|entry| entry.instruction_address);
+    Ok(Some(CompactUnwind {
+      iter: template,
+      entries,
+    }))
+  }
+
+  fn entry(&self, addr: u64) -> Option<&CompactUnwindInfoEntry> {
+    let index = self
+      .entries
+      .partition_point(|entry| u64::from(entry.instruction_address) <= addr)
+      .checked_sub(1)?;
+    let entry = &self.entries[index];
+    (addr - u64::from(entry.instruction_address) < u64::from(entry.len))
+      .then_some(entry)
+  }
+
+  /// The DWARF number of a register in compact unwind ops, if it matters
+  /// for unwinding.
+  fn register(&self, register: CompactCfiRegister) -> Option<u16> {
+    Some(match register.name(&self.iter)? {
+      "rbx" => 3,
+      "rbp" => 6,
+      "rsp" => 7,
+      "r12" => 12,
+      "r13" => 13,
+      "r14" => 14,
+      "r15" => 15,
+      "sp" => 31,
+      name => name.strip_prefix('x')?.parse().ok()?,
+    })
+  }
+
+  fn rules(&self, entry: &CompactUnwindInfoEntry) -> Option<FrameRules> {
+    let CompactUnwindOp::CfiOps(ops) = entry.instructions(&self.iter) else {
+      return None;
+    };
+    let mut cfa = None;
+    let mut return_address = None;
+    let mut registers = Vec::new();
+    for op in ops {
+      let (dest, rule) = match op {
+        CompactCfiOp::RegisterIs {
+          dest_reg,
+          src_reg,
+          offset_from_src,
+        } if dest_reg.is_cfa() => {
+          let src = self.register(src_reg)?;
+          cfa = Some((src, i64::from(offset_from_src)));
+          continue;
+        }
+        CompactCfiOp::RegisterIs {
+          dest_reg,
+          src_reg,
+          offset_from_src,
+        } => {
+          let rule = match (src_reg.is_cfa(), offset_from_src) {
+            (true, offset) => Rule::Is(offset.into()),
+            (false, 0) => Rule::Register(self.register(src_reg)?),
+            (false, _) => Rule::Undefined,
+          };
+          (dest_reg, rule)
+        }
+        CompactCfiOp::RegisterAt {
+          dest_reg,
+          src_reg,
+          offset_from_src,
+        } => {
+          if !src_reg.is_cfa() {
+            return None;
+          }
+          (dest_reg, Rule::At(offset_from_src.into()))
+        }
+      };
+      if dest == CompactCfiRegister::instruction_pointer() {
+        return_address = Some(rule);
+      } else if let Some(dest) = self.register(dest) {
+        registers.push((dest, rule));
+      }
+    }
+    Some(FrameRules {
+      cfa: cfa?,
+      return_address,
+      registers,
+    })
+  }
+}
+
+/// The unwind info of one file of the main executable.
+struct ObjectUnwindInfo<'d> {
+  load_address: u64,
+  text: Option<Range<u64>>,
+  eh_frame: Option<CfiSection<'d>>,
+  debug_frame: Option<CfiSection<'d>>,
+  compact: Option<CompactUnwind<'d>>,
+}
+
+impl<'d> ObjectUnwindInfo<'d> {
+  fn new(object: &Object<'d>) -> Result<Option<Self>, SymbolicateError> {
+    match object {
+      Object::Elf(elf) => Self::from_dwarf(elf, elf.load_address(), None),
+      Object::MachO(macho) => Self::from_dwarf(
+        macho,
+        macho.load_address(),
+        CompactUnwind::new(macho)?,
+      ),
+      // Windows unwind info (`.pdata`) isn't supported.
+      _ => Ok(None),
+    }
+  }
+
+  fn from_dwarf(
+    object: &impl Dwarf<'d>,
+    load_address: u64,
+    compact: Option<CompactUnwind<'d>>,
+  ) -> Result<Option<Self>, SymbolicateError> {
+    let endian = object.endianity();
+    let text = object.raw_section("text");
+    let eh_frame = match object.raw_section("eh_frame") {
+      Some(section) => {
+        let mut bases = BaseAddresses::default().set_eh_frame(section.address);
+        if let Some(text) = &text {
+          bases = bases.set_text(text.address);
+        }
+        if let Some(got) = object.raw_section("got") {
+          bases = bases.set_got(got.address);
+        }
+        Some(
+          CfiSection::new(CfiKind::EhFrame, section.data, endian, bases)
+            .map_err(unwind_error)?,
+        )
+      }
+      None => None,
+    };
+    let debug_frame = match object.section("debug_frame") {
+      Some(section) => Some(
+        CfiSection::new(
+          CfiKind::DebugFrame,
+          section.data,
+          endian,
+          BaseAddresses::default(),
+        )
+        .map_err(unwind_error)?,
+      ),
+      None => None,
+    };
+    if eh_frame.is_none() && debug_frame.is_none() && compact.is_none() {
+      return Ok(None);
+    }
+    Ok(Some(ObjectUnwindInfo {
+      load_address,
+      text: text
+        .map(|text| text.address..text.address + text.data.len() as u64),
+      eh_frame,
+      debug_frame,
+      compact,
+    }))
+  }
+
+  fn contains(&self, addr: u64) -> bool {
+    let svma = addr.wrapping_add(self.load_address);
+    self.text.as_ref().is_some_and(|text| text.contains(&svma))
+      || [&self.eh_frame, &self.debug_frame]
+        .into_iter()
+        .flatten()
+        .any(|cfi| cfi.fde_offset(svma).is_some())
+      || self
+        .compact
+        .as_ref()
+        .is_some_and(|compact| compact.entry(addr).is_some())
+  }
+
+  fn rules(&self, addr: u64) -> Option<FrameRules> {
+    let svma = addr.wrapping_add(self.load_address);
+    if let Some(compact) = &self.compact
+      && let Some(entry) = compact.entry(addr)
+    {
+      match entry.instructions(&compact.iter) {
+        CompactUnwindOp::UseDwarfFde { offset_in_eh_frame } => {
+          let eh = self.eh_frame.as_ref()?;
+          return eh.rules(offset_in_eh_frame as usize, svma);
+        }
+        CompactUnwindOp::CfiOps(_) => return compact.rules(entry),
+        CompactUnwindOp::None => {}
+      }
+    }
+    if let Some(eh) = &self.eh_frame
+      && let Some(offset) = eh.fde_offset(svma)
+      && let Some(rules) = eh.rules(offset, svma)
+    {
+      return Some(rules);
+    }
+    let debug = self.debug_frame.as_ref()?;
+    debug.rules(debug.fde_offset(svma)?, svma)
+  }
+}
+
+fn unwind_error(
+  error: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> SymbolicateError {
+  SymbolicateError::InvalidUnwindInfo(error.into())
+}
+
+/// The unwind info of the main executable, collected from its files.
+pub struct UnwindInfo<'d> {
+  objects: Vec<ObjectUnwindInfo<'d>>,
+}
+
+impl<'d> UnwindInfo<'d> {
+  /// Reads the unwind info of `objects`, which should all be files of the
+  /// main executable: the executable itself (which has `.eh_frame` or
+  /// compact unwind info) and its debug file (which may have
+  /// `.debug_frame`). Objects without unwind info are skipped.
+  pub fn new(objects: &[Object<'d>]) -> Result<Self, SymbolicateError> {
+    let objects = objects
+      .iter()
+      .filter_map(|object| ObjectUnwindInfo::new(object).transpose())
+      .collect::<Result<_, _>>()?;
+    Ok(UnwindInfo { objects })
+  }
+
+  /// Whether none of the objects had unwind info.
+  pub fn is_empty(&self) -> bool {
+    self.objects.is_empty()
+  }
+
+  /// Whether a stable address is in the main executable's code, as far as
+  /// its unwind info and text section tell.
+  fn contains(&self, addr: u64) -> bool {
+    self.objects.iter().any(|object| object.contains(addr))
+  }
+
+  fn rules(&self, addr: u64) -> Option<FrameRules> {
+    self.objects.iter().find_map(|object| object.rules(addr))
+  }
+}
+
+/// Unwinds captured stack memory. Frames in the main executable (loaded at
+/// `main_base`, if known) get stable addresses, while the others are
+/// absolute. The first frame is the one the registers are from, and the
+/// others are call sites.
+///
+/// Unwinding stops at the outermost frame, at the end of the captured memory,
+/// or when a frame can't be unwound.
+pub fn unwind_stack(
+  stack_memory: &StackMemory,
+  arch: &encode::Arch,
+  main_base: Option<u64>,
+  unwind_info: &UnwindInfo<'_>,
+) -> Result<Vec<FrameAddr>, SymbolicateError> {
+  let arch = ArchRegisters::for_arch(arch)?;
+  let mut registers: Registers = [None; REGISTER_COUNT];
+  for register in &stack_memory.registers {
+    if let Some(slot) = registers.get_mut(register.number as usize) {
+      *slot = Some(register.value);
+    }
+  }
+
+  let mut ip = stack_memory.ip;
+  let mut frames = Vec::new();
+  while frames.len() < MAX_FRAMES {
+    let call_site = !frames.is_empty();
+    let lookup_addr = if call_site { ip.wrapping_sub(1) } else { ip };
+    let stable = main_base
+      .and_then(|base| lookup_addr.checked_sub(base))
+      .filter(|&addr| unwind_info.contains(addr));
+    frames.push(match main_base.filter(|_| stable.is_some()) {
+      Some(base) => FrameAddr::Stable {
+        addr: ip - base,
+        call_site,
+      },
+      None => FrameAddr::Absolute {
+        addr: ip,
+        call_site,
+      },
+    });
+
+    let rules = stable
+      .and_then(|addr| unwind_info.rules(addr))
+      .unwrap_or_else(|| arch.frame_pointer_rules());
+    let Some((caller_ip, caller_registers)) =
+      rules.caller(&registers, stack_memory, &arch, frames.len() == 1)
+    else {
+      break;
+    };
+    let sp = registers[arch.stack_pointer as usize];
+    let caller_sp = caller_registers[arch.stack_pointer as usize];
+    // The stack grows down, so the caller's frame has to be above (or, for
+    // a frameless leaf function, at the same place as) the callee's.
+    if caller_ip == 0 || caller_sp < sp || (caller_sp == sp && caller_ip == ip)
+    {
+      break;
+    }
+    ip = caller_ip;
+    registers = caller_registers;
+  }
+  Ok(frames)
+}
+
+/// The absolute instruction pointer of a frame.
+fn absolute_addr(frame: &FrameAddr, modules: &[Module]) -> Option<u64> {
+  match *frame {
+    FrameAddr::Stable { addr, .. } => modules.first()?.base.checked_add(addr),
+    FrameAddr::Absolute { addr, .. } => Some(addr),
+    FrameAddr::InModule { module, addr, .. } => {
+      modules.get(module)?.base.checked_add(addr)
+    }
+    FrameAddr::Unknown => None,
+  }
+}
+
+/// Unwinds a trace's stack memory (see [`unwind_stack`]), and returns the
+/// trace with the unwound frames, or `None` if it has no stack memory.
+///
+/// The captured memory usually ends before the stack does, so if the client
+/// found the last unwound frame too, its frames after that one are appended.
+pub fn unwind_stack_trace(
+  stack_trace: &StackTrace,
+  unwind_info: &UnwindInfo<'_>,
+) -> Result<Option<StackTrace>, SymbolicateError> {
+  let Some(stack_memory) = stack_trace.stack_memory() else {
+    return Ok(None);
+  };
+  let modules = stack_trace.modules();
+  let main_base = modules.first().map(|module| module.base);
+  let mut frames = unwind_stack(
+    stack_memory,
+    stack_trace.header().arch(),
+    main_base,
+    unwind_info,
+  )?;
+  let last = frames
+    .last()
+    .and_then(|frame| absolute_addr(frame, modules));
+  if let Some(index) = stack_trace
+    .frames()
+    .iter()
+    .position(|frame| last.is_some() && absolute_addr(frame, modules) == last)
+  {
+    frames.extend_from_slice(&stack_trace.frames()[index + 1..]);
+  }
+  Ok(Some(stack_trace.clone().with_frames(frames)))
+}
+
+/// Like [`unwind_stack_trace`], with the unwind info of the main executable's
+/// files at `paths` (see [`UnwindInfo::new`]). The objects for the trace's
+/// architecture are picked from fat archives, and have to match its build
+/// ID.
+pub fn unwind_stack_trace_with_files(
+  stack_trace: &StackTrace,
+  paths: &[PathBuf],
+) -> Result<Option<StackTrace>, SymbolicateError> {
+  let views = paths
+    .iter()
+    .map(ByteView::open)
+    .collect::<Result<Vec<_>, _>>()?;
+  let selector = SliceSelector::for_trace_arch(stack_trace.header().arch())
+    .unwrap_or_default();
+  let objects = views
+    .iter()
+    .map(|view| parse_object(view, selector))
+    .collect::<Result<Vec<_>, _>>()?;
+  if let Some(build_id) = stack_trace.header().build_id() {
+    let expected = build_id_to_debug_id(build_id);
+    if let Some(object) = objects.iter().find(|o| o.debug_id() != expected) {
+      return Err(
+        DebugIdMismatch {
+          expected,
+          actual: object.debug_id(),
+        }
+        .into(),
+      );
+    }
+  }
+  unwind_stack_trace(stack_trace, &UnwindInfo::new(&objects)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use deno_stable_stacktrace::Register;
+
+  use super::*;
+
+  #[test]
+  fn unwind_frame_pointers() {
+    // Two frames with frame pointers, and an outermost frame without one.
+    let mut memory = vec![0; 0x10];
+    for value in [0x7020u64, 0x2005, 0, 0x3005] {
+      memory.extend(value.to_le_bytes());
+    }
+    let stack_memory = StackMemory {
+      ip: 0x1000,
+      registers: vec![
+        Register {
+          number: 6,
+          value: 0x7010,
+        },
+        Register {
+          number: 7,
+          value: 0x7000,
+        },
+      ],
+      start: 0x7000,
+      memory,
+    };
+    let unwind_info = UnwindInfo::new(&[]).unwrap();
+    let frames = unwind_stack(
+      &stack_memory,
+      &encode::Arch::X86_64,
+      Some(0x1000),
+      &unwind_info,
+    )
+    .unwrap();
+    assert_eq!(
+      frames,
+      [
+        FrameAddr::Absolute {
+          addr: 0x1000,
+          call_site: false,
+        },
+        FrameAddr::Absolute {
+          addr: 0x2005,
+          call_site: true,
+        },
+        FrameAddr::Absolute {
+          addr: 0x3005,
+          call_site: true,
+        },
+      ]
+    );
+
+    // Addresses from a decoded trace can't be trusted not to overflow.
+    let modules = [Module {
+      path: "deno".into(),
+      base: 0x1000,
+      build_id: None,
+    }];
+    let frame = FrameAddr::InModule {
+      module: 0,
+      addr: u64::MAX,
+      call_site: true,
+    };
+    assert_eq!(absolute_addr(&frame, &modules), None);
+
+    let other = encode::Arch::from("riscv64");
+    let error =
+      unwind_stack(&stack_memory, &other, None, &unwind_info).unwrap_err();
+    assert_eq!(error.code(), "unsupported_unwind_arch", "{error}");
+  }
+
+  /// A `.debug_frame` section with one FDE covering `range`, whose CFI only
+  /// defines the CFA as `sp`, as for an aarch64 leaf function.
+  fn leaf_debug_frame(range: Range<u64>) -> Vec<u8> {
+    let mut cie = Vec::new();
+    cie.extend(u32::MAX.to_le_bytes()); // CIE ID
+    // Version 1, no augmentation, code alignment 4, data alignment -8 and
+    // x30 as the return address register.
+    cie.extend([1, 0, 4, 0x78, 30]);
+    // DW_CFA_def_cfa sp, 0, and padding.
+    cie.extend([0x0c, 31, 0, 0, 0, 0, 0]);
+    let mut section = Vec::new();
+    section.extend((cie.len() as u32).to_le_bytes());
+    section.extend(cie);
+    section.extend(20u32.to_le_bytes());
+    section.extend(0u32.to_le_bytes()); // CIE pointer
+    section.extend(range.start.to_le_bytes());
+    section.extend((range.end - range.start).to_le_bytes());
+    section
+  }
+
+  #[test]
+  fn unwind_aarch64_leaf_function() {
+    const BASE: u64 = 0x10000;
+    let debug_frame = CfiSection::new(
+      CfiKind::DebugFrame,
+      Cow::Owned(leaf_debug_frame(0x1000..0x1100)),
+      RunTimeEndian::Little,
+      BaseAddresses::default(),
+    )
+    .unwrap();
+    let unwind_info = UnwindInfo {
+      objects: vec![ObjectUnwindInfo {
+        load_address: 0,
+        text: None,
+        eh_frame: None,
+        debug_frame: Some(debug_frame),
+        compact: None,
+      }],
+    };
+    // The leaf function's caller has no CFI, but a frame pointer.
+    let mut memory = vec![0; 0x10];
+    for value in [0u64, 0x3004] {
+      memory.extend(value.to_le_bytes());
+    }
+    let register = |number, value| Register { number, value };
+    let stack_memory = StackMemory {
+      ip: BASE + 0x1010,
+      registers: vec![
+        register(29, 0x7010),
+        // The return address, signed with a pointer authentication code.
+        register(30, 0x002a_0000_0000_0000 | (BASE + 0x2004)),
+        register(31, 0x7000),
+      ],
+      start: 0x7000,
+      memory,
+    };
+    let frames = unwind_stack(
+      &stack_memory,
+      &encode::Arch::Aarch64,
+      Some(BASE),
+      &unwind_info,
+    )
+    .unwrap();
+    assert_eq!(
+      frames,
+      [
+        FrameAddr::Stable {
+          addr: 0x1010,
+          call_site: false,
+        },
+        FrameAddr::Absolute {
+          addr: BASE + 0x2004,
+          call_site: true,
+        },
+        FrameAddr::Absolute {
+          addr: 0x3004,
+          call_site: true,
+        },
+      ]
+    );
+  }
+
+  #[cfg(all(
+    any(target_os = "linux", target_os = "macos"),
+    any(target_arch = "x86_64", target_arch = "aarch64")
+  ))]
+  #[inline(never)]
+  fn capture() -> StackTrace {
+    let captured = deno_stable_stacktrace::stable_stacktrace();
+    let stack_memory =
+      deno_stable_stacktrace::capture_stack_memory(64 * 1024).unwrap();
+    let version = encode::Version {
+      major: 2,
+      minor: 0,
+      patch: 0,
+      canary_hash: encode::CanaryHash::none(),
+      dev_build: true,
+    };
+    StackTrace::from_captured(&captured, version)
+      .with_stack_memory(stack_memory)
+  }
+
+  #[cfg(all(
+    any(target_os = "linux", target_os = "macos"),
+    any(target_arch = "x86_64", target_arch = "aarch64")
+  ))]
+  #[test]
+  fn unwind_captured_stack_memory() {
+    let stack_trace = capture();
+    let stack_trace = StackTrace::decode(&stack_trace.encode()).unwrap();
+    let path = std::env::current_exe().unwrap();
+    let unwound = unwind_stack_trace_with_files(&stack_trace, &[path])
+      .unwrap()
+      .unwrap();
+
+    let modules = stack_trace.modules();
+    let addrs = |frames: &[FrameAddr]| {
+      frames
+        .iter()
+        .map(|frame| absolute_addr(frame, modules))
+        .collect::<Vec<_>>()
+    };
+    let client = addrs(stack_trace.frames());
+    let unwound = addrs(unwound.frames());
+    // The first two frames are in `capture_stack_memory` and `capture`,
+    // after the client captured its frames, but the callers of `capture`
+    // have to match.
+    let callers = &unwound[2..6];
+    assert!(
+      client
+        .windows(callers.len())
+        .any(|window| window == callers),
+      "{client:x?}\n{unwound:x?}"
+    );
+    assert_eq!(unwound.last(), client.last());
+  }
+}
@@ -0,0 +1,327 @@
+//! Runs the `deno-symbolicate` binary.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use deno_stable_stacktrace::encode::{
+  CanaryHash, FrameAddr, StackTrace, TRACE_BANNER_BEGIN, TRACE_BANNER_END,
+  Version,
+};
+use deno_stable_stacktrace::{BuildId, Module};
+use symbolic::debuginfo::Object;
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+  fn new(name: &str) -> Self {
+    let path = std::env::temp_dir().join(format!(
+      "deno-symbolicate-cli-{name}-{}",
+      std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    TempDir(path)
+  }
+}
+
+impl Drop for TempDir {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_dir_all(&self.0);
+  }
+}
+
+fn run(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_deno-symbolicate"))
+    .args(args)
+    .output()
+    .unwrap()
+}
+
+fn run_with_stdin(args: &[&str], stdin: &str) -> Output {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_deno-symbolicate"))
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child
+    .stdin
+    .take()
+    .unwrap()
+    .write_all(stdin.as_bytes())
+    .unwrap();
+  child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+  assert!(
+    output.status.success(),
+    "{}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+  String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+  assert!(!output.status.success());
+  String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+fn fixture(name: &str) -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("fixtures")
+    .join(name)
+}
+
+/// Writes the symcache of `fixtures/elf/plain.debug` into `dir`.
+fn plain_symcache(dir: &TempDir) -> String {
+  let symcache = dir.0.join("plain.symcache");
+  let symcache = symcache.to_str().unwrap();
+  let debug_file = fixture("elf/plain.debug");
+  stdout(&run(&[
+    "symcache",
+    debug_file.to_str().unwrap(),
+    "-o",
+    symcache,
+  ]));
+  symcache.to_owned()
+}
+
+/// The build ID of the `fixtures/elf/plain` executable.
+fn plain_build_id() -> BuildId {
+  let debug_file = std::fs::read(fixture("elf/plain.debug")).unwrap();
+  let object = Object::parse(&debug_file).unwrap();
+  let code_id = object.code_id().unwrap();
+  let bytes = (0..code_id.as_str().len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&code_id.as_str()[i..i + 2], 16).unwrap())
+    .collect();
+  BuildId::Gnu(bytes)
+}
+
+fn plain_trace(frames: Vec<FrameAddr>) -> StackTrace {
+  let version = Version {
+    major: 1,
+    minor: 2,
+    patch: 3,
+    canary_hash: CanaryHash::none(),
+    dev_build: false,
+  };
+  StackTrace::from_frames(frames, "x86_64", "linux", version)
+    .with_build_id(plain_build_id())
+}
+
+#[test]
+fn symcache() {
+  let dir = TempDir::new("symcache");
+  // The output defaults to the debug file's path with `.symcache` appended.
+  let debug_file = dir.0.join("plain.debug");
+  std::fs::copy(fixture("elf/plain.debug"), &debug_file).unwrap();
+  stdout(&run(&["symcache", debug_file.to_str().unwrap()]));
+  assert!(dir.0.join("plain.debug.symcache").is_file());
+
+  let output = dir.0.join("out.symcache");
+  let output = output.to_str().unwrap();
+  let args = ["symcache", debug_file.to_str().unwrap(), "-o", output];
+  stdout(&run(&args));
+  assert!(Path::new(output).is_file());
+
+  let error = stderr(&run(&[&args[..], &["--all-slices"]].concat()));
+  assert!(error.contains("cannot be used with"), "{error}");
+  // A failed conversion leaves no output behind.
+  let missing = dir.0.join("missing");
+  let error = stderr(&run(&["symcache", missing.to_str().unwrap()]));
+  assert!(error.contains("missing"), "{error}");
+  assert!(!dir.0.join("missing.symcache").exists());
+}
+
+#[test]
+fn addrs() {
+  let dir = TempDir::new("addrs");
+  let symcache = plain_symcache(&dir);
+
+  let text = stdout(&run(&["addrs", "-s", &symcache, "0x1060", "1170"]));
+  let lines = text.lines().collect::<Vec<_>>();
+  assert!(
+    lines[0].starts_with("  0: 0x0000000000001060 at main"),
+    "{text}"
+  );
+  assert!(lines[1].contains("[inlined]"), "{text}");
+
+  let json = stdout(&run(&[
+    "addrs",
+    "-s",
+    &symcache,
+    "0x1061",
+    "0x1040",
+    "--call-sites",
+    "--format",
+    "json",
+  ]));
+  let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+  let frames = json["frames"].as_array().unwrap();
+  assert_eq!(json.get("header"), None, "{json:#}");
+  assert_eq!(frames[0]["addr"], "0x1061", "{json:#}");
+  assert_eq!(frames[0]["callSite"], true, "{json:#}");
+  assert_eq!(frames[0]["locations"][0]["name"], "main", "{json:#}");
+  assert_eq!(frames[1]["quality"], "nearestSymbol", "{json:#}");
+
+  let error = stderr(&run(&["addrs", "-s", &symcache, "0xzz"]));
+  assert!(error.contains("invalid hex address `0xzz`"), "{error}");
+  let error = stderr(&run(&["addrs", "-s", &symcache]));
+  assert!(error.contains("required"), "{error}");
+}
+
+#[test]
+fn trace() {
+  let dir = TempDir::new("trace");
+  let symcache = plain_symcache(&dir);
+  let stack_trace = plain_trace(vec![
+    FrameAddr::Stable {
+      addr: 0x1170,
+      call_site: false,
+    },
+    FrameAddr::Stable {
+      addr: 0x1061,
+      call_site: true,
+    },
+    FrameAddr::Absolute {
+      addr: 0x7f00_0000_1234,
+      call_site: true,
+    },
+  ]);
+  let encoded = stack_trace.encode_base64url();
+
+  let text = stdout(&run(&["trace", "-s", &symcache, &encoded]));
+  let lines = text.lines().collect::<Vec<_>>();
+  assert_eq!(lines[0], "deno 1.2.3 (linux x86_64)", "{text}");
+  assert!(
+    lines.iter().any(|line| line.contains("at compute")),
+    "{text}"
+  );
+  assert!(lines.iter().any(|line| line.contains("at main")), "{text}");
+  assert!(text.ends_with("<outside of main executable>\n"), "{text}");
+
+  // Crash output is read from stdin, and the trace found between its
+  // banner lines.
+  let crash_output = format!(
+    "thread 'main' panicked\n{TRACE_BANNER_BEGIN}\n{encoded}\n\
+     {TRACE_BANNER_END}\nhttps://example.com\n"
+  );
+  assert_eq!(
+    stdout(&run_with_stdin(&["trace", "-s", &symcache], &crash_output)),
+    text
+  );
+  assert_eq!(
+    stdout(&run_with_stdin(
+      &["trace", "-s", &symcache, "-"],
+      &crash_output
+    )),
+    text
+  );
+
+  let json = stdout(&run(&[
+    "trace", "-s", &symcache, &encoded, "--format", "json",
+  ]));
+  let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+  assert_eq!(json["header"]["os"], "linux", "{json:#}");
+  let frames = json["frames"].as_array().unwrap();
+  assert_eq!(frames.len(), 3, "{json:#}");
+  assert_eq!(frames[1]["locations"][0]["name"], "main", "{json:#}");
+  assert_eq!(frames[2]["kind"], "absolute", "{json:#}");
+  assert_eq!(frames[2]["quality"], "skipped", "{json:#}");
+
+  let error = stderr(&run(&["trace", &encoded]));
+  assert!(error.contains("--symcache"), "{error}");
+  let error = stderr(&run(&["trace", "-s", &symcache, "not a trace!"]));
+  assert!(error.contains("failed to decode stack trace"), "{error}");
+  // The symcache has to be for the build the trace is from.
+  let other = stack_trace.with_build_id(BuildId::Gnu(vec![1, 2, 3, 4]));
+  let error =
+    stderr(&run(&["trace", "-s", &symcache, &other.encode_base64url()]));
+  assert!(error.contains("debug ID"), "{error}");
+}
+
+#[test]
+fn trace_with_module_symcaches() {
+  let dir = TempDir::new("trace-modules");
+  let symcache = plain_symcache(&dir);
+  let stack_trace = plain_trace(vec![FrameAddr::InModule {
+    module: 1,
+    addr: 0x1061,
+    call_site: true,
+  }])
+  .with_modules(vec![
+    Module {
+      path: "deno".into(),
+      base: 0x5500_0000_0000,
+      build_id: None,
+    },
+    Module {
+      path: "libplain.so".into(),
+      base: 0x7f00_0000_0000,
+      build_id: Some(plain_build_id()),
+    },
+  ]);
+  let encoded = stack_trace.encode_base64url();
+
+  let text = stdout(&run(&["trace", "-s", &symcache, &encoded]));
+  assert!(
+    text.ends_with("0x0000000000001061 in libplain.so\n"),
+    "{text}"
+  );
+  let text = stdout(&run(&[
+    "trace",
+    "-s",
+    &symcache,
+    "--module-symcache",
+    &symcache,
+    &encoded,
+  ]));
+  assert!(
+    text.contains("0x0000000000001061 in libplain.so\n"),
+    "{text}"
+  );
+  assert!(text.contains(" at main"), "{text}");
+}
+
+#[test]
+fn store_gc() {
+  let root = TempDir::new("gc");
+  let store = root.0.to_str().unwrap();
+  let debug_file = fixture("elf/plain.debug");
+  let debug_file = debug_file.to_str().unwrap();
+  stdout(&run(&["store", store, "ingest", debug_file]));
+  stdout(&run(&[
+    "store",
+    store,
+    "ingest",
+    debug_file,
+    "--name",
+    "v1",
+    "--version",
+    "1.0.0",
+  ]));
+
+  // Without `--keep` or `--all`, nothing is removed.
+  let output = run(&["store", store, "gc"]);
+  assert!(!output.status.success());
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("--keep"), "{stderr}");
+  assert_eq!(stdout(&run(&["store", store, "list"])).lines().count(), 5);
+
+  let dry_run = stdout(&run(&["store", store, "gc", "--all", "--dry-run"]));
+  assert!(dry_run.contains("would remove version 1.0.0"), "{dry_run}");
+  assert_eq!(dry_run.lines().count(), 3, "{dry_run}");
+  assert_eq!(stdout(&run(&["store", store, "list"])).lines().count(), 5);
+
+  // The debug file ingested without a version is removed.
+  let removed = stdout(&run(&["store", store, "gc", "--keep", "1.0.0"]));
+  assert!(removed.starts_with("removed plain.debug/"), "{removed}");
+  assert_eq!(removed.lines().count(), 1, "{removed}");
+  let removed = stdout(&run(&["store", store, "gc", "--all"]));
+  assert_eq!(removed.lines().count(), 2, "{removed}");
+  assert_eq!(stdout(&run(&["store", store, "list"])), "");
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use deno_symbolicate::OwnedSymCache;
+use deno_symbolicate::symstore::StoredDebugFile;
+
+/// Parsed symcaches of the most recently used debug files, so each request doesn't
+/// have to read its symcache from disk again.
+pub struct SymCacheCache {
+  capacity: usize,
+  inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+  entries: HashMap<StoredDebugFile, Entry>,
+  /// Incremented on every access, to find the least recently used entry.
+  clock: u64,
+}
+
+struct Entry {
+  symcache: Arc<OwnedSymCache>,
+  last_used: u64,
+}
+
+impl SymCacheCache {
+  /// Creates a cache holding up to `capacity` symcaches. A capacity of 0
+  /// disables caching.
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      inner: Mutex::default(),
+    }
+  }
+
+  /// Returns the cached symcache for `key`, or loads and caches it. The lock
+  /// isn't held while loading, so two requests for the same debug file may both
+  /// load it.
+  pub fn get_or_load<E>(
+    &self,
+    key: &StoredDebugFile,
+    load: impl FnOnce() -> Result<OwnedSymCache, E>,
+  ) -> Result<Arc<OwnedSymCache>, E> {
+    if let Some(symcache) = self.get(key) {
+      return Ok(symcache);
+    }
+    let symcache = Arc::new(load()?);
+    self.insert(key.clone(), symcache.clone());
+    Ok(symcache)
+  }
+
+  fn get(&self, key: &StoredDebugFile) -> Option<Arc<OwnedSymCache>> {
+    let mut inner = self.lock();
+    inner.clock += 1;
+    let clock = inner.clock;
+    let entry = inner.entries.get_mut(key)?;
+    entry.last_used = clock;
+    Some(entry.symcache.clone())
+  }
+
+  fn insert(&self, key: StoredDebugFile, symcache: Arc<OwnedSymCache>) {
+    if self.capacity == 0 {
+      return;
+    }
+    let mut inner = self.lock();
+    inner.clock += 1;
+    let last_used = inner.clock;
+    if !inner.entries.contains_key(&key)
+      && inner.entries.len() >= self.capacity
+      && let Some(oldest) = inner
+        .entries
+        .iter()
+        .min_by_key(|(_, entry)| entry.last_used)
+        .map(|(key, _)| key.clone())
+    {
+      inner.entries.remove(&oldest);
+    }
+    inner.entries.insert(
+      key,
+      Entry {
+        symcache,
+        last_used,
+      },
+    );
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+    // The map is never left inconsistent, so a panic while holding the lock
+    // doesn't matter.
+    self.inner.lock().unwrap_or_else(|err| err.into_inner())
+  }
+}
@@ -0,0 +1,51 @@
+use deno_stable_stacktrace::encode::DecodeError;
+use deno_symbolicate::SymbolicateError;
+
+/// Everything that can go wrong while handling a symbolication request.
+///
+/// Like [`SymbolicateError`], each variant has a stable
+/// [`code`](ServerError::code), which is sent to clients along with the
+/// message.
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+  /// The request can't be handled, e.g. because its body is too large.
+  #[error("{0}")]
+  InvalidRequest(String),
+  /// The request body isn't an encoded stack trace.
+  #[error("invalid stack trace: {0}")]
+  InvalidTrace(#[source] DecodeError),
+  /// The symbol store has no debug info for the build, described by its
+  /// debug ID or version.
+  #[error("no debug info for {0}")]
+  DebugInfoNotFound(String),
+  #[error(transparent)]
+  Symbolicate(#[from] SymbolicateError),
+}
+
+impl ServerError {
+  /// A short, stable identifier for the kind of error.
+  pub fn code(&self) -> &'static str {
+    match self {
+      ServerError::InvalidRequest(_) => "invalid_request",
+      ServerError::InvalidTrace(_) => "invalid_trace",
+      ServerError::DebugInfoNotFound(_) => "debug_info_not_found",
+      ServerError::Symbolicate(error) => error.code(),
+    }
+  }
+
+  /// The HTTP status code to respond with.
+  pub fn status(&self) -> u16 {
+    match self.code() {
+      "invalid_request" | "invalid_trace" | "invalid_store_key"
+      | "debug_id_mismatch" | "target_mismatch" => 400,
+      "debug_info_not_found" | "no_matching_slice" => 404,
+      _ => 500,
+    }
+  }
+}
+
+impl From<std::io::Error> for ServerError {
+  fn from(error: std::io::Error) -> Self {
+    ServerError::Symbolicate(error.into())
+  }
+}
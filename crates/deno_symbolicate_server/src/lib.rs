@@ -0,0 +1,459 @@
+//! An HTTP service that symbolicates encoded stack traces, using debug info
+//! from a [`SymbolStore`]. Traces are looked up by their build ID, or by
+//! their version if they have none.
+//!
+//! | Route                    | Response                                     |
+//! | ------------------------ | -------------------------------------------- |
+//! | `POST /symbolicate`, `/` | the symbolicated trace in the request body   |
+//! | `GET /health`            | `ok`                                         |
+//!
+//! The request body is an encoded stack trace, or crash output containing one
+//! between banner lines. The response is JSON, in the format of
+//! `deno-symbolicate trace --format json`, unless `?format=text` is passed or
+//! the `Accept` header asks for `text/plain`. Errors are JSON objects with a
+//! `code` (see [`ServerError::code`]) and a `message`.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::Arc;
+
+use deno_stable_stacktrace::encode::{StackTrace, find_banner_trace};
+use deno_symbolicate::output::TraceOutput;
+use deno_symbolicate::symstore::SymbolStore;
+use deno_symbolicate::{
+  SymbolicatedStackTrace, build_id_to_debug_id,
+  symbolicate_stack_trace_with_modules,
+};
+
+mod cache;
+mod error;
+
+pub use cache::SymCacheCache;
+pub use error::ServerError;
+
+/// The largest request body accepted. Encoded traces are a few KiB at most,
+/// even with crash output around them.
+const MAX_BODY_SIZE: u64 = 1 << 20;
+
+pub struct Symbolicator {
+  store: SymbolStore,
+  cache: SymCacheCache,
+}
+
+/// A response to an HTTP request, independent of the server implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+  pub status: u16,
+  pub content_type: &'static str,
+  pub body: Vec<u8>,
+}
+
+impl Response {
+  fn error(error: &ServerError) -> Self {
+    Self::json_error(error.status(), error.code(), &error.to_string())
+  }
+
+  fn json_error(status: u16, code: &str, message: &str) -> Self {
+    let body = serde_json::json!({ "code": code, "message": message });
+    Response {
+      status,
+      content_type: "application/json",
+      body: body.to_string().into_bytes(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+  Text,
+  Json,
+}
+
+impl Symbolicator {
+  /// Creates a symbolicator that keeps up to `cache_capacity` parsed
+  /// symcaches in memory.
+  pub fn new(store: SymbolStore, cache_capacity: usize) -> Self {
+    Self {
+      store,
+      cache: SymCacheCache::new(cache_capacity),
+    }
+  }
+
+  pub fn store(&self) -> &SymbolStore {
+    &self.store
+  }
+
+  /// Decodes and symbolicates a trace, which may be surrounded by crash
+  /// output.
+  pub fn symbolicate(
+    &self,
+    trace: &str,
+  ) -> Result<SymbolicatedStackTrace, ServerError> {
+    let trace = find_banner_trace(trace).unwrap_or(trace).trim();
+    let stack_trace =
+      StackTrace::decode_base64url(trace).map_err(ServerError::InvalidTrace)?;
+    let header = stack_trace.header();
+    let Some(file) = self.store.lookup_trace(header)? else {
+      let build = match header.build_id() {
+        Some(build_id) => {
+          format!("debug ID {}", build_id_to_debug_id(build_id))
+        }
+        None => {
+          format!(
+            "deno {} ({} {})",
+            header.version(),
+            header.os(),
+            header.arch()
+          )
+        }
+      };
+      return Err(ServerError::DebugInfoNotFound(build));
+    };
+    let load = |file| {
+      self
+        .cache
+        .get_or_load(file, || self.store.load_symcache(file))
+    };
+    let symcache = load(&file)?;
+    let module_symcaches = self
+      .store
+      .lookup_modules(&stack_trace)?
+      .iter()
+      .map(|(index, file)| Ok((*index, load(file)?)))
+      .collect::<Result<BTreeMap<_, _>, ServerError>>()?;
+    Ok(symbolicate_stack_trace_with_modules(
+      &stack_trace,
+      &*symcache,
+      &module_symcaches,
+    )?)
+  }
+
+  /// Handles a request for `url` (the path and query string), with `accept`
+  /// being the `Accept` header, if any.
+  pub fn respond(
+    &self,
+    method: &str,
+    url: &str,
+    accept: Option<&str>,
+    body: impl Read,
+  ) -> Response {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    match (method, path) {
+      ("GET", "/health") => Response {
+        status: 200,
+        content_type: "text/plain; charset=utf-8",
+        body: b"ok".to_vec(),
+      },
+      ("POST", "/" | "/symbolicate") => {
+        let format = request_format(query, accept);
+        match self.symbolicate_body(body, format) {
+          Ok(response) => response,
+          Err(error) => Response::error(&error),
+        }
+      }
+      (_, "/health" | "/" | "/symbolicate") => {
+        Response::json_error(405, "method_not_allowed", "method not allowed")
+      }
+      _ => Response::json_error(404, "not_found", "not found"),
+    }
+  }
+
+  fn symbolicate_body(
+    &self,
+    body: impl Read,
+    format: Format,
+  ) -> Result<Response, ServerError> {
+    let mut trace = String::new();
+    body
+      .take(MAX_BODY_SIZE + 1)
+      .read_to_string(&mut trace)
+      .map_err(|_| {
+        ServerError::InvalidRequest("the request body isn't UTF-8".into())
+      })?;
+    if trace.len() as u64 > MAX_BODY_SIZE {
+      return Err(ServerError::InvalidRequest(format!(
+        "the request body is larger than {MAX_BODY_SIZE} bytes"
+      )));
+    }
+    let symbolicated = self.symbolicate(&trace)?;
+    let output = TraceOutput::from(&symbolicated);
+    let mut body = Vec::new();
+    let content_type = match format {
+      Format::Text => {
+        output.write_text(&mut body)?;
+        "text/plain; charset=utf-8"
+      }
+      Format::Json => {
+        output.write_json(&mut body)?;
+        "application/json"
+      }
+    };
+    Ok(Response {
+      status: 200,
+      content_type,
+      body,
+    })
+  }
+}
+
+/// `?format=` takes precedence over the `Accept` header.
+fn request_format(query: &str, accept: Option<&str>) -> Format {
+  for param in query.split('&') {
+    match param {
+      "format=text" => return Format::Text,
+      "format=json" => return Format::Json,
+      _ => {}
+    }
+  }
+  match accept {
+    Some(accept) if accept.contains("text/plain") => Format::Text,
+    _ => Format::Json,
+  }
+}
+
+/// Handles requests to `server` on `threads` worker threads, until the
+/// server is shut down.
+pub fn serve(
+  server: Arc<tiny_http::Server>,
+  symbolicator: Arc<Symbolicator>,
+  threads: usize,
+) {
+  let workers = (0..threads.max(1))
+    .map(|_| {
+      let server = server.clone();
+      let symbolicator = symbolicator.clone();
+      std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+          handle(&symbolicator, request);
+        }
+      })
+    })
+    .collect::<Vec<_>>();
+  for worker in workers {
+    let _ = worker.join();
+  }
+}
+
+fn handle(symbolicator: &Symbolicator, mut request: tiny_http::Request) {
+  let method = request.method().as_str().to_owned();
+  let url = request.url().to_owned();
+  let accept = request
+    .headers()
+    .iter()
+    .find(|header| header.field.equiv("Accept"))
+    .map(|header| header.value.as_str().to_owned());
+  let response =
+    symbolicator.respond(&method, &url, accept.as_deref(), request.as_reader());
+  let content_type =
+    tiny_http::Header::from_bytes("Content-Type", response.content_type)
+      .unwrap();
+  let response = tiny_http::Response::from_data(response.body)
+    .with_status_code(response.status)
+    .with_header(content_type);
+  // The client may have gone away, which only concerns that request.
+  let _ = request.respond(response);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use deno_stable_stacktrace::encode::{CanaryHash, FrameAddr, Version};
+
+  use super::*;
+
+  #[inline(never)]
+  fn capture() -> deno_stable_stacktrace::CapturedStackTrace {
+    deno_stable_stacktrace::stable_stacktrace()
+  }
+
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      let path =
+        std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+      let _ = std::fs::remove_dir_all(&path);
+      std::fs::create_dir_all(&path).unwrap();
+      TempDir(path)
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn json(response: &Response) -> serde_json::Value {
+    assert_eq!(response.content_type, "application/json");
+    serde_json::from_slice(&response.body).unwrap()
+  }
+
+  fn version(major: u64, minor: u64, patch: u64) -> Version {
+    Version {
+      major,
+      minor,
+      patch,
+      canary_hash: CanaryHash::none(),
+      dev_build: false,
+    }
+  }
+
+  fn has_capture(value: &serde_json::Value) -> bool {
+    value["frames"].as_array().unwrap().iter().any(|frame| {
+      frame["locations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|location| {
+          location["demangledName"]
+            .as_str()
+            .unwrap()
+            .ends_with("tests::capture")
+        })
+    })
+  }
+
+  #[test]
+  fn symbolicate_from_store() {
+    let root = TempDir::new("deno-symbolicate-server-test");
+    let store = SymbolStore::open(&root.0).unwrap();
+    let symbolicator = Symbolicator::new(store.clone(), 4);
+    let stack_trace = StackTrace::from_captured(&capture(), version(1, 11, 0));
+    let encoded = stack_trace.encode_base64url();
+
+    let response =
+      symbolicator.respond("POST", "/symbolicate", None, encoded.as_bytes());
+    assert_eq!(response.status, 404);
+    assert_eq!(json(&response)["code"], "debug_info_not_found");
+
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    let stored = store.ingest("deno", &exe, Some("1.11.0")).unwrap();
+    let file = &stored[0].file;
+    let crash_output = format!(
+      "thread 'main' panicked\n{}\n{encoded}\n{}\n",
+      deno_stable_stacktrace::encode::TRACE_BANNER_BEGIN,
+      deno_stable_stacktrace::encode::TRACE_BANNER_END,
+    );
+    let response =
+      symbolicator.respond("POST", "/", None, crash_output.as_bytes());
+    assert_eq!(response.status, 200, "{}", json(&response));
+    let value = json(&response);
+    assert!(has_capture(&value), "{value:#}");
+
+    // Frames in other modules are symbolicated with the debug file of their
+    // module, found by its build ID.
+    if stack_trace.modules()[0].build_id.is_some() {
+      let frames = stack_trace
+        .frames()
+        .iter()
+        .map(|frame| match *frame {
+          FrameAddr::Stable { addr, call_site } => FrameAddr::InModule {
+            module: 0,
+            addr,
+            call_site,
+          },
+          frame => frame,
+        })
+        .collect();
+      let in_module = stack_trace.clone().with_frames(frames);
+      let response = symbolicator.respond(
+        "POST",
+        "/",
+        None,
+        in_module.encode_base64url().as_bytes(),
+      );
+      let value = json(&response);
+      assert!(has_capture(&value), "{value:#}");
+    }
+
+    // A corrupt symcache is created again from the debug file.
+    std::fs::write(store.symcache_path(file), b"garbage").unwrap();
+    let fresh = Symbolicator::new(store.clone(), 4);
+    let response = fresh.respond("POST", "/", None, encoded.as_bytes());
+    assert_eq!(response.status, 200, "{}", json(&response));
+
+    // The parsed symcache is cached, so it isn't read again. Without the
+    // debug file, a corrupt symcache can't be recreated.
+    std::fs::write(store.symcache_path(file), b"garbage").unwrap();
+    std::fs::remove_file(store.debug_file_path(file)).unwrap();
+    let fresh = Symbolicator::new(store.clone(), 4);
+    let response = fresh.respond("POST", "/", None, encoded.as_bytes());
+    assert_eq!(json(&response)["code"], "corrupt_symcache");
+    let response = symbolicator.respond(
+      "POST",
+      "/symbolicate?format=text",
+      None,
+      encoded.as_bytes(),
+    );
+    assert_eq!(response.status, 200);
+    assert_eq!(response.content_type, "text/plain; charset=utf-8");
+    let text = String::from_utf8(response.body).unwrap();
+    assert!(text.starts_with("deno 1.11.0 ("), "{text}");
+    assert!(text.contains("tests::capture"), "{text}");
+  }
+
+  #[test]
+  fn symbolicate_by_version() {
+    let root = TempDir::new("deno-symbolicate-server-version-test");
+    let store = SymbolStore::open(&root.0).unwrap();
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    store.ingest("deno", &exe, Some("1.11.0")).unwrap();
+    let symbolicator = Symbolicator::new(store, 4);
+
+    // Traces without a build ID are looked up in the version index.
+    let captured = capture();
+    let frames = StackTrace::from_captured(&captured, version(1, 11, 0))
+      .frames()
+      .to_vec();
+    let trace = |version| {
+      StackTrace::from_frames(
+        frames.clone(),
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        version,
+      )
+      .encode_base64url()
+    };
+    let response = symbolicator.respond(
+      "POST",
+      "/",
+      None,
+      trace(version(1, 11, 0)).as_bytes(),
+    );
+    assert_eq!(response.status, 200, "{}", json(&response));
+    assert!(has_capture(&json(&response)));
+
+    // 1.1.10 used to collide with 1.11.0.
+    let response = symbolicator.respond(
+      "POST",
+      "/symbolicate",
+      Some("text/plain"),
+      trace(version(1, 1, 10)).as_bytes(),
+    );
+    assert_eq!(response.status, 404);
+  }
+
+  #[test]
+  fn request_errors() {
+    let root = TempDir::new("deno-symbolicate-server-errors");
+    let symbolicator =
+      Symbolicator::new(SymbolStore::open(&root.0).unwrap(), 1);
+    let response =
+      symbolicator.respond("POST", "/symbolicate", None, &b"!!"[..]);
+    assert_eq!(response.status, 400);
+    assert_eq!(json(&response)["code"], "invalid_trace");
+
+    let response = symbolicator.respond("GET", "/symbolicate", None, &[][..]);
+    assert_eq!(response.status, 405);
+    let response = symbolicator.respond("GET", "/other", None, &[][..]);
+    assert_eq!(response.status, 404);
+    let response = symbolicator.respond("GET", "/health", None, &[][..]);
+    assert_eq!((response.status, &response.body[..]), (200, &b"ok"[..]));
+
+    let body = vec![b'A'; MAX_BODY_SIZE as usize + 1];
+    let response = symbolicator.respond("POST", "/", None, &body[..]);
+    assert_eq!(json(&response)["code"], "invalid_request");
+  }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Parser;
+use deno_symbolicate::symstore::SymbolStore;
+use deno_symbolicate_server::{Symbolicator, serve};
+
+#[derive(Parser)]
+#[command(name = "deno-symbolicate-server", version, about)]
+/// Serve symbolication of encoded stack traces over HTTP
+struct Cli {
+  /// The symbol store directory, as managed by `deno-symbolicate store`
+  #[arg(short, long)]
+  symbol_store: PathBuf,
+  #[arg(short, long, default_value = "127.0.0.1:8000")]
+  listen: String,
+  /// How many parsed symcaches to keep in memory
+  #[arg(long, default_value_t = 8)]
+  cache_size: usize,
+  /// How many requests to handle at the same time
+  #[arg(long, default_value_t = 4)]
+  threads: usize,
+}
+
+fn main() -> Result<(), anyhow::Error> {
+  let cli = Cli::parse();
+  let server = tiny_http::Server::http(&cli.listen).map_err(|error| {
+    anyhow::anyhow!("failed to listen on {}: {error}", cli.listen)
+  })?;
+  eprintln!("listening on http://{}", server.server_addr());
+  let store = SymbolStore::open(cli.symbol_store)?;
+  let symbolicator = Symbolicator::new(store, cli.cache_size);
+  serve(Arc::new(server), Arc::new(symbolicator), cli.threads);
+  Ok(())
+}
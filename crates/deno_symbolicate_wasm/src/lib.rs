@@ -1,13 +1,60 @@
-use deno_symbolicate::{OwnedSymCache, create_symcache, symbolicate_addrs};
+use deno_stable_stacktrace::BuildId;
+use deno_symbolicate::{
+  InstructionAddr, OwnedSymCache, SliceSelector, SymbolicateError,
+  check_build_id, create_symcache, create_symcache_with_executable,
+  create_symcaches, symbolicate_addrs, symcache_format,
+};
 use wasm_bindgen::prelude::*;
 
+/// Thrown by every fallible binding. `code` is the `SymbolicateError` code,
+/// `invalid_argument` if a value passed in from JS couldn't be converted, or
+/// `internal` if a result couldn't be converted to JS.
+#[wasm_bindgen(js_name = SymbolicateError, getter_with_clone)]
+pub struct JsSymbolicateError {
+  pub code: String,
+  pub message: String,
+}
+
+impl From<SymbolicateError> for JsSymbolicateError {
+  fn from(error: SymbolicateError) -> Self {
+    Self {
+      code: error.code().into(),
+      message: error.to_string(),
+    }
+  }
+}
+
+impl JsSymbolicateError {
+  fn invalid_argument(message: impl ToString) -> Self {
+    Self {
+      code: "invalid_argument".into(),
+      message: message.to_string(),
+    }
+  }
+
+  fn internal(message: impl ToString) -> Self {
+    Self {
+      code: "internal".into(),
+      message: message.to_string(),
+    }
+  }
+}
+
 #[wasm_bindgen]
 pub fn parse_symbol_cache(
   bytes: Vec<u8>,
-) -> Result<*mut OwnedSymCache, String> {
-  Ok(Box::into_raw(Box::new(
-    OwnedSymCache::parse(bytes).map_err(|e| e.to_string())?,
-  )))
+) -> Result<*mut OwnedSymCache, JsSymbolicateError> {
+  Ok(Box::into_raw(Box::new(OwnedSymCache::parse(bytes)?)))
+}
+
+/// Returns `{ version, supported, latest }` for an encoded symbol cache, so
+/// cached ones written by an older version can be replaced.
+#[wasm_bindgen]
+pub fn symbol_cache_format(
+  bytes: &[u8],
+) -> Result<JsValue, JsSymbolicateError> {
+  let format = symcache_format(bytes)?;
+  serde_wasm_bindgen::to_value(&format).map_err(JsSymbolicateError::internal)
 }
 
 #[wasm_bindgen]
@@ -18,20 +65,112 @@ pub struct SymbolCache {
 #[wasm_bindgen]
 impl SymbolCache {
   #[wasm_bindgen(constructor)]
-  pub fn new(bytes: Vec<u8>) -> Result<Self, String> {
-    let cache = OwnedSymCache::parse(bytes).map_err(|e| e.to_string())?;
+  pub fn new(bytes: Vec<u8>) -> Result<Self, JsSymbolicateError> {
+    let cache = OwnedSymCache::parse(bytes)?;
     Ok(Self { cache })
   }
 
+  /// Non-zero entries of `call_sites` mark return addresses, which are looked
+  /// up at the call instruction before them.
+  #[wasm_bindgen]
+  pub fn lookup_addrs(
+    &self,
+    addrs: Vec<u64>,
+    call_sites: Option<Vec<u8>>,
+  ) -> Result<JsValue, JsSymbolicateError> {
+    let call_sites = call_sites.unwrap_or_default();
+    let addrs = addrs
+      .into_iter()
+      .enumerate()
+      .map(|(i, addr)| InstructionAddr {
+        addr,
+        call_site: call_sites.get(i).is_some_and(|&flag| flag != 0),
+      })
+      .collect::<Vec<_>>();
+    let value = symbolicate_addrs(&addrs, self.cache.as_ref())?;
+    serde_wasm_bindgen::to_value(&value).map_err(JsSymbolicateError::internal)
+  }
+
+  #[wasm_bindgen]
+  pub fn debug_id(&self) -> String {
+    self.cache.as_ref().debug_id().to_string()
+  }
+
+  /// Errors if the symbol cache isn't for the binary with the given build ID
+  /// (as decoded from a stack trace header).
   #[wasm_bindgen]
-  pub fn lookup_addrs(&self, addrs: Vec<u64>) -> Result<JsValue, String> {
-    let value = symbolicate_addrs(&addrs, self.cache.as_ref())
-      .map_err(|e| e.to_string())?;
-    serde_wasm_bindgen::to_value(&value).map_err(|e| e.to_string())
+  pub fn check_build_id(
+    &self,
+    build_id: JsValue,
+  ) -> Result<(), JsSymbolicateError> {
+    let build_id: BuildId = serde_wasm_bindgen::from_value(build_id)
+      .map_err(JsSymbolicateError::invalid_argument)?;
+    check_build_id(&build_id, self.cache.as_ref())
+      .map_err(|e| SymbolicateError::from(e).into())
   }
 }
 
+fn slice_selector(
+  arch: Option<String>,
+) -> Result<SliceSelector, JsSymbolicateError> {
+  match arch {
+    Some(arch) => SliceSelector::for_trace_arch(&arch.as_str().into())
+      .ok_or_else(|| {
+        JsSymbolicateError::invalid_argument(format!(
+          "unknown architecture `{arch}`"
+        ))
+      }),
+    None => Ok(SliceSelector::Only),
+  }
+}
+
+/// Creates a symbol cache from a debug file. For fat (universal) debug files,
+/// `arch` picks the slice, as found in a stack trace header.
+#[wasm_bindgen]
+pub fn create_symbol_cache(
+  debug_file: Vec<u8>,
+  arch: Option<String>,
+) -> Result<Vec<u8>, JsSymbolicateError> {
+  Ok(create_symcache(&debug_file, slice_selector(arch)?)?)
+}
+
+/// Like `create_symbol_cache`, but also adds the exports of the executable
+/// the debug file is for, e.g. the PE image of a PDB, or the function names
+/// of a wasm module whose DWARF was split off.
+#[wasm_bindgen]
+pub fn create_symbol_cache_with_executable(
+  debug_file: Vec<u8>,
+  executable: Vec<u8>,
+  arch: Option<String>,
+) -> Result<Vec<u8>, JsSymbolicateError> {
+  Ok(create_symcache_with_executable(
+    &debug_file,
+    slice_selector(arch)?,
+    &executable,
+  )?)
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct SymbolCacheSlice {
+  pub arch: String,
+  pub debug_id: String,
+  pub symcache: Vec<u8>,
+}
+
+/// Creates a symbol cache for every slice of a (possibly fat) debug file.
 #[wasm_bindgen]
-pub fn create_symbol_cache(debug_file: Vec<u8>) -> Result<Vec<u8>, String> {
-  create_symcache(&debug_file).map_err(|e| e.to_string())
+pub fn create_symbol_caches(
+  debug_file: Vec<u8>,
+) -> Result<Vec<SymbolCacheSlice>, JsSymbolicateError> {
+  let slices = create_symcaches(&debug_file)?;
+  Ok(
+    slices
+      .into_iter()
+      .map(|slice| SymbolCacheSlice {
+        arch: slice.arch.to_string(),
+        debug_id: slice.debug_id.to_string(),
+        symcache: slice.symcache,
+      })
+      .collect(),
+  )
 }
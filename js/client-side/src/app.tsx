@@ -581,7 +581,14 @@ function SymbolicatedStackTrace({
                   : "bg-gray-50 text-gray-700"
               }`}
             >
-              Frame #{index} at 0x{frame.addr.toString(16)}
+              Frame #{index} {frame.kind === "unknown"
+                ? "at unknown address"
+                : `at 0x${frame.addr.toString(16)}`}
+              {frame.kind === "absolute" && " (outside of main executable)"}
+              {frame.kind === "inModule" &&
+                ` in ${trace.modules?.[frame.module]?.path ?? "unknown module"}`}
+              {frame.quality === "symbolOnly" && " (symbol only)"}
+              {frame.quality === "nearestSymbol" && " (nearest symbol)"}
             </div>
             <div
               className={`divide-y ${
@@ -596,6 +603,10 @@ function SymbolicatedStackTrace({
                     }`}
                   >
                     {location.demangledName}
+                    {location.offset !== undefined &&
+                      `+0x${location.offset.toString(16)}`}
+                    {location.inlined && " (inlined)"}
+                    {location.code === "wasm" && " (wasm)"}
                   </div>
                   <div className="flex items-center text-xs">
                     <svg
@@ -618,14 +629,19 @@ function SymbolicatedStackTrace({
                         isDarkMode ? "text-gray-400" : "text-gray-500"
                       }`}
                     >
-                      {location.fullPath}:
-                      <span
-                        className={`font-medium ${
-                          isDarkMode ? "text-gray-200" : "text-gray-900"
-                        }`}
-                      >
-                        {location.line}
-                      </span>
+                      {location.fullPath ?? "no debug info"}
+                      {location.line !== undefined && (
+                        <>
+                          :
+                          <span
+                            className={`font-medium ${
+                              isDarkMode ? "text-gray-200" : "text-gray-900"
+                            }`}
+                          >
+                            {location.line}
+                          </span>
+                        </>
+                      )}
                     </span>
                   </div>
                 </div>
@@ -1,5 +1,6 @@
 import {
   debugInfoToSymcache,
+  symcacheFormat,
   Symbolicator,
 } from "@nathanwhit/deno-symbolicate";
 import type {
@@ -36,6 +37,18 @@ async function fetchDebugInfo(header: Header) {
   return contents;
 }
 
+/**
+ * Symcaches cached by an older version may not be readable anymore, or lack
+ * information newer ones have.
+ */
+async function isLatestSymcache(blob: Blob): Promise<boolean> {
+  try {
+    return symcacheFormat(new Uint8Array(await blob.arrayBuffer())).latest;
+  } catch {
+    return false;
+  }
+}
+
 // Mock function to simulate processing - replace this with actual implementation
 export async function processStackTrace(
   encodedTrace: string,
@@ -49,6 +62,11 @@ export async function processStackTrace(
   console.log("getting symcache");
   let symcacheBlob = await symcacheStorage.getFile(key);
 
+  if (symcacheBlob && !(await isLatestSymcache(symcacheBlob))) {
+    console.log("symcache is in an older format, creating it again");
+    symcacheBlob = null;
+  }
+
   if (!symcacheBlob) {
     console.log("no symcache, fetching");
     let debugInfo: Uint8Array;
@@ -63,7 +81,7 @@ export async function processStackTrace(
       debugInfo = await fetchDebugInfo(stack.header);
     }
     console.log("generating symcache");
-    const sym = debugInfoToSymcache(debugInfo);
+    const sym = debugInfoToSymcache(debugInfo, stack.header.arch);
     console.log("symcache", sym);
     symcacheBlob = new Blob([sym]);
     console.log("writing to storage");
@@ -1,6 +1,18 @@
 import { decodeBase64Url } from "@std/encoding/base64url";
 import { decodeVarint, decodeVarint32 } from "@std/encoding/varint";
-import type { Header, StackTrace, Version } from "./stacktrace.ts";
+import type {
+  BuildId,
+  Frame,
+  Header,
+  Module,
+  Register,
+  SignalInfo,
+  StackMemory,
+  StackTrace,
+  ThreadInfo,
+  ThreadStack,
+  Version,
+} from "./stacktrace.ts";
 
 function decodeEnumString(
   buf: Uint8Array,
@@ -51,9 +63,44 @@ function decodeBool(buf: Uint8Array, i: number): [boolean, number] {
   return [value === 1, i];
 }
 
+function decodeBuildId(
+  buf: Uint8Array,
+  i: number,
+): [BuildId | undefined, number] {
+  const kind = buf[i++];
+  switch (kind) {
+    case 0:
+      return [undefined, i];
+    case 1: {
+      const len = buf[i++];
+      const value = Array.from(buf.subarray(i, i + len));
+      return [{ kind: "gnu", value }, i + len];
+    }
+    case 2: {
+      const value = Array.from(buf.subarray(i, i + 16));
+      return [{ kind: "machO", value }, i + 16];
+    }
+    case 3: {
+      const guid = Array.from(buf.subarray(i, i + 16));
+      let age: number;
+      [age, i] = decodeVarint32(buf, i + 16);
+      return [{ kind: "pdb", value: { guid, age } }, i];
+    }
+    default:
+      throw new Error(`Unknown build id kind ${kind}`);
+  }
+}
+
+/** The newest trace version this decoder understands. */
+const LATEST_TRACE_VERSION = 1;
+
 function decodeHeader(buf: Uint8Array, i: number): [Header, number] {
   const header: Partial<Header> = {};
   [header.traceVersion, i] = decodeVarint32(buf, i);
+  // Newer versions may change the layout of everything after the header.
+  if (header.traceVersion > LATEST_TRACE_VERSION) {
+    throw new Error(`Unknown trace version ${header.traceVersion}`);
+  }
   [header.os, i] = decodeEnumString(buf, i, {
     0: "linux",
     1: "macos",
@@ -67,14 +114,177 @@ function decodeHeader(buf: Uint8Array, i: number): [Header, number] {
   return [header as Header, i];
 }
 
-function decodeAddrArray(buf: Uint8Array, i: number): [BigUint64Array, number] {
-  const out = [];
+/** Set in the tag of return address frames. */
+const FRAME_TAG_CALL_SITE = 0x80;
+
+function decodeFrame(buf: Uint8Array, i: number): [Frame, number] {
+  const tag = buf[i++];
+  const kind = tag & ~FRAME_TAG_CALL_SITE;
+  const callSite = (tag & FRAME_TAG_CALL_SITE) !== 0;
+  const flags = callSite ? { callSite } : {};
+  let addr: bigint;
+  switch (kind) {
+    case 0:
+      if (callSite) break;
+      return [{ kind: "unknown" }, i];
+    case 1:
+      [addr, i] = decodeVarint(buf, i);
+      return [{ kind: "stable", addr, ...flags }, i];
+    case 2:
+      [addr, i] = decodeVarint(buf, i);
+      return [{ kind: "absolute", addr, ...flags }, i];
+    case 3: {
+      let module: number;
+      [module, i] = decodeVarint32(buf, i);
+      [addr, i] = decodeVarint(buf, i);
+      return [{ kind: "inModule", module, addr, ...flags }, i];
+    }
+  }
+  throw new Error(`Unknown frame kind ${tag}`);
+}
+
+/** Decodes the stable addresses of a version 0 trace. */
+function decodeLegacyFrames(buf: Uint8Array, i: number): [Frame[], number] {
+  const out: Frame[] = [];
+  while (i < buf.byteLength) {
+    let addr: bigint;
+    [addr, i] = decodeVarint(buf, i);
+    out.push({ kind: "stable", addr });
+  }
+  return [out, i];
+}
+
+function decodeString(buf: Uint8Array, i: number): [string, number] {
+  let len: number;
+  [len, i] = decodeVarint32(buf, i);
+  const bytes = buf.subarray(i, i + len);
+  return [new TextDecoder().decode(bytes), i + len];
+}
+
+function decodeModule(buf: Uint8Array, i: number): [Module, number] {
+  let path: string, base: bigint, buildId: BuildId | undefined;
+  [path, i] = decodeString(buf, i);
+  [base, i] = decodeVarint(buf, i);
+  [buildId, i] = decodeBuildId(buf, i);
+  return [{ path, base, ...(buildId ? { buildId } : {}) }, i];
+}
+
+function decodeThread(buf: Uint8Array, i: number): [ThreadInfo, number] {
+  let id: bigint, name: string;
+  [id, i] = decodeVarint(buf, i);
+  [name, i] = decodeString(buf, i);
+  return [{ id, ...(name ? { name } : {}) }, i];
+}
+
+function decodeSignal(buf: Uint8Array, i: number): [SignalInfo, number] {
+  let signal: number, code: number, faultAddr: bigint;
+  [signal, i] = decodeVarint32(buf, i);
+  [code, i] = decodeVarint32(buf, i);
+  [faultAddr, i] = decodeVarint(buf, i);
+  return [{
+    signal,
+    // Zigzag encoded.
+    code: (code >>> 1) ^ -(code & 1),
+    ...(faultAddr ? { faultAddr: faultAddr - 1n } : {}),
+  }, i];
+}
+
+function decodeThreadStack(
+  buf: Uint8Array,
+  i: number,
+): [ThreadStack, number] {
+  let thread: ThreadInfo, frameCount: number;
+  [thread, i] = decodeThread(buf, i);
+  [frameCount, i] = decodeVarint32(buf, i);
+  const frames: Frame[] = [];
+  while (frameCount-- > 0) {
+    let frame: Frame;
+    [frame, i] = decodeFrame(buf, i);
+    frames.push(frame);
+  }
+  return [{ thread, frames }, i];
+}
+
+function decodeStackMemory(
+  buf: Uint8Array,
+  i: number,
+): [StackMemory, number] {
+  let ip: bigint, registerCount: number, start: bigint, len: number;
+  [ip, i] = decodeVarint(buf, i);
+  [registerCount, i] = decodeVarint32(buf, i);
+  const registers: Register[] = [];
+  while (registerCount-- > 0) {
+    let number: number, value: bigint;
+    [number, i] = decodeVarint32(buf, i);
+    [value, i] = decodeVarint(buf, i);
+    registers.push({ number, value });
+  }
+  [start, i] = decodeVarint(buf, i);
+  [len, i] = decodeVarint32(buf, i);
+  const memory = buf.slice(i, i + len);
+  return [{ ip, registers, start, memory }, i + len];
+}
+
+/**
+ * Decodes the sections of a version 1 trace into `stackTrace`, skipping
+ * unknown ones.
+ */
+function decodeSections(
+  buf: Uint8Array,
+  i: number,
+  stackTrace: Partial<StackTrace>,
+): number {
   while (i < buf.byteLength) {
-    let decoded: bigint;
-    [decoded, i] = decodeVarint(buf, i);
-    out.push(decoded);
+    let tag: number, len: number;
+    [tag, i] = decodeVarint32(buf, i);
+    [len, i] = decodeVarint32(buf, i);
+    if (i + len > buf.byteLength) {
+      throw new Error("Section extends past the end of the stack trace");
+    }
+    const payload = buf.subarray(i, i + len);
+    switch (tag) {
+      case 1: {
+        const [buildId] = decodeBuildId(payload, 0);
+        if (buildId) {
+          stackTrace.header!.buildId = buildId;
+        }
+        break;
+      }
+      case 2: {
+        let [count, j] = decodeVarint32(payload, 0);
+        const modules: Module[] = [];
+        while (count-- > 0) {
+          let module: Module;
+          [module, j] = decodeModule(payload, j);
+          modules.push(module);
+        }
+        stackTrace.modules = modules;
+        break;
+      }
+      case 3:
+        [stackTrace.thread] = decodeThread(payload, 0);
+        break;
+      case 4:
+        [stackTrace.signal] = decodeSignal(payload, 0);
+        break;
+      case 5: {
+        let [count, j] = decodeVarint32(payload, 0);
+        const threads: ThreadStack[] = [];
+        while (count-- > 0) {
+          let thread: ThreadStack;
+          [thread, j] = decodeThreadStack(payload, j);
+          threads.push(thread);
+        }
+        stackTrace.threads = threads;
+        break;
+      }
+      case 6:
+        [stackTrace.stackMemory] = decodeStackMemory(payload, 0);
+        break;
+    }
+    i += len;
   }
-  return [new BigUint64Array(out), i];
+  return i;
 }
 
 function decodeStackTrace(
@@ -83,7 +293,20 @@ function decodeStackTrace(
 ): [StackTrace, number] {
   const stackTrace: Partial<StackTrace> = {};
   [stackTrace.header, i] = decodeHeader(buf, i);
-  [stackTrace.addrs, i] = decodeAddrArray(buf, i);
+  if (stackTrace.header.traceVersion === 0) {
+    [stackTrace.frames, i] = decodeLegacyFrames(buf, i);
+    return [stackTrace as StackTrace, i];
+  }
+
+  let frameCount: number;
+  [frameCount, i] = decodeVarint32(buf, i);
+  stackTrace.frames = [];
+  while (frameCount-- > 0) {
+    let frame: Frame;
+    [frame, i] = decodeFrame(buf, i);
+    stackTrace.frames.push(frame);
+  }
+  i = decodeSections(buf, i, stackTrace);
   return [stackTrace as StackTrace, i];
 }
 
@@ -17,14 +17,19 @@ export function resolveDsym(path: string): string | undefined {
   }
 }
 
+/**
+ * `arch` selects the slice of a fat (universal) debug file, as found in a
+ * stack trace header.
+ */
 export function writeSymbolCacheFromDebugFile(
   debugFilePath: string,
   outPath?: string,
+  arch?: string,
 ) {
   const contents = Deno.readFileSync(
     resolveDsym(debugFilePath) ?? debugFilePath,
   );
-  const out = create_symbol_cache(contents);
+  const out = create_symbol_cache(contents, arch);
   outPath = outPath ?? debugFilePath + ".symcache";
   Deno.writeFileSync(outPath, out);
 }
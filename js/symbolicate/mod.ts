@@ -1,7 +1,15 @@
-import { create_symbol_cache, SymbolCache } from "symbolicate_wasm";
+import {
+  create_symbol_cache,
+  create_symbol_cache_with_executable,
+  create_symbol_caches,
+  symbol_cache_format,
+  SymbolCache,
+} from "symbolicate_wasm";
 
 import type {
+  Frame,
   FrameLocation,
+  FrameQuality,
   StackTrace,
   SymbolicatedFrame,
   SymbolicatedStackTrace,
@@ -9,6 +17,12 @@ import type {
 
 export * from "./decode.ts";
 
+/**
+ * Thrown by the wasm bindings. `code` identifies the kind of error, e.g.
+ * `invalid_debug_file`, `fat_archive` or `debug_id_mismatch`.
+ */
+export { SymbolicateError } from "symbolicate_wasm";
+
 export type * from "./stacktrace.ts";
 
 export type EncodedSymCache = Uint8Array;
@@ -20,26 +34,163 @@ export class Symbolicator {
   }
 
   symbolicateAddrs(addrs: BigUint64Array): SymbolicatedFrame[] {
-    const result: FrameLocation[][] = this.symcache.lookup_addrs(addrs);
-    const out: SymbolicatedFrame[] = [];
-    for (let i = 0; i < result.length; i++) {
-      out.push({
-        addr: addrs[i],
-        locations: result[i],
-      });
+    return this.symbolicateFrames(
+      Array.from(addrs, (addr) => ({ kind: "stable", addr })),
+    );
+  }
+
+  /**
+   * Stable frames are looked up in this symbol cache, and `inModule` frames
+   * with the symbolicator in `modules` for their module's index, if any.
+   * Other frames get no locations.
+   */
+  symbolicateFrames(
+    frames: Frame[],
+    modules?: Map<number, Symbolicator>,
+  ): SymbolicatedFrame[] {
+    const locations: FrameLocation[][] = frames.map(() => []);
+    // The indices of the frames to look up in each symbol cache.
+    const lookups = new Map<SymbolCache, number[]>();
+    const lookedUp = new Set<number>();
+    frames.forEach((frame, i) => {
+      const symcache = frame.kind === "stable"
+        ? this.symcache
+        : frame.kind === "inModule"
+        ? modules?.get(frame.module)?.symcache
+        : undefined;
+      if (symcache) {
+        const indices = lookups.get(symcache) ?? [];
+        indices.push(i);
+        lookups.set(symcache, indices);
+        lookedUp.add(i);
+      }
+    });
+    for (const [symcache, indices] of lookups) {
+      const lookupFrames = indices.map((i) =>
+        frames[i] as Extract<Frame, { addr: bigint }>
+      );
+      const addrs = new BigUint64Array(lookupFrames.map((frame) => frame.addr));
+      const callSites = new Uint8Array(
+        lookupFrames.map((frame) => frame.callSite ? 1 : 0),
+      );
+      const result: FrameLocation[][] = symcache.lookup_addrs(
+        addrs,
+        callSites,
+      );
+      indices.forEach((i, j) => locations[i] = result[j]);
     }
-    return out;
+    return frames.map((frame, i) => ({
+      ...frame,
+      locations: locations[i],
+      quality: lookedUp.has(i) ? frameQuality(locations[i]) : "skipped",
+    }));
   }
 
-  symbolicate(stackTrace: StackTrace): SymbolicatedStackTrace {
-    const frames = this.symbolicateAddrs(stackTrace.addrs);
+  /**
+   * Throws if the symbol cache doesn't match the binary the stack trace was
+   * captured from. Only traces with a build ID (version 1 and up) are checked.
+   *
+   * `modules` symbolicates the frames in the trace's other modules (e.g.
+   * shared libraries), by module index. Each is checked against its
+   * module's build ID, if the trace recorded one.
+   */
+  symbolicate(
+    stackTrace: StackTrace,
+    modules?: Map<number, Symbolicator>,
+  ): SymbolicatedStackTrace {
+    if (stackTrace.header.buildId) {
+      this.symcache.check_build_id(stackTrace.header.buildId);
+    }
+    for (const [index, symbolicator] of modules ?? []) {
+      const buildId = stackTrace.modules?.[index]?.buildId;
+      if (buildId) {
+        symbolicator.symcache.check_build_id(buildId);
+      }
+    }
+    const frames = this.symbolicateFrames(stackTrace.frames, modules);
+    const threads = stackTrace.threads?.map(({ thread, frames }) => ({
+      thread,
+      frames: this.symbolicateFrames(frames, modules),
+    }));
     return {
+      ...stackTrace,
       frames,
-      header: stackTrace.header,
+      ...(threads ? { threads } : {}),
     };
   }
 }
 
-export function debugInfoToSymcache(debugInfo: Uint8Array): EncodedSymCache {
-  return create_symbol_cache(debugInfo);
+/** The quality of a looked up frame with the inline chain `locations`. */
+export function frameQuality(locations: FrameLocation[]): FrameQuality {
+  if (locations.length === 0) {
+    return "unresolved";
+  }
+  const [innermost] = locations;
+  if (innermost.symbolTable) {
+    return "nearestSymbol";
+  }
+  if (innermost.line === undefined) {
+    return "symbolOnly";
+  }
+  return locations.length > 1 ? "inlined" : "debugInfo";
+}
+
+/**
+ * For fat (universal) debug files, `arch` selects the slice to use. Pass the
+ * `arch` from the stack trace header.
+ *
+ * `executable` is the binary the debug info is for, e.g. the `.exe` or `.dll`
+ * of a PDB, or the wasm module whose DWARF was split off. Its exports (or the
+ * names of a wasm module's name section) cover the functions without debug
+ * info. It has to be from the same build, or this throws an
+ * `unrelated_executable` error.
+ */
+export function debugInfoToSymcache(
+  debugInfo: Uint8Array,
+  arch?: string,
+  executable?: Uint8Array,
+): EncodedSymCache {
+  if (executable) {
+    return create_symbol_cache_with_executable(debugInfo, executable, arch);
+  }
+  return create_symbol_cache(debugInfo, arch);
+}
+
+export interface SymcacheFormat {
+  version: number;
+  /**
+   * Whether the symbol cache can still be read. Unsupported ones have to be
+   * created again from their debug file.
+   */
+  supported: boolean;
+  /** Whether it is in the format `debugInfoToSymcache` writes. */
+  latest: boolean;
+}
+
+/**
+ * Reads the format version of a symbol cache, e.g. one cached by an older
+ * version of this package. Throws if it isn't a symbol cache.
+ */
+export function symcacheFormat(symcache: EncodedSymCache): SymcacheFormat {
+  return symbol_cache_format(symcache);
+}
+
+export interface SymcacheSlice {
+  /** The architecture, as named by `symbolic` (e.g. `x86_64` or `arm64`). */
+  arch: string;
+  debugId: string;
+  symcache: EncodedSymCache;
+}
+
+/** Creates a symbol cache for every slice of a (possibly fat) debug file. */
+export function debugInfoToSymcaches(debugInfo: Uint8Array): SymcacheSlice[] {
+  return create_symbol_caches(debugInfo).map((slice) => {
+    const out = {
+      arch: slice.arch,
+      debugId: slice.debug_id,
+      symcache: slice.symcache,
+    };
+    slice.free();
+    return out;
+  });
 }
@@ -1,21 +1,69 @@
-export interface SymbolicatedFrame {
-  addr: bigint;
+export type SymbolicatedFrame = Frame & {
   locations: FrameLocation[];
-}
+  quality: FrameQuality;
+};
+
+/**
+ * How much of a frame could be symbolicated, from best to worst:
+ *
+ * - `debugInfo`: the function, file and line.
+ * - `inlined`: the same, in a function inlined into others.
+ * - `symbolOnly`: only the function, from debug info without a line.
+ * - `nearestSymbol`: only the closest entry of the symbol table (e.g. of a
+ *   stripped binary) before the address, which may not contain it.
+ * - `unresolved`: the address was looked up, but nothing is known about it.
+ * - `skipped`: the frame wasn't looked up, since there's no symbol cache for
+ *   its module, or its address is unknown.
+ */
+export type FrameQuality =
+  | "debugInfo"
+  | "inlined"
+  | "symbolOnly"
+  | "nearestSymbol"
+  | "unresolved"
+  | "skipped";
 
 export interface SymbolicatedStackTrace {
   header: Header;
   frames: SymbolicatedFrame[];
+  modules?: Module[];
+  thread?: ThreadInfo;
+  signal?: SignalInfo;
+  threads?: SymbolicatedThread[];
+}
+
+export interface SymbolicatedThread {
+  thread: ThreadInfo;
+  frames: SymbolicatedFrame[];
 }
 
+/** One entry of the inline chain for an address, innermost first. */
 export interface FrameLocation {
   demangledName: string;
   name: string;
   language: string;
-  fullPath: string;
-  line: number;
+  /**
+   * `wasm` for WebAssembly modules, whose addresses are byte offsets into the
+   * module. Absent for native code.
+   */
+  code?: CodeKind;
+  /** Inlined into the next entry. The last entry is never inlined. */
+  inlined: boolean;
+  /** Address of the function's first instruction. */
+  functionStart?: number;
+  /** Offset of the address into the function, as in `fn+0x1a`. */
+  offset?: number;
+  fullPath?: string;
+  line?: number;
+  /**
+   * The closest symbol table entry before the address, with no language, file
+   * or line, rather than debug info.
+   */
+  symbolTable?: boolean;
 }
 
+export type CodeKind = "native" | "wasm";
+
 export type Os = string;
 export type Arch = string;
 
@@ -26,14 +74,101 @@ export interface Version {
   canaryHash?: string;
   devBuild: boolean;
 }
+/**
+ * Identifies the exact binary a trace was captured from. Byte arrays are
+ * plain arrays of numbers.
+ */
+export type BuildId =
+  | { kind: "gnu"; value: number[] }
+  | { kind: "machO"; value: number[] }
+  | { kind: "pdb"; value: { guid: number[]; age: number } };
+
 export interface Header {
   traceVersion: number;
   os: Os;
   version: Version;
   arch: Arch;
+  buildId?: BuildId;
+}
+
+/** A module (executable or shared library) loaded into the process. */
+export interface Module {
+  path: string;
+  base: bigint;
+  buildId?: BuildId;
+}
+
+/** The thread a stack trace was captured on. */
+export interface ThreadInfo {
+  id: bigint;
+  name?: string;
+}
+
+/**
+ * The stack of another thread than the one a stack trace was captured on,
+ * e.g. when dumping every thread of a hung process. Its frames share the
+ * trace's module table.
+ */
+export interface ThreadStack {
+  thread: ThreadInfo;
+  frames: Frame[];
+}
+
+/** The signal that caused a stack trace to be captured. */
+export interface SignalInfo {
+  signal: number;
+  code: number;
+  faultAddr?: bigint;
 }
 
+/**
+ * A register captured in `StackMemory`, by its DWARF number for the trace's
+ * architecture.
+ */
+export interface Register {
+  number: number;
+  value: bigint;
+}
+
+/**
+ * The registers of the capturing frame and a slice of the stack above its
+ * stack pointer, which the symbolicator can unwind with the executable's
+ * unwind info.
+ */
+export interface StackMemory {
+  /** The absolute instruction pointer of the frame the registers are from. */
+  ip: bigint;
+  registers: Register[];
+  /** The address `memory` was copied from. */
+  start: bigint;
+  memory: Uint8Array;
+}
+
+/**
+ * A frame of a stack trace. Version 0 traces only contain stable frames.
+ *
+ * `callSite` marks return addresses, which are looked up at `addr - 1` to
+ * find the call instruction. It's never set for version 0 traces, whose
+ * addresses were adjusted when captured.
+ */
+export type Frame =
+  /** An address relative to the main executable. */
+  | { kind: "stable"; addr: bigint; callSite?: boolean }
+  /** The absolute address of a frame outside of the main executable. */
+  | { kind: "absolute"; addr: bigint; callSite?: boolean }
+  /** An address relative to `modules[module]` of the stack trace. */
+  | { kind: "inModule"; module: number; addr: bigint; callSite?: boolean }
+  /** A frame whose address couldn't be determined. */
+  | { kind: "unknown" };
+
 export interface StackTrace {
   header: Header;
-  addrs: BigUint64Array;
+  frames: Frame[];
+  /** The module table. The main executable is always first. */
+  modules?: Module[];
+  thread?: ThreadInfo;
+  signal?: SignalInfo;
+  /** The stacks of other threads. */
+  threads?: ThreadStack[];
+  stackMemory?: StackMemory;
 }
@@ -1,8 +1,8 @@
-import { assertEquals } from "@std/assert";
+import { assertEquals, assertThrows } from "@std/assert";
 import { encode_stack_trace } from "stacktrace_wasm";
 import { decodeStackTraceString } from "../decode.ts";
-import type { Header, StackTrace, Version } from "../stacktrace.ts";
-import { decodeBase64Url } from "@std/encoding/base64url";
+import type { Frame, Header, StackTrace, Version } from "../stacktrace.ts";
+import { decodeBase64Url, encodeBase64Url } from "@std/encoding/base64url";
 
 /**
  * Make a type assembled from several types/utilities more readable.
@@ -21,11 +21,8 @@ function encodeStackTrace(trace: StackTrace): string {
   return encode_stack_trace(trace);
 }
 
-function addrs(v: number[] | bigint[] | BigUint64Array) {
-  if (v instanceof BigUint64Array) {
-    return v;
-  }
-  return new BigUint64Array(v.map((v) => BigInt(v)));
+function stableFrames(v: number[] | bigint[]): Frame[] {
+  return v.map((addr) => ({ kind: "stable", addr: BigInt(addr) }));
 }
 
 function num(v: string): [number, string] {
@@ -96,8 +93,9 @@ type PartialStackTrace = Override<
 
 function stackTrace(v: PartialStackTrace): StackTrace {
   return {
+    ...v,
     header: header(v.header ?? {}),
-    addrs: addrs(v.addrs ?? [1, 2, 3]),
+    frames: v.frames ?? stableFrames([1, 2, 3]),
   };
 }
 
@@ -126,7 +124,7 @@ testRoundTrip.debug = (input: Parameters<typeof testRoundTrip>[0]) => {
 
 Deno.test("decode basic", () => {
   testRoundTrip({
-    addrs: addrs([1, 2, 3]),
+    frames: stableFrames([1, 2, 3]),
     header: {
       traceVersion: 1,
       os: "linux",
@@ -136,6 +134,10 @@ Deno.test("decode basic", () => {
   });
 });
 
+Deno.test("decode version 0", () => {
+  testRoundTrip({ header: { traceVersion: 0 } });
+});
+
 Deno.test("decode with canary hash", () => {
   const result = testRoundTrip({
     header: {
@@ -170,6 +172,112 @@ Deno.test("decode with dev build and canary hash", () => {
   );
 });
 
+Deno.test("decode with build id", () => {
+  const guid = Array.from({ length: 16 }, (_, i) => i);
+  testRoundTrip({
+    header: { buildId: { kind: "gnu", value: [1, 2, 3, 4, 5] } },
+  });
+  testRoundTrip({ header: { buildId: { kind: "machO", value: guid } } });
+  testRoundTrip({
+    header: { buildId: { kind: "pdb", value: { guid, age: 300 } } },
+  });
+});
+
+Deno.test("decode unknown and absolute frames", () => {
+  testRoundTrip({
+    frames: [
+      { kind: "stable", addr: 1n },
+      { kind: "unknown" },
+      { kind: "absolute", addr: 0x7fff12345678n },
+    ],
+  });
+});
+
+Deno.test("decode sections", () => {
+  testRoundTrip({
+    header: { buildId: { kind: "gnu", value: [1, 2, 3] } },
+    frames: [
+      { kind: "stable", addr: 1n },
+      { kind: "inModule", module: 1, addr: 0x1234n },
+      { kind: "unknown" },
+    ],
+    modules: [
+      {
+        path: "/usr/bin/deno",
+        base: 0x1000n,
+        buildId: { kind: "gnu", value: [1, 2, 3] },
+      },
+      { path: "/usr/lib/libc.so.6", base: 0x7f0000000000n },
+    ],
+    thread: { id: 1234n, name: "main" },
+    signal: { signal: 11, code: -6, faultAddr: 0n },
+  });
+});
+
+Deno.test("decode call sites", () => {
+  testRoundTrip({
+    frames: [
+      { kind: "stable", addr: 0x10n },
+      { kind: "stable", addr: 0x20n, callSite: true },
+      { kind: "inModule", module: 0, addr: 0x30n, callSite: true },
+      { kind: "absolute", addr: 0x7fff12345678n, callSite: true },
+    ],
+  });
+});
+
+Deno.test("decode threads", () => {
+  testRoundTrip({
+    frames: [{ kind: "stable", addr: 0x10n }],
+    thread: { id: 1n, name: "main" },
+    threads: [
+      {
+        thread: { id: 2n, name: "worker" },
+        frames: [
+          { kind: "stable", addr: 0x20n },
+          { kind: "absolute", addr: 0x7fff12345678n, callSite: true },
+        ],
+      },
+      { thread: { id: 3n }, frames: [] },
+    ],
+  });
+});
+
+Deno.test("decode stack memory", () => {
+  testRoundTrip({
+    frames: [{ kind: "stable", addr: 0x10n }],
+    stackMemory: {
+      ip: 0x7fff12345678n,
+      registers: [
+        { number: 6, value: 0x7ffe00001010n },
+        { number: 7, value: 0x7ffe00001000n },
+      ],
+      start: 0x7ffe00001000n,
+      memory: new Uint8Array([1, 2, 3, 4, 5, 6, 7, 8]),
+    },
+  });
+});
+
+Deno.test("decode skips unknown sections", () => {
+  const buf = new Uint8Array([
+    1, 0, 0, 1, 0, 0, 0, 0, // header
+    1, 1, 5, // one stable frame
+    99, 2, 0xff, 0xff, // unknown section
+    3, 2, 7, 0, // thread section
+  ]);
+  const decoded = decodeStackTraceString(encodeBase64Url(buf));
+  assertEquals(decoded.frames, stableFrames([5]));
+  assertEquals(decoded.thread, { id: 7n });
+});
+
+Deno.test("decode rejects unknown trace versions", () => {
+  const buf = new Uint8Array([2, 0, 0, 1, 0, 0, 0, 0, 1, 1, 5]);
+  assertThrows(
+    () => decodeStackTraceString(encodeBase64Url(buf)),
+    Error,
+    "Unknown trace version 2",
+  );
+});
+
 Deno.test("decode other values", () => {
   testRoundTrip({
     header: {
@@ -3,6 +3,7 @@ import {
   decodeStackTraceString,
   Header,
   SymbolicatedStackTrace,
+  SymbolicateError,
   Symbolicator,
 } from "@nathanwhit/deno-symbolicate";
 import { writeSymbolCacheFromDebugFile } from "@nathanwhit/deno-symbolicate/deno";
@@ -11,11 +12,31 @@ import { writeSymbolCacheFromDebugFile } from "@nathanwhit/deno-symbolicate/deno
 function toJSON(stack: SymbolicatedStackTrace): Record<string, any> {
   // deno-lint-ignore no-explicit-any
   const out: Record<string, any> = {};
+  const hex = (addr: bigint) => "0x" + addr.toString(16);
   out.header = stack.header;
+  if (stack.modules) {
+    out.modules = stack.modules.map((module) => ({
+      ...module,
+      base: hex(module.base),
+    }));
+  }
+  if (stack.thread) {
+    out.thread = { ...stack.thread, id: stack.thread.id.toString() };
+  }
+  if (stack.signal) {
+    const { faultAddr } = stack.signal;
+    out.signal = {
+      ...stack.signal,
+      ...(faultAddr !== undefined ? { faultAddr: hex(faultAddr) } : {}),
+    };
+  }
   out.frames = stack.frames.map((frame) => {
+    if (frame.kind === "unknown") {
+      return frame;
+    }
     return {
       ...frame,
-      addr: "0x" + frame.addr.toString(16),
+      addr: hex(frame.addr),
     };
   });
   return out;
@@ -42,6 +63,7 @@ function getSymbolicator(
       writeSymbolCacheFromDebugFile(
         debugInfo,
         symcache,
+        header.arch,
       );
     }
     const contents = Deno.readFileSync(symcache);
@@ -55,13 +77,36 @@ function getDebugInfo(_header: Header): string {
   throw new Error("todo");
 }
 
+/** Maps `SymbolicateError` codes to HTTP status codes. */
+function errorStatus(code: string): number {
+  switch (code) {
+    case "debug_id_mismatch":
+    case "invalid_argument":
+      return 400;
+    case "no_matching_slice":
+      return 404;
+    default:
+      return 500;
+  }
+}
+
 Deno.serve(async (request) => {
   const encodedStackTrace = await request.text();
   const decoded = decodeStackTraceString(encodedStackTrace);
   const debugInfo = getDebugInfo(decoded.header);
   const symcache = "./debug-info.symcache";
-  const symbolicator = getSymbolicator(decoded.header, debugInfo, symcache);
-  const symbolicated = symbolicator.symbolicate(decoded);
-  const response = Response.json(toJSON(symbolicated));
-  return response;
+  try {
+    const symbolicator = getSymbolicator(decoded.header, debugInfo, symcache);
+    const symbolicated = symbolicator.symbolicate(decoded);
+    const response = Response.json(toJSON(symbolicated));
+    return response;
+  } catch (e) {
+    if (e instanceof SymbolicateError) {
+      return Response.json(
+        { code: e.code, message: e.message },
+        { status: errorStatus(e.code) },
+      );
+    }
+    throw e;
+  }
 });